@@ -0,0 +1,243 @@
+//! Removal of capabilities from the bounding set, and retaining capabilities
+//! through a change of user.
+//!
+//! See capabilities(7) for details of each capability.
+
+use std::fs::read_to_string;
+
+use libc::{c_int, c_ulong, pid_t, prctl, syscall, SYS_capget, SYS_capset};
+use libc::{PR_CAPBSET_DROP, PR_CAPBSET_READ, PR_CAP_AMBIENT, PR_CAP_AMBIENT_RAISE, PR_SET_KEEPCAPS};
+use nix::errno::Errno;
+
+use error::*;
+
+/// The names of the capabilities, indexed by their number.
+const CAPABILITIES: &[&str] = &[
+    "CAP_CHOWN",
+    "CAP_DAC_OVERRIDE",
+    "CAP_DAC_READ_SEARCH",
+    "CAP_FOWNER",
+    "CAP_FSETID",
+    "CAP_KILL",
+    "CAP_SETGID",
+    "CAP_SETUID",
+    "CAP_SETPCAP",
+    "CAP_LINUX_IMMUTABLE",
+    "CAP_NET_BIND_SERVICE",
+    "CAP_NET_BROADCAST",
+    "CAP_NET_ADMIN",
+    "CAP_NET_RAW",
+    "CAP_IPC_LOCK",
+    "CAP_IPC_OWNER",
+    "CAP_SYS_MODULE",
+    "CAP_SYS_RAWIO",
+    "CAP_SYS_CHROOT",
+    "CAP_SYS_PTRACE",
+    "CAP_SYS_PACCT",
+    "CAP_SYS_ADMIN",
+    "CAP_SYS_BOOT",
+    "CAP_SYS_NICE",
+    "CAP_SYS_RESOURCE",
+    "CAP_SYS_TIME",
+    "CAP_SYS_TTY_CONFIG",
+    "CAP_MKNOD",
+    "CAP_LEASE",
+    "CAP_AUDIT_WRITE",
+    "CAP_AUDIT_CONTROL",
+    "CAP_SETFCAP",
+    "CAP_MAC_OVERRIDE",
+    "CAP_MAC_ADMIN",
+    "CAP_SYSLOG",
+    "CAP_WAKE_ALARM",
+    "CAP_BLOCK_SUSPEND",
+    "CAP_AUDIT_READ",
+    "CAP_PERFMON",
+    "CAP_BPF",
+    "CAP_CHECKPOINT_RESTORE",
+];
+
+/// The value of `drop` that removes every capability not kept.
+const ALL: &str = "all";
+
+/// The version of the capability sets that holds 64 capabilities.
+const CAPABILITY_VERSION_3: u32 = 0x2008_0522;
+
+/// The process to get or set the capabilities of, as
+/// `struct __user_cap_header_struct`.
+#[repr(C)]
+struct CapHeader {
+    version: u32,
+    pid: pid_t,
+}
+
+/// Half of each capability set, as `struct __user_cap_data_struct`.
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct CapData {
+    effective: u32,
+    permitted: u32,
+    inheritable: u32,
+}
+
+/// Capabilities to remove from the bounding set of the container.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Capabilities {
+    /// Capabilities to drop, or `all`.
+    #[serde(default)]
+    drop: Vec<String>,
+    /// Capabilities to keep when dropping `all`.
+    #[serde(default)]
+    keep: Vec<String>,
+    /// Capabilities to retain when changing to another user.
+    #[serde(default)]
+    keep_caps: Vec<String>,
+}
+
+impl Capabilities {
+    /// Merge the capabilities of one configuration over another, adding to
+    /// each list.
+    pub fn merge(mut base: Capabilities, overlay: Capabilities) -> Capabilities {
+        base.drop.extend(overlay.drop);
+        base.keep.extend(overlay.keep);
+        base.keep_caps.extend(overlay.keep_caps);
+        base
+    }
+
+    /// Remove the capabilities from the bounding set.
+    ///
+    /// Capabilities already held are unaffected, but can never be regained
+    /// after a change of user or an exec.
+    pub fn drop_bounding(&self) -> Failure {
+        for capability in self.dropped()? {
+            let result = unsafe {
+                prctl(PR_CAPBSET_DROP, capability as c_ulong, 0, 0, 0)
+            };
+            Errno::result(result)
+                .map_err(Error::from)
+                .chain_err(|| ErrorKind::DropCapability(name(capability)))?;
+        }
+
+        ok!()
+    }
+
+    /// Keep the permitted capabilities through the next change of user, if
+    /// any are to be retained.
+    pub fn keep_through_setuid(&self) -> Failure {
+        if self.keep_caps.is_empty() {
+            return ok!();
+        }
+        let result = unsafe { prctl(PR_SET_KEEPCAPS, 1, 0, 0, 0) };
+        Errno::result(result).map(drop).map_err(Error::from)
+    }
+
+    /// Once the user has changed, limit the capabilities to those retained and
+    /// raise them into the effective and ambient sets, so that they are also
+    /// held by the command.
+    pub fn raise_retained(&self) -> Failure {
+        if self.keep_caps.is_empty() {
+            return ok!();
+        }
+        let retained = self.keep_caps.iter()
+            .map(|name| number(name))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut header = CapHeader { version: CAPABILITY_VERSION_3, pid: 0 };
+        let mut data = [CapData::default(); 2];
+        let result = unsafe { syscall(SYS_capget, &mut header as *mut CapHeader, data.as_mut_ptr()) };
+        Errno::result(result)?;
+
+        // Each must still be permitted, and in the bounding set to be made
+        // inheritable.
+        let mut sets = [0u32; 2];
+        for &capability in &retained {
+            let (index, bit) = (capability as usize / 32, 1 << (capability % 32));
+            let bounded = unsafe { prctl(PR_CAPBSET_READ, capability as c_ulong, 0, 0, 0) };
+            ensure!(
+                data[index].permitted & bit != 0 && bounded == 1,
+                ErrorKind::RetainCapability(name(capability))
+            );
+            sets[index] |= bit;
+        }
+
+        // The ambient set can only hold capabilities that are both permitted
+        // and inheritable.
+        for (data, &set) in data.iter_mut().zip(&sets) {
+            *data = CapData { effective: set, permitted: set, inheritable: set };
+        }
+        let result = unsafe { syscall(SYS_capset, &header as *const CapHeader, data.as_ptr()) };
+        Errno::result(result)?;
+
+        for &capability in &retained {
+            let result = unsafe {
+                prctl(PR_CAP_AMBIENT, PR_CAP_AMBIENT_RAISE, capability as c_ulong, 0, 0)
+            };
+            Errno::result(result)
+                .map_err(Error::from)
+                .chain_err(|| ErrorKind::RetainCapability(name(capability)))?;
+        }
+
+        let result = unsafe { prctl(PR_SET_KEEPCAPS, 0, 0, 0, 0) };
+        Errno::result(result).map(drop).map_err(Error::from)
+    }
+
+    /// The names of the capabilities retained when changing to another user.
+    pub fn describe_retained(&self) -> Result<Vec<String>> {
+        self.keep_caps.iter().map(|cap| number(cap).map(name)).collect()
+    }
+
+    /// The names of the capabilities that will be dropped.
+    pub fn describe(&self) -> Result<Vec<String>> {
+        Ok(self.dropped()?.into_iter().map(name).collect())
+    }
+
+    /// The capabilities to drop.
+    fn dropped(&self) -> Result<Vec<c_int>> {
+        let keep = self.keep.iter()
+            .map(|name| number(name))
+            .collect::<Result<Vec<_>>>()?;
+
+        if self.drop.iter().any(|name| name == ALL) {
+            Ok((0..=last_capability()).filter(|cap| !keep.contains(cap)).collect())
+        } else {
+            self.drop.iter().map(|name| number(name)).collect()
+        }
+    }
+}
+
+/// Whether a capability is in the effective set of the current process.
+pub fn is_effective(name: &str) -> Result<bool> {
+    let capability = number(name)?;
+    let mut header = CapHeader { version: CAPABILITY_VERSION_3, pid: 0 };
+    let mut data = [CapData::default(); 2];
+    let result = unsafe { syscall(SYS_capget, &mut header as *mut CapHeader, data.as_mut_ptr()) };
+    Errno::result(result)?;
+
+    let (index, bit) = (capability as usize / 32, 1 << (capability % 32));
+    Ok(data[index].effective & bit != 0)
+}
+
+/// The number of a capability from its name.
+fn number(name: &str) -> Result<c_int> {
+    let upper = name.to_uppercase();
+    CAPABILITIES.iter()
+        .position(|&capability| {
+            capability == upper || &capability["CAP_".len()..] == upper
+        })
+        .map(|capability| capability as c_int)
+        .ok_or_else(|| ErrorKind::UnknownCapability(name.to_owned()).into())
+}
+
+/// The name of a capability from its number.
+fn name(capability: c_int) -> String {
+    CAPABILITIES.get(capability as usize)
+        .map(|&name| name.to_owned())
+        .unwrap_or_else(|| capability.to_string())
+}
+
+/// The highest numbered capability supported by the kernel.
+fn last_capability() -> c_int {
+    read_to_string("/proc/sys/kernel/cap_last_cap")
+        .ok()
+        .and_then(|last| last.trim().parse().ok())
+        .unwrap_or(CAPABILITIES.len() as c_int - 1)
+}
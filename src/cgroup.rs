@@ -0,0 +1,178 @@
+//! Resource limits using the unified cgroup hierarchy.
+//!
+//! See cgroups(7) and the kernel's cgroup-v2 documentation for the files used.
+
+use std::fs::{create_dir_all, read_link, read_to_string, write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+use cpu;
+use devices::{self, DeviceRule};
+use error::*;
+
+/// The default mount point of the unified hierarchy.
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+/// The default period of the cpu limit in microseconds.
+const CPU_PERIOD: u64 = 100_000;
+
+/// A cgroup created for the container.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Cgroup {
+    /// The cgroup to create the cgroup of the container in.
+    parent: Option<PathBuf>,
+    /// The name of the cgroup of the container.
+    name: Option<String>,
+    /// The maximum memory use in bytes.
+    memory_max: Option<u64>,
+    /// The maximum cpu time in each period.
+    cpu_max: Option<CpuMax>,
+    /// The maximum number of processes.
+    pids_max: Option<u64>,
+    /// The CPUs the cgroup may use.
+    cpuset_cpus: Option<Vec<usize>>,
+    /// The devices that may be used, every other device being denied.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    devices: Vec<DeviceRule>,
+}
+
+/// A limit on the cpu time used within each period.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CpuMax {
+    /// The time in microseconds that can be used each period.
+    quota: u64,
+    /// The length of the period in microseconds.
+    #[serde(default = "default_cpu_period")]
+    period: u64,
+}
+
+fn default_cpu_period() -> u64 {
+    CPU_PERIOD
+}
+
+impl Cgroup {
+    /// Create the cgroup, set its limits, and move the current process into
+    /// it.
+    ///
+    /// Only the controllers needed for the limits set are enabled, so a
+    /// delegated parent need only make those available.
+    pub fn enter(&self) -> Failure {
+        let parent = self.parent();
+        let path = self.path();
+
+        let available = read_to_string(parent.join("cgroup.controllers"))
+            .chain_err(|| ErrorKind::Cgroup(parent.join("cgroup.controllers")))?;
+        let available: Vec<_> = available.split_whitespace().collect();
+
+        if let Some(ref cpus) = self.cpuset_cpus {
+            cpu::validate(cpus)?;
+        }
+        for rule in &self.devices {
+            rule.validate()?;
+        }
+
+        let controllers = self.controllers();
+        for controller in &controllers {
+            ensure!(
+                available.contains(controller),
+                ErrorKind::CgroupController(controller.to_string())
+            );
+        }
+
+        if !controllers.is_empty() {
+            let enable: Vec<_> = controllers.iter()
+                .map(|controller| format!("+{}", controller))
+                .collect();
+            write_file(&parent.join("cgroup.subtree_control"), &enable.join(" "))?;
+        }
+
+        create_dir_all(&path).chain_err(|| ErrorKind::Cgroup(path.clone()))?;
+        for (file, value) in self.limits() {
+            write_file(&path.join(file), &value)?;
+        }
+        if !self.devices.is_empty() {
+            devices::attach(&self.devices, &path)?;
+        }
+
+        write_file(&path.join("cgroup.procs"), &process::id().to_string())
+    }
+
+    /// Describe the cgroup and its limits.
+    pub fn describe(&self) -> Vec<String> {
+        let mut description = vec![format!("cgroup: {}", self.path().display())];
+        for (file, value) in self.limits() {
+            description.push(format!("cgroup {}: {}", file, value));
+        }
+        for rule in &self.devices {
+            description.push(format!("cgroup device: {}", rule.describe()));
+        }
+        description
+    }
+
+    /// The device rules of the cgroup, including the device nodes to create.
+    pub fn devices(&self) -> &[DeviceRule] {
+        &self.devices
+    }
+
+    /// The parent of the cgroup.
+    fn parent(&self) -> PathBuf {
+        self.parent.clone().unwrap_or_else(|| PathBuf::from(CGROUP_ROOT))
+    }
+
+    /// The path of the cgroup.
+    ///
+    /// Without a name, the cgroup is named after the process. Within a PID
+    /// namespace its ID is taken from the original `/proc` as every container
+    /// would otherwise have the same ID.
+    fn path(&self) -> PathBuf {
+        let name = self.name.clone().unwrap_or_else(|| {
+            let pid = read_link("/proc/self")
+                .ok()
+                .and_then(|pid| pid.to_str().map(String::from))
+                .unwrap_or_else(|| process::id().to_string());
+            format!("container-{}", pid)
+        });
+        self.parent().join(name)
+    }
+
+    /// The controllers needed to apply the limits.
+    fn controllers(&self) -> Vec<&'static str> {
+        let mut controllers = Vec::new();
+        if self.memory_max.is_some() {
+            controllers.push("memory");
+        }
+        if self.cpu_max.is_some() {
+            controllers.push("cpu");
+        }
+        if self.pids_max.is_some() {
+            controllers.push("pids");
+        }
+        if self.cpuset_cpus.is_some() {
+            controllers.push("cpuset");
+        }
+        controllers
+    }
+
+    /// The files to write to set each limit.
+    fn limits(&self) -> Vec<(&'static str, String)> {
+        let mut limits = Vec::new();
+        if let Some(memory_max) = self.memory_max {
+            limits.push(("memory.max", memory_max.to_string()));
+        }
+        if let Some(CpuMax { quota, period }) = self.cpu_max {
+            limits.push(("cpu.max", format!("{} {}", quota, period)));
+        }
+        if let Some(pids_max) = self.pids_max {
+            limits.push(("pids.max", pids_max.to_string()));
+        }
+        if let Some(ref cpus) = self.cpuset_cpus {
+            limits.push(("cpuset.cpus", cpu::list(cpus)));
+        }
+        limits
+    }
+}
+
+/// Write a value to a file of a cgroup.
+fn write_file(path: &Path, value: &str) -> Failure {
+    write(path, value).chain_err(|| ErrorKind::Cgroup(path.to_owned()))
+}
@@ -1,110 +1,802 @@
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
 use std::env;
-use std::path::{Path, PathBuf};
+use std::ffi::{CString, OsStr, OsString};
+use std::fs::{create_dir_all, metadata, read_to_string, write, File};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::os::unix::process::CommandExt;
+use std::path::{Component, Path, PathBuf};
 use std::process;
+use std::str::FromStr;
 
-use libc::{uid_t, gid_t};
+use libc::{self, c_char, mode_t, prctl, pid_t, uid_t, gid_t, PR_SET_NO_NEW_PRIVS, PR_SET_PDEATHSIG};
+use serde::{Deserialize, Deserializer};
+use toml;
 use unshare;
-use nix::unistd::{chroot, sethostname, setuid, setgid, setgroups, Uid, Gid};
+use nix::Error as NixError;
+use nix::errno::Errno;
+use nix::mount::{mount, umount2, MntFlags, MsFlags};
+use nix::sched::{setns, CloneFlags};
+use nix::sys::memfd::{memfd_create, MemFdCreateFlag};
+use nix::sys::signal::Signal;
+use nix::unistd::{chroot, getgid, getpid, getppid, getuid, pivot_root, sethostname, setuid, setgid, setgroups, Uid, Gid};
 
 use error::*;
-use mount::Mount;
+use error_chain::ChainedError;
+use capability::Capabilities;
+use cgroup::Cgroup;
+use rlimit::Rlimit;
+use cpu;
+use fstab;
+use priority::{self, SchedPolicy};
+use network::{self, Network};
+use seccomp::Seccomp;
+use subid;
+use tty;
+use sysctl;
+use user;
+use mount::{retry_interrupted, Mount, MountFlags, MountStack};
+use oci;
+use hint::{hint, Operation};
+
+/// The resolver configuration written within the container.
+const RESOLV_CONF: &str = "/etc/resolv.conf";
+
+/// The static host names written within the container.
+const HOSTS: &str = "/etc/hosts";
+
+/// The paths masked when the mount namespace is private and no others are
+/// given, as by common OCI runtimes.
+const DEFAULT_MASKED_PATHS: &[&str] = &[
+    // Interfaces to the ACPI of the host, some of which can be written.
+    "/proc/acpi",
+    // The sound devices of the host, some of which can be configured.
+    "/proc/asound",
+    // The physical memory of the host, in the format of a core file.
+    "/proc/kcore",
+    // The keys of every keyring that can be viewed, which may belong to the
+    // host or to other containers.
+    "/proc/keys",
+    // The latency of every process on the host, by function of the kernel.
+    "/proc/latency_stats",
+    // The pending timers of the host, with the addresses of their callbacks
+    // within the kernel and the processes that set them.
+    "/proc/timer_list",
+    // The timers of every process on older kernels, with the commands that set
+    // them, which reveal the activity of the host.
+    "/proc/timer_stats",
+    // The state of the scheduler and every process on the host.
+    "/proc/sched_debug",
+    // The SCSI devices of the host, which can be added and removed by writing.
+    "/proc/scsi",
+    // The firmware of the host, including EFI variables that can be written.
+    "/sys/firmware",
+    // The energy counters of the host, which leak the data being processed
+    // by other processes through the power drawn.
+    "/sys/devices/virtual/powercap",
+];
 
 /// Configuration for the container.
-#[derive(Debug, Default, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct Config {
+    // Files merged beneath this configuration, which are resolved on loading
+    #[serde(default, skip_serializing)]
+    include: Vec<PathBuf>,
+
     // Namespaces to unshare
     #[serde(default)]
+    #[serde(deserialize_with = "namespace_set")]
     namespaces: Vec<Namespace>,
+    #[serde(default)]
+    init: bool,
+    join: Option<Join>,
+    time_offsets: Option<TimeOffsets>,
 
     // User namespace configuration
+    user: Option<String>,
     #[serde(default)]
     uid: Option<uid_t>,
     #[serde(default)]
     gid: Option<gid_t>,
     #[serde(default)]
+    groups: Vec<gid_t>,
+    #[serde(default)]
+    #[serde(deserialize_with = "one_or_many")]
     uid_map: Vec<UidMap>,
     #[serde(default)]
+    #[serde(deserialize_with = "one_or_many")]
     gid_map: Vec<GidMap>,
+    #[serde(default)]
+    auto_subid: bool,
 
     // Mount configuration
     #[serde(default)]
     #[serde(rename = "mount")]
     mounts: Vec<Mount>,
+    #[serde(default)]
+    cleanup_mounts: bool,
+    #[serde(default)]
+    ordered_mounts: bool,
+    #[serde(default)]
+    propagate_mounts: bool,
+    #[serde(default)]
+    source_prefix: Option<PathBuf>,
+    /// Mask the default paths. If unset, they are only masked when the root
+    /// is made private (see `private_root`), not whenever the mount namespace
+    /// is unshared.
+    default_masks: Option<bool>,
+    #[serde(default)]
+    masked_paths: Vec<PathBuf>,
+    #[serde(default)]
+    readonly_paths: Vec<PathBuf>,
+    #[serde(default)]
+    idempotent: bool,
+
+    // Capability configuration
+    #[serde(default)]
+    capabilities: Capabilities,
+    #[serde(default)]
+    no_new_privs: bool,
+    seccomp: Option<Seccomp>,
+
+    // Resource limits
+    cgroup: Option<Cgroup>,
+    #[serde(default)]
+    rlimits: BTreeMap<String, Rlimit>,
+    #[serde(default)]
+    cpu_affinity: Vec<usize>,
+    nice: Option<i32>,
+    oom_score_adj: Option<i32>,
+    sched_policy: Option<SchedPolicy>,
+
+    // Kernel parameters
+    #[serde(default)]
+    sysctls: BTreeMap<String, String>,
 
     // Uts COnfiguration
     hostname: Option<String>,
+    domainname: Option<String>,
+
+    // Network configuration
+    network: Option<Network>,
+    #[serde(default)]
+    resolv_conf: Vec<String>,
+    #[serde(default)]
+    write_hosts: bool,
 
     // Additional configuration
+    pid_file: Option<PathBuf>,
+    log_file: Option<PathBuf>,
+    state_file: Option<PathBuf>,
+    /// The OCI bundle the configuration was loaded from.
+    #[serde(skip)]
+    bundle: Option<PathBuf>,
+    #[serde(default)]
+    tty: bool,
     chroot_dir: Option<PathBuf>,
+    #[serde(default)]
+    make_chroot_dir: bool,
+    #[serde(default)]
+    use_pivot_root: bool,
     working_dir: Option<PathBuf>,
+    #[serde(default)]
+    make_working_dir: bool,
+    umask: Option<u32>,
+    #[serde(default)]
+    command: Vec<String>,
+    #[serde(default)]
+    pre_start: Vec<String>,
+    #[serde(default)]
+    post_stop: Vec<String>,
+    #[serde(default)]
+    post_stop_required: bool,
+    #[serde(default)]
+    die_with_parent: bool,
+
+    // Environment of the command
+    #[serde(default)]
+    env_clear: bool,
+    #[serde(default)]
+    env_passthrough: Vec<String>,
+    #[serde(default)]
+    env: BTreeMap<String, String>,
 }
 
 impl Config {
+    /// Load the configuration from a specific file.
+    ///
+    /// Included files are relative to the directory of the file.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Config> {
+        Config::load_included(path.as_ref(), &mut Vec::new())
+    }
+
+    /// Load the configuration from the standard input.
+    ///
+    /// Included files are relative to the current directory.
+    pub fn from_stdin() -> Result<Config> {
+        let mut config = String::new();
+        io::stdin().read_to_string(&mut config)
+            .map_err(Error::from)
+            .and_then(|_| Ok(toml::from_str::<Config>(&config)?))
+            .chain_err(|| ErrorKind::LoadConfig(PathBuf::from("stdin")))?
+            .include_from(Path::new(""), &mut Vec::new())
+    }
+
+    /// Merge the files included by a configuration that was not loaded from a
+    /// specific file, relative to the current directory.
+    pub fn resolve_includes(self) -> Result<Config> {
+        self.include_from(Path::new(""), &mut Vec::new())
+    }
+
+    /// Load a file and the files it includes, given the files already being
+    /// loaded.
+    fn load_included(path: &Path, loading: &mut Vec<PathBuf>) -> Result<Config> {
+        let canonical = path.canonicalize().chain_err(|| ErrorKind::LoadConfig(path.to_owned()))?;
+        ensure!(!loading.contains(&canonical), ErrorKind::IncludeCycle(path.to_owned()));
+        loading.push(canonical);
+
+        let config: Config = read_to_string(path)
+            .map_err(Error::from)
+            .and_then(|config| Ok(toml::from_str(&config)?))
+            .chain_err(|| ErrorKind::LoadConfig(path.to_owned()))?;
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        let config = config.include_from(dir, loading)?;
+
+        loading.pop();
+        Ok(config)
+    }
+
+    /// Merge the included files in order beneath the configuration.
+    fn include_from(mut self, dir: &Path, loading: &mut Vec<PathBuf>) -> Result<Config> {
+        let mut base = Config::default();
+        for path in ::std::mem::replace(&mut self.include, Vec::new()) {
+            let included = Config::load_included(&dir.join(path), loading)?;
+            base = Config::merge(base, included);
+        }
+        Ok(Config::merge(base, self))
+    }
+
+    /// Merge one configuration over another.
+    ///
+    /// Values set in the overlay replace those of the base, with lists appended
+    /// to and tables extended. Flags are set if set in either, and sections such
+    /// as the cgroup are replaced as a whole.
+    pub fn merge(base: Config, overlay: Config) -> Config {
+        fn append<T>(mut base: Vec<T>, overlay: Vec<T>) -> Vec<T> {
+            base.extend(overlay);
+            base
+        }
+        fn extend<V>(mut base: BTreeMap<String, V>, overlay: BTreeMap<String, V>) -> BTreeMap<String, V> {
+            base.extend(overlay);
+            base
+        }
+
+        let mut namespaces = base.namespaces;
+        for namespace in overlay.namespaces {
+            if !namespaces.contains(&namespace) {
+                namespaces.push(namespace);
+            }
+        }
+
+        // The command is only replaced, as the arguments of one are meaningless
+        // to another.
+        let command = match overlay.command {
+            ref command if command.is_empty() => base.command,
+            command => command,
+        };
+
+        Config {
+            include: append(base.include, overlay.include),
+            namespaces,
+            init: base.init || overlay.init,
+            join: overlay.join.or(base.join),
+            time_offsets: overlay.time_offsets.or(base.time_offsets),
+            user: overlay.user.or(base.user),
+            uid: overlay.uid.or(base.uid),
+            gid: overlay.gid.or(base.gid),
+            groups: append(base.groups, overlay.groups),
+            uid_map: append(base.uid_map, overlay.uid_map),
+            gid_map: append(base.gid_map, overlay.gid_map),
+            auto_subid: base.auto_subid || overlay.auto_subid,
+            mounts: append(base.mounts, overlay.mounts),
+            cleanup_mounts: base.cleanup_mounts || overlay.cleanup_mounts,
+            ordered_mounts: base.ordered_mounts || overlay.ordered_mounts,
+            propagate_mounts: base.propagate_mounts || overlay.propagate_mounts,
+            source_prefix: overlay.source_prefix.or(base.source_prefix),
+            default_masks: overlay.default_masks.or(base.default_masks),
+            masked_paths: append(base.masked_paths, overlay.masked_paths),
+            readonly_paths: append(base.readonly_paths, overlay.readonly_paths),
+            idempotent: base.idempotent || overlay.idempotent,
+            capabilities: Capabilities::merge(base.capabilities, overlay.capabilities),
+            no_new_privs: base.no_new_privs || overlay.no_new_privs,
+            seccomp: overlay.seccomp.or(base.seccomp),
+            cgroup: overlay.cgroup.or(base.cgroup),
+            rlimits: extend(base.rlimits, overlay.rlimits),
+            cpu_affinity: append(base.cpu_affinity, overlay.cpu_affinity),
+            nice: overlay.nice.or(base.nice),
+            oom_score_adj: overlay.oom_score_adj.or(base.oom_score_adj),
+            sched_policy: overlay.sched_policy.or(base.sched_policy),
+            sysctls: extend(base.sysctls, overlay.sysctls),
+            hostname: overlay.hostname.or(base.hostname),
+            domainname: overlay.domainname.or(base.domainname),
+            network: overlay.network.or(base.network),
+            resolv_conf: append(base.resolv_conf, overlay.resolv_conf),
+            write_hosts: base.write_hosts || overlay.write_hosts,
+            pid_file: overlay.pid_file.or(base.pid_file),
+            log_file: overlay.log_file.or(base.log_file),
+            state_file: overlay.state_file.or(base.state_file),
+            bundle: overlay.bundle.or(base.bundle),
+            tty: base.tty || overlay.tty,
+            chroot_dir: overlay.chroot_dir.or(base.chroot_dir),
+            make_chroot_dir: base.make_chroot_dir || overlay.make_chroot_dir,
+            use_pivot_root: base.use_pivot_root || overlay.use_pivot_root,
+            working_dir: overlay.working_dir.or(base.working_dir),
+            make_working_dir: base.make_working_dir || overlay.make_working_dir,
+            umask: overlay.umask.or(base.umask),
+            command,
+            pre_start: append(base.pre_start, overlay.pre_start),
+            post_stop: append(base.post_stop, overlay.post_stop),
+            post_stop_required: base.post_stop_required || overlay.post_stop_required,
+            die_with_parent: base.die_with_parent || overlay.die_with_parent,
+            env_clear: base.env_clear || overlay.env_clear,
+            env_passthrough: append(base.env_passthrough, overlay.env_passthrough),
+            env: extend(base.env, overlay.env),
+        }
+    }
+
+    /// Load a configuration written by `to_file`.
+    pub fn from_file(mut file: File) -> Result<Config> {
+        let mut config = String::new();
+        file.read_to_string(&mut config)?;
+        Ok(toml::from_str(&config)?)
+    }
+
+    /// Write the configuration to an anonymous file, to pass it on to another
+    /// process.
+    ///
+    /// The file is closed on exec unless it is duplicated.
+    pub fn to_file(&self) -> Result<File> {
+        let name = CString::new(env!("CARGO_PKG_NAME"))?;
+        let fd = memfd_create(&name, MemFdCreateFlag::MFD_CLOEXEC)?;
+        let mut file = unsafe { File::from_raw_fd(fd) };
+
+        let value = toml::Value::try_from(self)?;
+        file.write_all(toml::to_string(&value)?.as_bytes())?;
+        file.seek(SeekFrom::Start(0))?;
+        Ok(file)
+    }
+
+    /// Load the configuration from the `config.json` of an OCI bundle.
+    ///
+    /// Only the namespaces, id mappings, mounts, user, root, working directory,
+    /// command, environment, and masked and read-only paths are used. Any other
+    /// fields are ignored, and a warning for each is returned for the caller to
+    /// print.
+    pub fn from_oci<P: AsRef<Path>>(bundle: P) -> Result<(Config, Vec<String>)> {
+        let bundle = bundle.as_ref();
+        let path = bundle.join("config.json");
+        let spec: oci::Spec = File::open(&path)
+            .map_err(Error::from)
+            .and_then(|file| Ok(::serde_json::from_reader(file)?))
+            .chain_err(|| ErrorKind::LoadConfig(path.clone()))?;
+
+        let mut config = Config::default();
+        config.bundle = Some(bundle.to_owned());
+        let mut warnings = oci::unsupported_fields("", &spec.unsupported);
+
+        if let Some(root) = spec.root {
+            warnings.extend(oci::unsupported_fields("root.", &root.unsupported));
+            config.chroot_dir = Some(bundle.join(root.path));
+        }
+        let root = config.chroot_dir.clone().unwrap_or_else(|| PathBuf::from("/"));
+
+        config.hostname = spec.hostname;
+        config.mounts = spec.mounts.into_iter()
+            .map(|mount| oci_mount(mount, &root))
+            .collect();
+        // The mounts are listed in the order they must be made.
+        config.ordered_mounts = true;
+
+        if let Some(process) = spec.process {
+            warnings.extend(oci::unsupported_fields("process.", &process.unsupported));
+            if let Some(user) = process.user {
+                warnings.extend(oci::unsupported_fields("process.user.", &user.unsupported));
+                config.uid = Some(user.uid);
+                config.gid = Some(user.gid);
+                config.groups = user.additional_gids;
+            }
+            config.working_dir = process.cwd;
+            config.command = process.args;
+            config.no_new_privs = process.no_new_privileges;
+
+            // The environment is given in full.
+            config.env_clear = true;
+            config.env = process.env.iter()
+                .filter_map(|variable| {
+                    let mut parts = variable.splitn(2, '=');
+                    Some((parts.next()?.to_owned(), parts.next()?.to_owned()))
+                })
+                .collect();
+        }
+
+        if let Some(linux) = spec.linux {
+            warnings.extend(oci::unsupported_fields("linux.", &linux.unsupported));
+            let mut paths = Vec::new();
+            for oci::Namespace { namespace, path } in linux.namespaces {
+                let namespace = match namespace.as_str() {
+                    "mount"   => Namespace::Mount,
+                    "uts"     => Namespace::Uts,
+                    "ipc"     => Namespace::Ipc,
+                    "user"    => Namespace::User,
+                    "pid"     => Namespace::Pid,
+                    "network" => Namespace::Net,
+                    "cgroup"  => Namespace::Cgroup,
+                    "time"    => Namespace::Time,
+                    _ => {
+                        warnings.push(format!("ignoring unsupported OCI namespace {}", namespace));
+                        continue;
+                    },
+                };
+                match path {
+                    Some(path) => paths.push(JoinPath { namespace, path }),
+                    None => config.namespaces.push(namespace),
+                }
+            }
+            if !paths.is_empty() {
+                config.join = Some(Join { pid: None, paths });
+            }
+
+            config.uid_map = linux.uid_mappings.into_iter()
+                .map(|map| UidMap { inside: map.container_id, outside: map.host_id, count: map.size })
+                .collect();
+            config.gid_map = linux.gid_mappings.into_iter()
+                .map(|map| GidMap { inside: map.container_id, outside: map.host_id, count: map.size })
+                .collect();
+            // The bundle lists every path to mask.
+            config.default_masks = Some(false);
+            config.masked_paths = linux.masked_paths;
+            config.readonly_paths = linux.readonly_paths;
+        }
+
+        Ok((config, warnings))
+    }
+
+    /// Add a bind mount given as `source:target[:options]`, in the same form
+    /// as the volumes of docker.
+    ///
+    /// The target is within the chroot directory, if any, and the only options
+    /// are `ro` and `rw`. The mount is added after those of the configuration.
+    pub fn add_volume(&mut self, volume: &str) -> Failure {
+        let bad_volume = || ErrorKind::BadVolume(volume.to_owned());
+
+        let mut parts = volume.split(':');
+        let (source, target) = match (parts.next(), parts.next()) {
+            (Some(source), Some(target)) if !source.is_empty() && !target.is_empty() => {
+                (source, Path::new(target))
+            },
+            _ => bail!(bad_volume()),
+        };
+        let read_only = match parts.next() {
+            None | Some("rw") => false,
+            Some("ro") => true,
+            Some(_) => bail!(bad_volume()),
+        };
+        if parts.next().is_some() {
+            bail!(bad_volume());
+        }
+
+        let target = match self.chroot_dir {
+            Some(ref root) => root.join(target.strip_prefix("/").unwrap_or(target)),
+            None => target.to_owned(),
+        };
+        let mount = Mount::bind(PathBuf::from(source), target).make_target_dir();
+        self.mounts.push(if read_only { mount.read_only() } else { mount });
+
+        ok!()
+    }
+
+    /// Add the mounts listed in a file in the format of `/etc/fstab`, with
+    /// each target within `chroot_dir`.
+    pub fn mounts_from_fstab<P: AsRef<Path>>(&mut self, path: P) -> Failure {
+        let root = self.chroot_dir.clone();
+        let mounts = fstab::load(path.as_ref(), root.as_ref().map(PathBuf::as_path))?;
+        self.mounts.extend(mounts);
+        ok!()
+    }
+
     /// Configure the container prior to the container.
-    pub fn unshare(self, command: &mut unshare::Command) -> Failure {
-        let uses_root = self.uses_root();
+    pub fn unshare(mut self, command: &mut unshare::Command) -> Failure {
+        let (start_uid, start_gid) = self.start_ids();
+        self.add_subordinate_maps()?;
+        self.add_implied_namespaces();
+        self.validate_maps()?;
 
         let Config {
             namespaces,
+            join,
             uid_map,
             gid_map,
-            uid,
-            gid,
+            network,
+            die_with_parent,
             ..
         } = self;
 
-        command.unshare(namespaces.into_iter().map(Namespace::into));
-        command.set_id_maps(
-            uid_map.into_iter().map(UidMap::into).collect(),
-            gid_map.into_iter().map(GidMap::into).collect(),
-        );
+        if let Some(join) = join {
+            for (namespace, path) in join.namespaces(&namespaces)? {
+                trace!("join: {} {}", namespace.name(), path.display());
+                let namespace = namespace.unshare_namespace()
+                    .ok_or_else(|| ErrorKind::UnsupportedJoin(namespace.name()))?;
+                File::open(&path)
+                    .and_then(|file| command.set_namespace(&file, namespace).map(drop))
+                    .chain_err(|| ErrorKind::JoinNamespace(path))?;
+            }
+        }
+        trace!("unshare: {}", namespaces.iter()
+            .map(|&namespace| namespace.name())
+            .collect::<Vec<_>>()
+            .join(", "));
+        command.unshare(namespaces.into_iter().filter_map(Namespace::unshare_namespace));
+        for map in &uid_map {
+            trace!("uid_map: {} {} {}", map.inside, map.outside, map.count);
+        }
+        for map in &gid_map {
+            trace!("gid_map: {} {} {}", map.inside, map.outside, map.count);
+        }
 
-        if let (
-            Some(newuidmap), Some(newgidmap)
-        ) = (
-            find_exec("newuidmap"), find_exec("newgidmap")
-        ) {
-            command.set_id_map_commands(newuidmap, newgidmap);
+        // Without the helpers, only root can map more than its own ids. The
+        // maps of any other user are written here so that setgroups is denied
+        // first, as is required to write the gid map.
+        let paths = env::var_os("PATH");
+        let find_helper = |name| find_id_map_helper(paths.as_ref().map(OsString::as_os_str), name);
+        let helpers = (find_helper("newuidmap"), find_helper("newgidmap"));
+        let maps_ids = !uid_map.is_empty() || !gid_map.is_empty();
+        let mut direct_maps = None;
+        match helpers {
+            (Ok(newuidmap), Ok(newgidmap)) => {
+                trace!("id maps written by {} and {}", newuidmap.display(), newgidmap.display());
+                command.set_id_map_commands(newuidmap, newgidmap);
+            },
+            (newuidmap, newgidmap) if maps_ids && !getuid().is_root() => {
+                let uid = uid_t::from(getuid());
+                let gid = gid_t::from(getgid());
+                let maps_self = uid_map.len() <= 1 && gid_map.len() <= 1
+                    && uid_map.iter().all(|map| map.outside == uid && map.count == 1)
+                    && gid_map.iter().all(|map| map.outside == gid && map.count == 1);
+                // The helpers are required, so whichever is missing is reported.
+                if !maps_self {
+                    newuidmap.and(newgidmap).chain_err(|| ErrorKind::IdMapHelpers)?;
+                }
+                trace!("id maps written directly");
+                let uid_ranges: Vec<_> = uid_map.iter().map(UidMap::range).collect();
+                let gid_ranges: Vec<_> = gid_map.iter().map(GidMap::range).collect();
+                direct_maps = Some((map_contents(&uid_ranges), map_contents(&gid_ranges)));
+            },
+            _ => {},
+        }
+        if direct_maps.is_none() {
+            command.set_id_maps(
+                uid_map.into_iter().map(UidMap::into).collect(),
+                gid_map.into_iter().map(GidMap::into).collect(),
+            );
         }
 
-        if uses_root {
-            command.uid(0);
-            command.gid(0);
-        } else {
-            if let Some(uid) = uid {
-                command.uid(uid);
-            }
-            if let Some(gid) = gid {
-                command.uid(gid);
-            }
+        // The host end of the network is created from outside of the container
+        // before it starts.
+        if direct_maps.is_some() || network.is_some() {
+            command.before_unfreeze(move |pid| {
+                let pid = pid as pid_t;
+                let prepare = || -> Failure {
+                    if let Some((ref uid_map, ref gid_map)) = direct_maps {
+                        write_id_maps(pid, uid_map, gid_map)?;
+                    }
+                    if let Some(ref network) = network {
+                        network.create(pid)?;
+                    }
+                    ok!()
+                };
+                prepare().map_err(|err| err.display_chain().to_string().into())
+            });
+        }
+
+        if die_with_parent {
+            trace!("die with parent");
+            command.set_parent_death_signal(Signal::SIGKILL);
+        }
+
+        if let Some(uid) = start_uid {
+            trace!("start as: uid {}", uid);
+            command.uid(uid);
+        }
+        if let Some(gid) = start_gid {
+            trace!("start as: gid {}", gid);
+            command.gid(gid);
         }
 
         ok!()
     }
 
     /// Configure the container after having entered.
-    pub fn configure(self, _command: &mut process::Command) -> Failure {
+    ///
+    /// If the mounts are to be cleaned up, they are unmounted when the returned
+    /// stack is dropped.
+    pub fn configure(self, command: &mut process::Command) -> Result<MountStack> {
         let uses_root = self.uses_root();
+        let private_root = self.private_root();
+        let private_uts = self.namespaces.contains(&Namespace::Uts);
+        let private_time = self.namespaces.contains(&Namespace::Time);
+        let private_net = self.namespaces.contains(&Namespace::Net) || self.network.is_some();
+        let private_namespaces = self.private_namespaces();
+        let masked_paths = self.masked_paths();
+        let (clear_env, env) = self.environment(|key| env::var_os(key));
 
         let Config {
             chroot_dir,
+            make_chroot_dir,
+            use_pivot_root,
             working_dir,
+            make_working_dir,
             mounts,
+            cleanup_mounts,
+            ordered_mounts,
+            source_prefix,
+            readonly_paths,
+            idempotent,
             hostname,
+            domainname,
+            network,
+            resolv_conf,
+            write_hosts,
+            user,
             uid,
             gid,
+            groups,
+            capabilities,
+            no_new_privs,
+            seccomp,
+            cgroup,
+            rlimits,
+            cpu_affinity,
+            nice,
+            oom_score_adj,
+            sched_policy,
+            sysctls,
+            umask,
+            die_with_parent,
+            tty,
+            time_offsets,
             ..
         } = self;
 
-        if let Some(hostname) = hostname {
-            sethostname(&hostname).chain_err(|| ErrorKind::SetHostName)?;
+        if clear_env {
+            command.env_clear();
+        }
+        command.envs(env);
+
+        if let Some(ref hostname) = hostname {
+            trace!("hostname: {}", hostname);
+            sethostname(hostname)
+                .map_err(hint(Operation::SetHostName))
+                .chain_err(|| ErrorKind::SetHostName)?;
+        }
+
+        // Unlike the host name, the domain name is only ever set within a new
+        // UTS namespace.
+        if let (Some(domainname), true) = (domainname, private_uts) {
+            trace!("domainname: {}", domainname);
+            setdomainname(&domainname)
+                .map_err(hint(Operation::SetDomainName))
+                .chain_err(|| ErrorKind::SetDomainName)?;
+        }
+
+        // Only a container configured as root has the privileges to change
+        // interfaces.
+        let loopback_up = network.as_ref().map_or(true, Network::loopback_up);
+        if private_net && uses_root && loopback_up {
+            trace!("network: lo up");
+            network::bring_up_loopback()?;
+        }
+        if let Some(network) = network {
+            for line in network.describe() {
+                trace!("{}", line);
+            }
+            network.configure()?;
+        }
+
+        // Set once any network is configured, before /proc may be hidden.
+        for (key, value) in sysctls {
+            trace!("sysctl {}: {}", key, value);
+            sysctl::validate(&key, &private_namespaces)?;
+            sysctl::set(&key, &value)?;
+        }
+
+        // The device nodes are created once within the new root.
+        let device_nodes: Vec<_> = cgroup.iter()
+            .flat_map(|cgroup| cgroup.devices().to_vec())
+            .filter(|rule| rule.path().is_some())
+            .collect();
+
+        // The cgroup hierarchy may be hidden by the mounts or chroot.
+        if let Some(cgroup) = cgroup {
+            for line in cgroup.describe() {
+                trace!("{}", line);
+            }
+            cgroup.enter()?;
+        }
+
+        // Set after entering the cgroup, as the affinity must be within its
+        // cpuset.
+        if !cpu_affinity.is_empty() {
+            trace!("cpu affinity: {}", cpu::list(&cpu_affinity));
+            cpu::set_affinity(&cpu_affinity)?;
+        }
+
+        // Set before the mounts, which may hide /proc.
+        if let Some(policy) = sched_policy {
+            trace!("sched policy: {}", policy.name());
+            policy.set()?;
+        }
+        if let Some(nice) = nice {
+            trace!("nice: {}", nice);
+            priority::set_nice(nice)?;
+        }
+        if let Some(adj) = oom_score_adj {
+            trace!("oom_score_adj: {}", adj);
+            priority::set_oom_score_adj(adj)?;
         }
 
-        for mount in mounts {
-            mount.mount().chain_err(|| ErrorKind::SetMount)?;
+        if private_time {
+            let offsets = time_offsets.unwrap_or_default();
+            trace!("time offsets: monotonic {} boottime {}", offsets.monotonic, offsets.boottime);
+            unshare_time(&offsets)?;
+        }
+
+        // The new mount namespace is a copy of the original, including the
+        // propagation of each mount point. If any are shared with the original
+        // namespace, mounts made in the container would also be made outside
+        // of it.
+        if private_root {
+            let root = Mount::recursive_private("/");
+            trace!("{}", root.describe());
+            root.mount()
+                .map_err(hint(Operation::Mount))
+                .chain_err(|| ErrorKind::SetMount(PathBuf::from("/")))?;
+        }
+
+        // Created before the mounts, which may be within it.
+        if let (Some(ref chroot_dir), true) = (&chroot_dir, make_chroot_dir) {
+            trace!("make chroot_dir: {}", chroot_dir.display());
+            create_dir_all(chroot_dir)
+                .chain_err(|| ErrorKind::MakeChrootDir(chroot_dir.clone()))?;
+        }
+
+        // Any mounts already made are undone if a later mount fails.
+        let mounts = prepare_mounts(mounts, ordered_mounts, &source_prefix)?;
+        let path_mounts = path_mounts(&masked_paths, &readonly_paths, chroot_dir.as_ref());
+        let mut mounted = MountStack::make(mounts.into_iter().chain(path_mounts), |mount| {
+            let target = mount.target().to_owned();
+            if idempotent && mount.is_mounted()? {
+                trace!("already mounted: {}", target.display());
+                return Ok(None);
+            }
+            trace!("{}", mount.describe());
+            mount.mount_scoped()
+                .map(Some)
+                .map_err(hint(Operation::Mount))
+                .chain_err(|| ErrorKind::SetMount(target))
+        })?;
+        if !cleanup_mounts {
+            mounted.forget();
         }
 
         if let Some(ref chroot_dir) = chroot_dir {
+            if use_pivot_root {
+                trace!("pivot_root: {}", chroot_dir.display());
+            } else {
+                trace!("chroot: {}", chroot_dir.display());
+            }
+            ensure!(chroot_dir.is_dir(), ErrorKind::ChrootNotFound(chroot_dir.clone()));
             chroot_dir.canonicalize()
                 .map_err(Error::from)
                 .and_then(|path| {
@@ -112,134 +804,1955 @@ impl Config {
                     Ok(path)
                 })
                 .and_then(|path| {
-                    chroot(&path)?;
+                    if use_pivot_root {
+                        enter_pivot_root(&path)?;
+                    } else {
+                        chroot(&path)?;
+                    }
+                    mounted.rebase(&path);
                     ok!()
                 })
+                .map_err(hint(Operation::EnterChroot))
                 .chain_err(|| ErrorKind::EnterChroot)?;
         }
 
+        // Only written within a new root, which may not have its own, so that
+        // those of the host are never replaced.
+        if chroot_dir.is_some() && !resolv_conf.is_empty() {
+            trace!("write {}: nameservers {}", RESOLV_CONF, resolv_conf.join(", "));
+            write_etc_file(RESOLV_CONF, &resolv_conf_contents(&resolv_conf))?;
+        }
+        if chroot_dir.is_some() && write_hosts {
+            trace!("write {}", HOSTS);
+            write_etc_file(HOSTS, &hosts_contents(hostname.as_ref()))?;
+        }
+        for rule in &device_nodes {
+            trace!("device: {}", rule.describe());
+            rule.create_node()?;
+        }
+
+        // Named users are looked up within the new root, with any ids that are
+        // configured taking precedence.
+        let (uid, gid, groups) = match user {
+            Some(ref name) => {
+                trace!("user: {}", name);
+                let user = user::lookup(name)?;
+                let groups = if groups.is_empty() { user.groups } else { groups };
+                (uid.or(Some(user.uid)), gid.or(user.gid), groups)
+            },
+            None => (uid, gid, groups),
+        };
+
         if let Some(working_dir) = working_dir {
-            ensure!(
-                working_dir.is_absolute() || chroot_dir.is_none(),
-                ErrorKind::RelativeWorkingDir
-            );
+            let working_dir = resolve_working_dir(working_dir, chroot_dir.is_some())?;
+            trace!("working_dir: {}", working_dir.display());
+            if make_working_dir {
+                create_dir_all(&working_dir)
+                    .chain_err(|| ErrorKind::MakeWorkingDir(working_dir.clone()))?;
+            }
             env::set_current_dir(&working_dir)
                 .chain_err(|| ErrorKind::EnterWorkingDir)?;
         }
 
-        if let Some(gid) = gid {
-            setgroups(&[Gid::from_raw(gid)])
-                    .map_err(Error::from)
-                    .chain_err(|| ErrorKind::SetUser)?;
+        // Raising a hard limit requires the privileges of root.
+        for (name, limit) in rlimits {
+            trace!("rlimit {}: {}", name, limit.describe());
+            limit.set(&name)?;
+        }
+
+        // The bounding set can only be changed while still holding
+        // CAP_SETPCAP, before changing user.
+        capabilities.drop_bounding()?;
+
+        // Any supplementary groups of the user that started the container are
+        // replaced, leaving none unless more are given.
+        //
+        // Where setgroups is denied, as for a user namespace created without
+        // privileges, the groups can only be left as they are.
+        let keep_groups = groups.is_empty() && setgroups_denied();
+        if (uses_root || gid.is_some() || !groups.is_empty()) && !keep_groups {
+            trace!("groups: {:?}", groups);
+            let groups: Vec<_> = groups.into_iter().map(Gid::from_raw).collect();
+            setgroups(&groups)
+                .map_err(|err| match err {
+                    NixError::Sys(Errno::EPERM) => ErrorKind::SetGroupsDenied.into(),
+                    err => hint(Operation::SetUser)(err),
+                })
+                .chain_err(|| ErrorKind::SetUser)?;
         }
 
         if uses_root {
             if let Some(gid) = gid {
+                trace!("gid: {}", gid);
                 setgid(Gid::from_raw(gid))
-                    .map_err(Error::from)
+                    .map_err(hint(Operation::SetUser))
                     .chain_err(|| ErrorKind::SetUser)?;
             }
             if let Some(uid) = uid {
+                trace!("uid: {}", uid);
+                capabilities.keep_through_setuid()?;
                 setuid(Uid::from_raw(uid))
+                    .map_err(hint(Operation::SetUser))
                     .chain_err(|| ErrorKind::SetUser)?;
+                for name in capabilities.describe_retained()? {
+                    trace!("keep capability: {}", name);
+                }
+                capabilities.raise_retained()?;
             }
         }
 
-        ok!()
-    }
-
-    /// The inner program needs to start as root.
-    fn uses_root(&self) -> bool {
-        self.hostname.is_some()
-            || self.chroot_dir.is_some()
-            || self.mounts.len() > 0
-    }
-}
-
-/// Serialisable namespaces.
-#[derive(Debug, Clone, Copy, Deserialize)]
-#[serde(rename_all = "snake_case")]
-enum Namespace {
-    Mount,
-    Uts,
-    Ipc,
-    User,
-    Pid,
-    Net,
-    Cgroup,
-}
-
-impl Into<unshare::Namespace> for Namespace {
-    fn into(self) -> unshare::Namespace {
-        match self {
-            Namespace::Mount  => unshare::Namespace::Mount,
-            Namespace::Uts    => unshare::Namespace::Uts,
-            Namespace::Ipc    => unshare::Namespace::Ipc,
-            Namespace::User   => unshare::Namespace::User,
-            Namespace::Pid    => unshare::Namespace::Pid,
-            Namespace::Net    => unshare::Namespace::Net,
-            Namespace::Cgroup => unshare::Namespace::Cgroup,
+        if let Some(umask) = umask {
+            trace!("umask: {:04o}", umask);
+            unsafe { libc::umask(umask as mode_t) };
         }
-    }
-}
-
-#[derive(Debug, Deserialize)]
-struct UidMap {
-    inside: uid_t,
-    outside: uid_t,
-    count: uid_t,
-}
 
-impl Into<unshare::UidMap> for UidMap {
-    fn into(self) -> unshare::UidMap {
-        let UidMap {inside, outside, count} = self;
-        unshare::UidMap {
-            inside_uid: inside,
-            outside_uid: outside,
-            count
+        // The signal is set in the command itself once it has started. If this
+        // process has already exited by then, the signal would never be sent,
+        // so the command is stopped instead.
+        if die_with_parent {
+            trace!("die with parent");
+            let parent = getpid();
+            let set_death_signal = move || {
+                let result = unsafe { prctl(PR_SET_PDEATHSIG, Signal::SIGKILL as libc::c_ulong) };
+                Errno::result(result).map_err(|_| ::std::io::Error::last_os_error())?;
+                if getppid() != parent {
+                    return Err(::std::io::Error::from_raw_os_error(libc::ESRCH));
+                }
+                Ok(())
+            };
+            unsafe { command.pre_exec(set_death_signal) };
         }
-    }
-}
 
-#[derive(Debug, Deserialize)]
-struct GidMap {
-    inside: gid_t,
-    outside: gid_t,
-    count: gid_t,
-}
+        // The pseudo-terminal given as the standard streams becomes the
+        // controlling terminal of the command, in a session of its own.
+        if tty {
+            trace!("controlling terminal");
+            unsafe { command.pre_exec(tty::set_controlling_terminal) };
+        }
 
-impl Into<unshare::GidMap> for GidMap {
-    fn into(self) -> unshare::GidMap {
-        let GidMap {inside, outside, count} = self;
-        unshare::GidMap {
-            inside_gid: inside,
-            outside_gid: outside,
-            count
+        // This process still runs the hooks, forwards signals, and waits for
+        // the command, so the filter is installed by the command itself as
+        // the last step before it is executed. The filter then doesn't need to
+        // allow anything used to configure the container.
+        let filter = match seccomp {
+            Some(seccomp) => Some(seccomp.prepare(no_new_privs)?),
+            None => None,
+        };
+        if no_new_privs || filter.is_some() {
+            if no_new_privs {
+                trace!("no new privileges");
+            }
+            if filter.is_some() {
+                trace!("seccomp filter");
+            }
+            let restrict = move || {
+                // Stop setuid and file capabilities from granting privileges
+                // to the command once it is executed.
+                if no_new_privs {
+                    let result = unsafe { prctl(PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) };
+                    Errno::result(result).map_err(|_| ::std::io::Error::last_os_error())?;
+                }
+                if let Some(ref filter) = filter {
+                    filter.install()?;
+                }
+                Ok(())
+            };
+            unsafe { command.pre_exec(restrict) };
         }
+
+        Ok(mounted)
     }
-}
 
-/// Find a path for an executable.
-fn find_exec<P: AsRef<Path>>(executable: P) -> Option<PathBuf> {
-    env::var_os("PATH")
-        .as_ref()
-        .and_then(|s| s.to_str())
-        .and_then(|s| Some(s.split(':')))
-        .and_then(|p| find_first(p, executable))
-}
+    /// Print the configuration that would be applied to the container without
+    /// applying it.
+    pub fn dry_run(mut self) -> Failure {
+        let uses_root = self.uses_root();
+        self.add_subordinate_maps()?;
+        self.add_implied_namespaces();
+        self.validate_maps()?;
+        let private_root = self.private_root();
+        let reaps_orphans = self.reaps_orphans();
+        let private_uts = self.namespaces.contains(&Namespace::Uts);
+        let private_time = self.namespaces.contains(&Namespace::Time);
+        let private_net = self.namespaces.contains(&Namespace::Net);
+        let private_namespaces = self.private_namespaces();
+        let masked_paths = self.masked_paths();
 
-fn find_first<'p, I, P, E>(paths: I, executable: E) -> Option<PathBuf>
-where
-    I: Iterator<Item = P>,
-    P: AsRef<Path>,
-    E: AsRef<Path>,
-{
-    for prefix in paths {
-        let path = prefix.as_ref().join(executable.as_ref());
-        if path.exists() {
-            return Some(path)
+        let Config {
+            namespaces,
+            join,
+            user,
+            uid,
+            gid,
+            groups,
+            uid_map,
+            gid_map,
+            mounts,
+            cleanup_mounts,
+            ordered_mounts,
+            source_prefix,
+            readonly_paths,
+            capabilities,
+            no_new_privs,
+            seccomp,
+            cgroup,
+            rlimits,
+            cpu_affinity,
+            nice,
+            oom_score_adj,
+            sched_policy,
+            sysctls,
+            hostname,
+            domainname,
+            network,
+            resolv_conf,
+            write_hosts,
+            pid_file,
+            log_file,
+            state_file,
+            chroot_dir,
+            make_chroot_dir,
+            use_pivot_root,
+            working_dir,
+            umask,
+            die_with_parent,
+            env_clear,
+            env_passthrough,
+            env,
+            time_offsets,
+            ..
+        } = self;
+
+        if let Some(join) = join {
+            for (namespace, path) in join.namespaces(&namespaces)? {
+                println!("join: {} {}", namespace.name(), path.display());
+            }
+        }
+        let namespaces: Vec<_> = namespaces.iter()
+            .map(|&namespace| namespace.name())
+            .collect();
+        println!("unshare: {}", namespaces.join(", "));
+        for map in &uid_map {
+            println!("uid_map: {} {} {}", map.inside, map.outside, map.count);
+        }
+        for map in &gid_map {
+            println!("gid_map: {} {} {}", map.inside, map.outside, map.count);
+        }
+        if uses_root {
+            println!("start as: uid 0, gid 0");
+        }
+        if reaps_orphans {
+            println!("init: reap orphaned processes");
+        }
+        if let Some(pid_file) = pid_file {
+            println!("pid_file: {}", pid_file.display());
+        }
+        if let Some(log_file) = log_file {
+            println!("log_file: {}", log_file.display());
+        }
+        if let Some(state_file) = state_file {
+            println!("state_file: {}", state_file.display());
         }
-    }
 
-    None
-}
+        if let Some(ref hostname) = hostname {
+            println!("hostname: {}", hostname);
+        }
+        if let (Some(domainname), true) = (domainname, private_uts) {
+            println!("domainname: {}", domainname);
+        }
+        let loopback_up = network.as_ref().map_or(true, Network::loopback_up);
+        if private_net && uses_root && loopback_up {
+            println!("network: lo up");
+        }
+        if let Some(network) = network {
+            for line in network.describe() {
+                println!("{}", line);
+            }
+        }
+        for (key, value) in &sysctls {
+            sysctl::validate(key, &private_namespaces)?;
+            println!("sysctl {}: {}", key, value);
+        }
+        if private_time {
+            let offsets = time_offsets.unwrap_or_default();
+            println!("time offsets: monotonic {} boottime {}", offsets.monotonic, offsets.boottime);
+        }
+        if let Some(cgroup) = cgroup {
+            for line in cgroup.describe() {
+                println!("{}", line);
+            }
+        }
+        if !cpu_affinity.is_empty() {
+            cpu::validate(&cpu_affinity)?;
+            println!("cpu affinity: {}", cpu::list(&cpu_affinity));
+        }
+        if let Some(policy) = sched_policy {
+            println!("sched policy: {}", policy.name());
+        }
+        if let Some(nice) = nice {
+            println!("nice: {}", nice);
+        }
+        if let Some(adj) = oom_score_adj {
+            println!("oom_score_adj: {}", adj);
+        }
+        if private_root {
+            println!("{}", Mount::recursive_private("/").describe());
+        }
+        if let (Some(ref chroot_dir), true) = (&chroot_dir, make_chroot_dir) {
+            println!("make chroot_dir: {}", chroot_dir.display());
+        }
+        let mounts = prepare_mounts(mounts, ordered_mounts, &source_prefix)?;
+        let path_mounts = path_mounts(&masked_paths, &readonly_paths, chroot_dir.as_ref());
+        for mount in mounts.into_iter().chain(path_mounts) {
+            println!("{}", mount.describe());
+        }
+        if cleanup_mounts {
+            println!("cleanup mounts on exit");
+        }
+        if let Some(chroot_dir) = chroot_dir {
+            if use_pivot_root {
+                println!("pivot_root: {}", chroot_dir.display());
+            } else {
+                println!("chroot: {}", chroot_dir.display());
+            }
+            if !resolv_conf.is_empty() {
+                println!("write {}: nameservers {}", RESOLV_CONF, resolv_conf.join(", "));
+            }
+            if write_hosts {
+                println!("write {}", HOSTS);
+            }
+        }
+        if let Some(working_dir) = working_dir {
+            println!("working_dir: {}", working_dir.display());
+        }
+        if let Some(umask) = umask {
+            println!("umask: {:04o}", umask);
+        }
+        if env_clear || !env_passthrough.is_empty() {
+            println!("clear environment");
+        }
+        if !env_passthrough.is_empty() {
+            println!("pass through: {}", env_passthrough.join(", "));
+        }
+        for (key, value) in &env {
+            println!("env: {}={}", key, value);
+        }
+        for (name, limit) in &rlimits {
+            println!("rlimit {}: {}", name, limit.describe());
+        }
+        let dropped = capabilities.describe()?;
+        if !dropped.is_empty() {
+            println!("drop capabilities: {}", dropped.join(", "));
+        }
+        let retained = capabilities.describe_retained()?;
+        if !retained.is_empty() {
+            println!("keep capabilities: {}", retained.join(", "));
+        }
+        if no_new_privs {
+            println!("no new privileges");
+        }
+        if let Some(user) = user {
+            println!("user: {}", user);
+        }
+        if let Some(gid) = gid {
+            println!("gid: {}", gid);
+        }
+        if uses_root || gid.is_some() || !groups.is_empty() {
+            let groups: Vec<_> = groups.iter().map(gid_t::to_string).collect();
+            println!("groups: {}", groups.join(", "));
+        }
+        if let Some(uid) = uid {
+            println!("uid: {}", uid);
+        }
+        if die_with_parent {
+            println!("die with parent");
+        }
+        if let Some(seccomp) = seccomp {
+            for rule in seccomp.describe() {
+                println!("seccomp: {}", rule);
+            }
+        }
+
+        ok!()
+    }
+
+    /// Map root to the current user and map the subordinate ids of the user
+    /// from 1, in addition to any other maps.
+    fn add_subordinate_maps(&mut self) -> Failure {
+        if !self.auto_subid {
+            return ok!();
+        }
+
+        let (uid_start, uid_count) = subid::allocation(subid::SUBUID)?;
+        self.uid_map.push(UidMap { inside: 0, outside: uid_t::from(getuid()), count: 1 });
+        self.uid_map.push(UidMap { inside: 1, outside: uid_start, count: uid_count });
+
+        let (gid_start, gid_count) = subid::allocation(subid::SUBGID)?;
+        self.gid_map.push(GidMap { inside: 0, outside: gid_t::from(getgid()), count: 1 });
+        self.gid_map.push(GidMap { inside: 1, outside: gid_start, count: gid_count });
+
+        ok!()
+    }
+
+    /// Check that the maps can be written, which otherwise fails without
+    /// describing why.
+    fn validate_maps(&self) -> Failure {
+        let uid_map: Vec<_> = self.uid_map.iter().map(UidMap::range).collect();
+        let gid_map: Vec<_> = self.gid_map.iter().map(GidMap::range).collect();
+        validate_map("uid_map", &uid_map)?;
+        validate_map("gid_map", &gid_map)
+    }
+
+    /// Check the whole configuration before it is applied, collecting every
+    /// problem found rather than stopping at the first.
+    ///
+    /// Mount sources are checked as they are before any mounts are made, so a
+    /// source made by an earlier mount is reported as missing.
+    pub fn validate(&self) -> ::std::result::Result<(), Vec<Error>> {
+        let mut errors = Vec::new();
+        let mut check = |result: Failure| if let Err(err) = result {
+            errors.push(err);
+        };
+
+        let maps_ids = self.auto_subid || !self.uid_map.is_empty() || !self.gid_map.is_empty();
+        let private_user = maps_ids || self.namespaces.contains(&Namespace::User);
+        let private_pid = self.namespaces.contains(&Namespace::Pid);
+
+        if private_user && self.uses_root() && !self.maps_root() {
+            check(Err(ErrorKind::RootNotMapped.into()));
+        }
+        check(self.validate_maps());
+
+        if let Some(ref join) = self.join {
+            check(join.namespaces(&self.namespaces).map(drop));
+        }
+
+        if let Some(ref chroot_dir) = self.chroot_dir {
+            if !self.make_chroot_dir && !chroot_dir.is_dir() {
+                check(Err(ErrorKind::ChrootNotFound(chroot_dir.clone()).into()));
+            }
+            if let Some(ref working_dir) = self.working_dir {
+                check(resolve_working_dir(working_dir.clone(), true).map(drop));
+            }
+        }
+
+        for mount in &self.mounts {
+            let target = mount.target().to_owned();
+            let mount = match mount.clone().expand_env().chain_err(|| ErrorKind::SetMount(target)) {
+                Ok(mount) => mount,
+                Err(err) => {
+                    check(Err(err));
+                    continue;
+                },
+            };
+            // Within a user namespace, proc can only be mounted for a PID
+            // namespace of its own.
+            if let (Mount::Proc {..}, true, false) = (&mount, private_user, private_pid) {
+                check(Err(ErrorKind::ProcPidNamespace.into()));
+            }
+            for source in mount.sources() {
+                if !source.exists() {
+                    check(Err(ErrorKind::MountSourceNotFound(source.to_owned()).into()));
+                }
+            }
+        }
+
+        if !self.cpu_affinity.is_empty() {
+            check(cpu::validate(&self.cpu_affinity));
+        }
+        let private_namespaces = self.private_namespaces();
+        for key in self.sysctls.keys() {
+            check(sysctl::validate(key, &private_namespaces));
+        }
+        if let Some(ref cgroup) = self.cgroup {
+            for rule in cgroup.devices() {
+                check(rule.validate());
+            }
+        }
+
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+
+    /// Unshare the user namespace if any ids are mapped, as the maps would
+    /// otherwise be ignored.
+    fn add_implied_namespaces(&mut self) {
+        let maps_ids = !self.uid_map.is_empty() || !self.gid_map.is_empty();
+        if maps_ids && !self.namespaces.contains(&Namespace::User) {
+            self.namespaces.push(Namespace::User);
+        }
+        if self.network.is_some() && !self.namespaces.contains(&Namespace::Net) {
+            self.namespaces.push(Namespace::Net);
+        }
+    }
+
+    /// The command to run if none is given on the command line.
+    pub fn command(&self) -> &[String] {
+        &self.command
+    }
+
+    /// The hook to run in the container before the command, if any.
+    pub fn pre_start(&self) -> &[String] {
+        &self.pre_start
+    }
+
+    /// The hook to run outside of the container after the command exits, if
+    /// any.
+    pub fn post_stop(&self) -> &[String] {
+        &self.post_stop
+    }
+
+    /// Write the PID of the container to a file in place of any configured.
+    pub fn set_pid_file<P: AsRef<Path>>(&mut self, path: P) {
+        self.pid_file = Some(path.as_ref().to_owned());
+    }
+
+    /// Write the output of a detached container to a file in place of any
+    /// configured.
+    pub fn set_log_file<P: AsRef<Path>>(&mut self, path: P) {
+        self.log_file = Some(path.as_ref().to_owned());
+    }
+
+    /// The file the output of a detached container is written to.
+    pub fn log_file(&self) -> Option<&Path> {
+        self.log_file.as_ref().map(PathBuf::as_path)
+    }
+
+    /// Write the state of the container to a file in place of any configured.
+    pub fn set_state_file<P: AsRef<Path>>(&mut self, path: P) {
+        self.state_file = Some(path.as_ref().to_owned());
+    }
+
+    /// The file to write the state of the container to.
+    pub fn state_file(&self) -> Option<&Path> {
+        self.state_file.as_ref().map(PathBuf::as_path)
+    }
+
+    /// The OCI bundle the configuration was loaded from, if any.
+    pub fn bundle(&self) -> Option<&Path> {
+        self.bundle.as_ref().map(PathBuf::as_path)
+    }
+
+    /// The host name of the container, if one is set.
+    pub fn hostname(&self) -> Option<&str> {
+        self.hostname.as_ref().map(String::as_str)
+    }
+
+    /// Give the container its own pseudo-terminal, proxied to the terminal it
+    /// is started from.
+    pub fn set_tty(&mut self, tty: bool) {
+        self.tty = tty;
+    }
+
+    /// The container is given its own pseudo-terminal.
+    pub fn tty(&self) -> bool {
+        self.tty
+    }
+
+    /// The container fails if the post_stop hook fails.
+    pub fn post_stop_required(&self) -> bool {
+        self.post_stop_required
+    }
+
+    /// The file to write the PID of the container to.
+    pub fn pid_file(&self) -> Option<&Path> {
+        self.pid_file.as_ref().map(PathBuf::as_path)
+    }
+
+    /// Orphaned processes in the PID namespace should be waited for along with
+    /// the command.
+    pub fn reaps_orphans(&self) -> bool {
+        self.init && self.namespaces.contains(&Namespace::Pid)
+    }
+
+    /// All mounts should be made private after entering the mount namespace.
+    ///
+    /// This is only possible with privileges in the namespace, so it is left
+    /// as it is when root isn't mapped in a user namespace of its own.
+    fn private_root(&self) -> bool {
+        !self.propagate_mounts
+            && self.namespaces.contains(&Namespace::Mount)
+            && self.privileged()
+    }
+
+    /// The container has the privileges to configure itself, either having
+    /// no user namespace of its own or mapping root within it.
+    fn privileged(&self) -> bool {
+        let private_user = self.namespaces.contains(&Namespace::User)
+            || !self.uid_map.is_empty()
+            || !self.gid_map.is_empty();
+        !private_user || self.maps_root()
+    }
+
+    /// The paths to mask, being the defaults unless others are given.
+    ///
+    /// The defaults are only masked by default when the root is private, so
+    /// that the masks can never affect the host and there are the privileges
+    /// to make them.
+    fn masked_paths(&self) -> Vec<PathBuf> {
+        if !self.masked_paths.is_empty() {
+            self.masked_paths.clone()
+        } else if self.default_masks.unwrap_or_else(|| self.private_root()) {
+            DEFAULT_MASKED_PATHS.iter().map(PathBuf::from).collect()
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// The namespaces that are not shared with the host, being either unshared
+    /// or joined.
+    fn private_namespaces(&self) -> Vec<Namespace> {
+        let mut private: Vec<_> = Namespace::ALL.iter()
+            .cloned()
+            .filter(|namespace| {
+                self.namespaces.contains(namespace)
+                    || self.join.as_ref().map_or(false, |join| join.includes(*namespace))
+            })
+            .collect();
+        if self.network.is_some() && !private.contains(&Namespace::Net) {
+            private.push(Namespace::Net);
+        }
+        private
+    }
+
+    /// Print the configuration in the same format that it is loaded from.
+    pub fn dump(self) -> Failure {
+        // Converting to a value first ensures that tables are written after
+        // all other values.
+        let value = toml::Value::try_from(self)?;
+        print!("{}", toml::to_string(&value)?);
+        ok!()
+    }
+
+    /// The inner program needs to start as root.
+    ///
+    /// This is either to have the privileges to configure the container, or
+    /// because root is mapped in the user namespace and so is the user the
+    /// command expects to start as.
+    fn uses_root(&self) -> bool {
+        self.hostname.is_some()
+            || self.domainname.is_some()
+            || self.network.is_some()
+            || self.user.is_some()
+            || self.chroot_dir.is_some()
+            || self.mounts.len() > 0
+            || !self.masked_paths().is_empty()
+            || self.readonly_paths.len() > 0
+            || self.maps_root()
+    }
+
+    /// Whether the environment of the command is cleared, and the variables
+    /// then set in it, given how to look up the variables of the host.
+    ///
+    /// Only the variables passed through are kept from the environment, unless
+    /// replaced by those that are set.
+    fn environment<F>(&self, host: F) -> (bool, BTreeMap<OsString, OsString>)
+    where
+        F: Fn(&str) -> Option<OsString>,
+    {
+        let clear = self.env_clear || !self.env_passthrough.is_empty();
+        let mut variables = BTreeMap::new();
+        for key in &self.env_passthrough {
+            if let Some(value) = host(key) {
+                variables.insert(OsString::from(key), value);
+            }
+        }
+        for (key, value) in &self.env {
+            variables.insert(OsString::from(key), OsString::from(value));
+        }
+        (clear, variables)
+    }
+
+    /// Root inside the user namespace is mapped to a user outside of it.
+    fn maps_root(&self) -> bool {
+        self.auto_subid
+            || self.uid_map.iter().any(|map| map.inside == 0)
+    }
+
+    /// The user and group the inner program starts as, if not those running
+    /// the container.
+    fn start_ids(&self) -> (Option<uid_t>, Option<gid_t>) {
+        if self.uses_root() {
+            (Some(0), Some(0))
+        } else {
+            (self.uid, self.gid)
+        }
+    }
+}
+
+/// The directory to start the command in.
+///
+/// Within a chroot, a relative directory is from the new root, and must not
+/// leave it.
+fn resolve_working_dir(working_dir: PathBuf, chroot: bool) -> Result<PathBuf> {
+    if chroot && working_dir.is_relative() {
+        within_root(&working_dir)
+            .ok_or_else(|| ErrorKind::WorkingDirOutsideRoot(working_dir).into())
+    } else {
+        Ok(working_dir)
+    }
+}
+
+/// The contents of the `uid_map` or `gid_map` file of a process for the
+/// ranges of a map, as `(inside, outside, count)`.
+fn map_contents(map: &[(u32, u32, u32)]) -> String {
+    map.iter()
+        .map(|&(inside, outside, count)| format!("{} {} {}\n", inside, outside, count))
+        .collect()
+}
+
+/// Write the id maps of a process directly, denying setgroups before writing
+/// the gid map.
+fn write_id_maps(pid: pid_t, uid_map: &str, gid_map: &str) -> Failure {
+    let proc_dir = PathBuf::from(format!("/proc/{}", pid));
+    let write_file = |name: &str, contents: &str| {
+        let path = proc_dir.join(name);
+        write(&path, contents).chain_err(|| ErrorKind::WriteIdMap(path))
+    };
+
+    if !uid_map.is_empty() {
+        write_file("uid_map", uid_map)?;
+    }
+    if !gid_map.is_empty() {
+        write_file("setgroups", "deny")?;
+        write_file("gid_map", gid_map)?;
+    }
+    ok!()
+}
+
+/// Setting supplementary groups has been denied in the user namespace.
+fn setgroups_denied() -> bool {
+    read_to_string("/proc/self/setgroups")
+        .map(|setgroups| setgroups.trim() == "deny")
+        .unwrap_or(false)
+}
+
+/// Check that the ranges of a map, as `(inside, outside, count)`, are not
+/// empty, do not overflow, and do not overlap inside or outside.
+fn validate_map(name: &'static str, map: &[(u32, u32, u32)]) -> Failure {
+    let invalid = |reason: String| ErrorKind::InvalidIdMap(name, reason);
+
+    for &(inside, outside, count) in map {
+        ensure!(count > 0, invalid(format!("the range from {} inside is empty", inside)));
+        ensure!(
+            inside.checked_add(count).is_some(),
+            invalid(format!("{} ids from {} inside exceeds the largest id", count, inside))
+        );
+        ensure!(
+            outside.checked_add(count).is_some(),
+            invalid(format!("{} ids from {} outside exceeds the largest id", count, outside))
+        );
+    }
+
+    let overlaps = |a: u32, b: u32, count_a: u32, count_b: u32| a < b + count_b && b < a + count_a;
+    for (i, &(inside, outside, count)) in map.iter().enumerate() {
+        for &(other_inside, other_outside, other_count) in &map[i + 1..] {
+            ensure!(
+                !overlaps(inside, other_inside, count, other_count),
+                invalid(format!("the ranges from {} and {} overlap inside", inside, other_inside))
+            );
+            ensure!(
+                !overlaps(outside, other_outside, count, other_count),
+                invalid(format!("the ranges from {} and {} overlap outside", outside, other_outside))
+            );
+        }
+    }
+
+    ok!()
+}
+
+/// Resolve a relative path from the root, or `None` if it would leave the root.
+fn within_root(path: &Path) -> Option<PathBuf> {
+    let mut resolved = PathBuf::from("/");
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => resolved.push(name),
+            Component::ParentDir => if !resolved.pop() {
+                return None;
+            },
+            _ => {},
+        }
+    }
+    Some(resolved)
+}
+
+/// Write a file in `/etc`, creating the directory if needed.
+fn write_etc_file(path: &str, contents: &str) -> Failure {
+    create_dir_all("/etc")
+        .and_then(|_| write(path, contents))
+        .chain_err(|| ErrorKind::WriteEtcFile(PathBuf::from(path)))
+}
+
+/// The contents of `/etc/resolv.conf` using the given nameservers.
+fn resolv_conf_contents(nameservers: &[String]) -> String {
+    nameservers.iter()
+        .map(|nameserver| format!("nameserver {}\n", nameserver))
+        .collect()
+}
+
+/// The contents of `/etc/hosts`, resolving the host name to the loopback
+/// address.
+fn hosts_contents(hostname: Option<&String>) -> String {
+    let names = match hostname {
+        Some(hostname) => format!("localhost {}", hostname),
+        None => "localhost".to_owned(),
+    };
+    format!("127.0.0.1\t{}\n::1\t{}\n", names, names)
+}
+
+/// Set the NIS domain name, see setdomainname(2).
+fn setdomainname(name: &str) -> Failure {
+    let result = unsafe {
+        libc::setdomainname(name.as_ptr() as *const c_char, name.len())
+    };
+    Errno::result(result)?;
+    ok!()
+}
+
+/// Change the root directory using `pivot_root(2)`.
+///
+/// Unlike a chroot, the old root is detached entirely so that it can't be
+/// reached from within the new root.
+fn enter_pivot_root(new_root: &Path) -> Failure {
+    // The new root must be a mount point.
+    let none: Option<&Path> = None;
+    let flags = MsFlags::MS_BIND | MsFlags::MS_REC;
+    retry_interrupted(|| mount(Some(new_root), new_root, none, flags, none))?;
+    env::set_current_dir(new_root)?;
+
+    // Pivoting onto the current directory stacks the old root on top of the
+    // new root, which avoids needing a directory in the new root to hold it.
+    pivot_root(".", ".")?;
+    retry_interrupted(|| umount2(".", MntFlags::MNT_DETACH))?;
+    env::set_current_dir("/")?;
+
+    ok!()
+}
+
+/// Expand, check, and order the mounts to be made.
+fn prepare_mounts(
+    mounts: Vec<Mount>,
+    ordered: bool,
+    source_prefix: &Option<PathBuf>,
+) -> Result<Vec<Mount>> {
+    let mut mounts = mounts.into_iter()
+        .map(|mount| {
+            let target = mount.target().to_owned();
+            mount.expand_env()
+                .and_then(|mount| match source_prefix {
+                    Some(prefix) => mount.confine_source(prefix),
+                    None => Ok(mount),
+                })
+                .chain_err(|| ErrorKind::SetMount(target))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    // Mount parents before any mounts nested within them, keeping the order of
+    // mounts at the same depth.
+    if !ordered {
+        mounts.sort_by_key(|mount| mount.target().components().count());
+    }
+
+    Ok(mounts)
+}
+
+/// Convert a mount of an OCI bundle to a mount within the root.
+fn oci_mount(mount: oci::Mount, root: &Path) -> Mount {
+    let oci::Mount { destination, filesystem_type, source, options } = mount;
+    let target = root.join(destination.strip_prefix("/").unwrap_or(&destination));
+
+    let mut bind = None;
+    let mut remaining = Vec::new();
+    for option in &options {
+        match option.as_str() {
+            "bind" => bind = Some(false),
+            "rbind" => bind = Some(true),
+            "rw" => {},
+            _ => remaining.push(option.as_str()),
+        }
+    }
+    // Options that are not flags are passed on to the filesystem.
+    let (flags, data) = MountFlags::from_options(&remaining.join(","));
+
+    let filesystem_type = filesystem_type.unwrap_or_else(|| "none".to_owned());
+    let source = source.unwrap_or_else(|| PathBuf::from(&filesystem_type));
+    let mount = match (bind, data) {
+        (Some(false), _) => Mount::bind(source, target),
+        (Some(true), _) => Mount::recursive_bind(source, target),
+        (None, None) => Mount::new(source, target, PathBuf::from(filesystem_type)),
+        (None, Some(data)) => Mount::new(source, target, PathBuf::from(filesystem_type)).data(data),
+    };
+
+    mount.flags(flags).make_target_dir()
+}
+
+/// Mounts that hide or make read-only paths within the container.
+///
+/// The paths are within the chroot, if there is one. Any paths that don't exist
+/// are skipped.
+fn path_mounts(
+    masked: &[PathBuf],
+    read_only: &[PathBuf],
+    root: Option<&PathBuf>,
+) -> Vec<Mount> {
+    let resolve = |path: &PathBuf| match root {
+        Some(root) => root.join(path.strip_prefix("/").unwrap_or(path)),
+        None => path.clone(),
+    };
+
+    // Directories are hidden by an empty tmpfs, and files by the null device.
+    let masked = masked.iter()
+        .map(&resolve)
+        .filter_map(|path| {
+            let metadata = metadata(&path).ok()?;
+            if metadata.is_dir() {
+                Some(Mount::tmpfs(&path).read_only())
+            } else {
+                Some(Mount::bind(Path::new("/dev/null"), &path))
+            }
+        });
+
+    let read_only = read_only.iter()
+        .map(&resolve)
+        .filter(|path| path.exists())
+        .map(|path| Mount::recursive_bind(&path, &path).recursive_read_only());
+
+    masked.chain(read_only).collect()
+}
+
+/// Serialisable namespaces.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Namespace {
+    Mount,
+    Uts,
+    Ipc,
+    User,
+    Pid,
+    Net,
+    Cgroup,
+    Time,
+}
+
+impl Namespace {
+    /// Every type of namespace that can be joined.
+    const ALL: &'static [Namespace] = &[
+        Namespace::Mount,
+        Namespace::Uts,
+        Namespace::Ipc,
+        Namespace::User,
+        Namespace::Pid,
+        Namespace::Net,
+        Namespace::Cgroup,
+    ];
+
+    /// Every type of namespace the kernel supports.
+    fn supported() -> Vec<Namespace> {
+        Namespace::ALL.iter()
+            .chain(&[Namespace::Time])
+            .cloned()
+            .filter(|namespace| Path::new("/proc/self/ns").join(namespace.proc_name()).exists())
+            .collect()
+    }
+
+    /// The name of the namespace.
+    pub fn name(self) -> String {
+        format!("{:?}", self).to_lowercase()
+    }
+
+    /// The name of the namespace file in `/proc/<pid>/ns`.
+    fn proc_name(self) -> &'static str {
+        match self {
+            Namespace::Mount  => "mnt",
+            Namespace::Uts    => "uts",
+            Namespace::Ipc    => "ipc",
+            Namespace::User   => "user",
+            Namespace::Pid    => "pid",
+            Namespace::Net    => "net",
+            Namespace::Cgroup => "cgroup",
+            Namespace::Time   => "time",
+        }
+    }
+
+    /// The namespace to unshare when starting the container.
+    ///
+    /// The time namespace is instead unshared from within the container, as
+    /// only children of the process that unshares it enter it.
+    fn unshare_namespace(self) -> Option<unshare::Namespace> {
+        match self {
+            Namespace::Mount  => Some(unshare::Namespace::Mount),
+            Namespace::Uts    => Some(unshare::Namespace::Uts),
+            Namespace::Ipc    => Some(unshare::Namespace::Ipc),
+            Namespace::User   => Some(unshare::Namespace::User),
+            Namespace::Pid    => Some(unshare::Namespace::Pid),
+            Namespace::Net    => Some(unshare::Namespace::Net),
+            Namespace::Cgroup => Some(unshare::Namespace::Cgroup),
+            Namespace::Time   => None,
+        }
+    }
+}
+
+impl FromStr for Namespace {
+    type Err = Error;
+
+    /// Parse a namespace by its name or the name of its namespace file.
+    fn from_str(name: &str) -> Result<Namespace> {
+        for &namespace in Namespace::ALL.iter().chain(&[Namespace::Time]) {
+            if name == namespace.name() || name == namespace.proc_name() {
+                return Ok(namespace);
+            }
+        }
+        bail!(ErrorKind::UnknownNamespace(name.to_owned()))
+    }
+}
+
+/// Offsets of the clocks within a time namespace, in seconds.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct TimeOffsets {
+    #[serde(default)]
+    monotonic: i64,
+    #[serde(default)]
+    boottime: i64,
+}
+
+/// Unshare the time namespace for any children of this process and set the
+/// offsets of its clocks.
+fn unshare_time(offsets: &TimeOffsets) -> Failure {
+    let result = unsafe { libc::unshare(libc::CLONE_NEWTIME) };
+    Errno::result(result)
+        .map_err(Error::from)
+        .chain_err(|| ErrorKind::UnshareTime)?;
+
+    // The offsets can only be written before any process enters the namespace.
+    let offsets = format!(
+        "monotonic {} 0\nboottime {} 0\n",
+        offsets.monotonic,
+        offsets.boottime,
+    );
+    write("/proc/self/timens_offsets", offsets).chain_err(|| ErrorKind::SetTimeOffsets)
+}
+
+/// Existing namespaces to enter in place of unsharing them.
+#[derive(Debug, Serialize, Deserialize)]
+struct Join {
+    /// A process to join every namespace of.
+    pid: Option<pid_t>,
+    /// Namespaces to join by the path of a namespace file.
+    #[serde(default)]
+    #[serde(rename = "path")]
+    paths: Vec<JoinPath>,
+}
+
+/// A namespace to join by the path of its namespace file.
+#[derive(Debug, Serialize, Deserialize)]
+struct JoinPath {
+    namespace: Namespace,
+    path: PathBuf,
+}
+
+impl Join {
+    /// Whether a namespace is joined, either by path or as a namespace of the
+    /// process.
+    fn includes(&self, namespace: Namespace) -> bool {
+        self.pid.is_some() || self.paths.iter().any(|path| path.namespace == namespace)
+    }
+
+    /// The namespace files to join.
+    ///
+    /// A namespace joined by its path can't also be unshared. The namespaces of
+    /// a process are only joined if they are not unshared, joined by path, or
+    /// already the namespace of this process.
+    fn namespaces(&self, unshared: &[Namespace]) -> Result<Vec<(Namespace, PathBuf)>> {
+        let mut joined = Vec::new();
+        for &JoinPath { namespace, ref path } in &self.paths {
+            ensure!(!unshared.contains(&namespace), ErrorKind::JoinUnshared(namespace.name()));
+            joined.push((namespace, path.clone()));
+        }
+
+        if let Some(pid) = self.pid {
+            for &namespace in Namespace::ALL {
+                if unshared.contains(&namespace) || joined.iter().any(|&(n, _)| n == namespace) {
+                    continue;
+                }
+
+                let path = PathBuf::from(format!("/proc/{}/ns/{}", pid, namespace.proc_name()));
+                let current = Path::new("/proc/self/ns").join(namespace.proc_name());
+                let target = metadata(&path).chain_err(|| ErrorKind::JoinNamespace(path.clone()))?;
+                match metadata(current) {
+                    Ok(ref current) if current.ino() == target.ino() => continue,
+                    _ => joined.push((namespace, path)),
+                }
+            }
+        }
+
+        Ok(joined)
+    }
+}
+
+/// Enter the namespaces of a running process, either every namespace it has or
+/// only those given.
+///
+/// Every namespace file is opened before any is entered, as entering the mount
+/// namespace changes what `/proc` refers to. The user namespace is entered
+/// first so that its capabilities apply to entering the others. As when
+/// unsharing, only children enter the PID and time namespaces.
+pub fn enter_namespaces(pid: pid_t, only: &[Namespace]) -> Failure {
+    let proc_dir = PathBuf::from(format!("/proc/{}", pid));
+    metadata(&proc_dir).chain_err(|| ErrorKind::NoSuchProcess(pid))?;
+
+    let namespaces = if only.is_empty() { Namespace::ALL } else { only };
+    let mut files = Vec::new();
+    for &namespace in namespaces {
+        let path = proc_dir.join("ns").join(namespace.proc_name());
+        let current = Path::new("/proc/self/ns").join(namespace.proc_name());
+        // Namespaces the kernel does not support are skipped unless asked for.
+        let target = match metadata(&path) {
+            Ok(target) => target,
+            Err(_) if only.is_empty() => continue,
+            Err(err) => return Err(err).chain_err(|| ErrorKind::JoinNamespace(path)),
+        };
+        match metadata(current) {
+            Ok(ref current) if current.ino() == target.ino() => continue,
+            _ => {},
+        }
+        let file = File::open(&path).chain_err(|| ErrorKind::JoinNamespace(path.clone()))?;
+        files.push((namespace, path, file));
+    }
+
+    files.sort_by_key(|&(namespace, _, _)| namespace != Namespace::User);
+    for (namespace, path, file) in files {
+        trace!("enter: {} {}", namespace.name(), path.display());
+        setns(file.as_raw_fd(), CloneFlags::empty())
+            .chain_err(|| ErrorKind::JoinNamespace(path))?;
+    }
+
+    ok!()
+}
+
+/// The forms the namespaces to unshare can be written in.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum NamespaceSet {
+    /// Written as `all` for every namespace the kernel supports.
+    All(AllNamespaces),
+    /// Every namespace the kernel supports except those listed.
+    Except { except: Vec<Namespace> },
+    List(Vec<Namespace>),
+}
+
+/// Written as `all` in place of a list.
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum AllNamespaces {
+    All,
+}
+
+/// Deserialize the namespaces to unshare, expanding `all` and `except`.
+fn namespace_set<'de, D>(deserializer: D) -> ::std::result::Result<Vec<Namespace>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(match NamespaceSet::deserialize(deserializer)? {
+        NamespaceSet::All(_) => Namespace::supported(),
+        NamespaceSet::Except { except } => Namespace::supported()
+            .into_iter()
+            .filter(|namespace| !except.contains(namespace))
+            .collect(),
+        // A namespace listed more than once is only unshared once.
+        NamespaceSet::List(list) => {
+            let mut namespaces = Vec::new();
+            for namespace in list {
+                if !namespaces.contains(&namespace) {
+                    namespaces.push(namespace);
+                }
+            }
+            namespaces
+        },
+    })
+}
+
+/// Either a single value or a list of values.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OneOrMany<T> {
+    One(T),
+    Many(Vec<T>),
+}
+
+/// Deserialize a list that may also be given as a single value.
+fn one_or_many<'de, D, T>(deserializer: D) -> ::std::result::Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(value) => vec![value],
+        OneOrMany::Many(values) => values,
+    })
+}
+
+/// The forms an id map can be written in.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum IdMapForm {
+    /// Written as `outside:inside` for a single id, where the outside id may be
+    /// `current` for the id of the user starting the container.
+    Short(String),
+    Full {
+        inside: u32,
+        outside: u32,
+        #[serde(default = "default_count")]
+        count: u32,
+    },
+}
+
+fn default_count() -> u32 {
+    1
+}
+
+impl IdMapForm {
+    /// The inside id, outside id, and count of the map.
+    fn resolve(self, current: u32) -> ::std::result::Result<(u32, u32, u32), String> {
+        match self {
+            IdMapForm::Full { inside, outside, count } => Ok((inside, outside, count)),
+            IdMapForm::Short(map) => {
+                let invalid = || format!("invalid id map \"{}\", expected \"outside:inside\"", map);
+                let mut ids = map.splitn(2, ':');
+                let outside = match ids.next() {
+                    Some("current") => current,
+                    Some(outside) => outside.parse().map_err(|_| invalid())?,
+                    None => return Err(invalid()),
+                };
+                let inside = ids.next()
+                    .and_then(|inside| inside.parse().ok())
+                    .ok_or_else(invalid)?;
+                Ok((inside, outside, 1))
+            },
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(try_from = "IdMapForm")]
+struct UidMap {
+    inside: uid_t,
+    outside: uid_t,
+    count: uid_t,
+}
+
+impl UidMap {
+    /// The range mapped, as `(inside, outside, count)`.
+    fn range(&self) -> (uid_t, uid_t, uid_t) {
+        (self.inside, self.outside, self.count)
+    }
+}
+
+impl TryFrom<IdMapForm> for UidMap {
+    type Error = String;
+
+    fn try_from(form: IdMapForm) -> ::std::result::Result<UidMap, String> {
+        let (inside, outside, count) = form.resolve(uid_t::from(getuid()))?;
+        Ok(UidMap { inside, outside, count })
+    }
+}
+
+impl Into<unshare::UidMap> for UidMap {
+    fn into(self) -> unshare::UidMap {
+        let UidMap {inside, outside, count} = self;
+        unshare::UidMap {
+            inside_uid: inside,
+            outside_uid: outside,
+            count
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(try_from = "IdMapForm")]
+struct GidMap {
+    inside: gid_t,
+    outside: gid_t,
+    count: gid_t,
+}
+
+impl GidMap {
+    /// The range mapped, as `(inside, outside, count)`.
+    fn range(&self) -> (gid_t, gid_t, gid_t) {
+        (self.inside, self.outside, self.count)
+    }
+}
+
+impl TryFrom<IdMapForm> for GidMap {
+    type Error = String;
+
+    fn try_from(form: IdMapForm) -> ::std::result::Result<GidMap, String> {
+        let (inside, outside, count) = form.resolve(gid_t::from(getgid()))?;
+        Ok(GidMap { inside, outside, count })
+    }
+}
+
+impl Into<unshare::GidMap> for GidMap {
+    fn into(self) -> unshare::GidMap {
+        let GidMap {inside, outside, count} = self;
+        unshare::GidMap {
+            inside_gid: inside,
+            outside_gid: outside,
+            count
+        }
+    }
+}
+
+/// Find an id map helper in the directories of a value of `PATH`, which need
+/// not be valid UTF-8.
+fn find_id_map_helper(paths: Option<&OsStr>, name: &str) -> Result<PathBuf> {
+    find_in_path(paths, name).ok_or_else(|| ErrorKind::MissingIdMapHelper(name.to_owned()).into())
+}
+
+/// Find the first executable file of a name in the directories of a value of
+/// `PATH`.
+///
+/// Empty and relative directories are skipped rather than searched from the
+/// current directory, as the executables found are run with privileges.
+fn find_in_path<P: AsRef<Path>>(paths: Option<&OsStr>, executable: P) -> Option<PathBuf> {
+    env::split_paths(paths?)
+        .filter(|prefix| prefix.is_absolute())
+        .map(|prefix| prefix.join(executable.as_ref()))
+        .find(|path| {
+            metadata(path)
+                .map(|metadata| metadata.is_file() && metadata.mode() & 0o111 != 0)
+                .unwrap_or(false)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(config: &str) -> Config {
+        toml::from_str(config).unwrap()
+    }
+
+    #[test]
+    fn private_root_without_user_namespace() {
+        let config = parse(r#"namespaces = ["mount"]"#);
+        assert!(config.private_root());
+    }
+
+    #[test]
+    fn private_root_kept_when_propagating() {
+        let config = parse(r#"
+            namespaces = ["mount"]
+            propagate_mounts = true
+        "#);
+        assert!(!config.private_root());
+    }
+
+    #[test]
+    fn private_root_needs_root_in_user_namespace() {
+        // A non-root mapping doesn't start as root, so has no privileges to
+        // change the propagation.
+        let unprivileged = parse(r#"
+            namespaces = ["user", "mount"]
+            uid_map = { inside = 1000, outside = 1000 }
+        "#);
+        assert!(!unprivileged.uses_root());
+        assert!(!unprivileged.private_root());
+
+        let privileged = parse(r#"
+            namespaces = ["user", "mount"]
+            uid_map = { inside = 0, outside = 1000 }
+        "#);
+        assert!(privileged.uses_root());
+        assert!(privileged.private_root());
+    }
+
+    #[test]
+    fn masked_paths_default_with_private_root() {
+        let config = parse(r#"namespaces = ["mount"]"#);
+        let defaults: Vec<_> = DEFAULT_MASKED_PATHS.iter().map(PathBuf::from).collect();
+        assert_eq!(config.masked_paths(), defaults);
+        assert!(config.uses_root());
+
+        let config = parse(r#"namespaces = ["pid"]"#);
+        assert!(config.masked_paths().is_empty());
+        assert!(!config.uses_root());
+    }
+
+    #[test]
+    fn masked_paths_replace_defaults() {
+        let config = parse(r#"
+            namespaces = ["mount"]
+            masked_paths = ["/proc/kcore"]
+        "#);
+        assert_eq!(config.masked_paths(), vec![PathBuf::from("/proc/kcore")]);
+    }
+
+    #[test]
+    fn masked_paths_without_default_masks() {
+        let config = parse(r#"
+            namespaces = ["mount"]
+            default_masks = false
+        "#);
+        assert!(config.masked_paths().is_empty());
+        assert!(!config.uses_root());
+    }
+
+    #[test]
+    fn masked_paths_default_without_root_mapped() {
+        // The masks couldn't be made, so are not made by default.
+        let config = parse(r#"
+            namespaces = ["user", "mount"]
+            uid_map = { inside = 1000, outside = 1000 }
+        "#);
+        assert!(config.masked_paths().is_empty());
+        assert!(!config.uses_root());
+    }
+
+    #[test]
+    fn private_root_needs_a_map_in_user_namespace() {
+        let config = parse(r#"namespaces = ["user", "mount"]"#);
+        assert!(!config.uses_root());
+        assert!(!config.private_root());
+    }
+
+    /// The targets of mounts once prepared.
+    fn mount_order(targets: &[&str], ordered: bool) -> Vec<PathBuf> {
+        let mounts = targets.iter()
+            .map(|target| Mount::new("tmpfs", target, "tmpfs"))
+            .collect();
+        prepare_mounts(mounts, ordered, &None).unwrap()
+            .iter()
+            .map(|mount| mount.target().to_owned())
+            .collect()
+    }
+
+    #[test]
+    fn mounts_parents_first() {
+        let targets = ["/a/b/c", "/a", "/x/y", "/a/b", "/z"];
+        let expected: Vec<_> = ["/a", "/z", "/x/y", "/a/b", "/a/b/c"].iter().map(PathBuf::from).collect();
+        assert_eq!(mount_order(&targets, false), expected);
+    }
+
+    #[test]
+    fn mounts_ordered() {
+        let targets = ["/a/b/c", "/a", "/x/y", "/a/b", "/z"];
+        let expected: Vec<_> = targets.iter().map(PathBuf::from).collect();
+        assert_eq!(mount_order(&targets, true), expected);
+    }
+
+    #[test]
+    fn start_ids_keep_uid_and_gid_apart() {
+        let config = parse("uid = 1000\ngid = 100");
+        assert_eq!(config.start_ids(), (Some(1000), Some(100)));
+    }
+
+    #[test]
+    fn start_ids_as_root() {
+        let config = parse("uid = 1000\ngid = 100\nhostname = \"box\"");
+        assert_eq!(config.start_ids(), (Some(0), Some(0)));
+    }
+
+    #[test]
+    fn map_short_form() {
+        let config = parse(r#"
+            uid_map = ["100000:0"]
+            gid_map = ["current:0", "100001:1"]
+        "#);
+        let uid_map = &config.uid_map[0];
+        assert_eq!((uid_map.inside, uid_map.outside, uid_map.count), (0, 100000, 1));
+        let gid_map = &config.gid_map[0];
+        assert_eq!((gid_map.inside, gid_map.outside, gid_map.count), (0, gid_t::from(getgid()), 1));
+        let gid_map = &config.gid_map[1];
+        assert_eq!((gid_map.inside, gid_map.outside, gid_map.count), (1, 100001, 1));
+    }
+
+    #[test]
+    fn map_full_form() {
+        let config = parse(r#"
+            [[uid_map]]
+            inside = 1
+            outside = 100000
+            count = 65536
+
+            [[uid_map]]
+            inside = 0
+            outside = 1000
+        "#);
+        let uid_map = &config.uid_map[0];
+        assert_eq!((uid_map.inside, uid_map.outside, uid_map.count), (1, 100000, 65536));
+        let uid_map = &config.uid_map[1];
+        assert_eq!((uid_map.inside, uid_map.outside, uid_map.count), (0, 1000, 1));
+    }
+
+    #[test]
+    fn map_invalid_short_form() {
+        assert!(toml::from_str::<Config>(r#"uid_map = ["1000"]"#).is_err());
+        assert!(toml::from_str::<Config>(r#"uid_map = ["1000:root"]"#).is_err());
+        assert!(toml::from_str::<Config>(r#"uid_map = ["user:0"]"#).is_err());
+    }
+
+    #[test]
+    fn user_namespace_implied_by_maps() {
+        let mut config = parse(r#"
+            namespaces = ["mount"]
+            gid_map = ["current:0"]
+        "#);
+        config.add_implied_namespaces();
+        assert_eq!(config.namespaces, vec![Namespace::Mount, Namespace::User]);
+    }
+
+    #[test]
+    fn user_namespace_not_implied_twice() {
+        let mut config = parse(r#"
+            namespaces = ["user"]
+            uid_map = ["current:0"]
+        "#);
+        config.add_implied_namespaces();
+        assert_eq!(config.namespaces, vec![Namespace::User]);
+    }
+
+    #[test]
+    fn user_namespace_not_implied_without_maps() {
+        let mut config = parse(r#"namespaces = ["mount"]"#);
+        config.add_implied_namespaces();
+        assert_eq!(config.namespaces, vec![Namespace::Mount]);
+    }
+
+    #[test]
+    fn maps_root() {
+        assert!(parse(r#"uid_map = ["current:0"]"#).maps_root());
+        assert!(parse("auto_subid = true").maps_root());
+        assert!(!parse(r#"uid_map = ["current:1000"]"#).maps_root());
+        // Only the uid map decides the user started as.
+        assert!(!parse(r#"gid_map = ["current:0"]"#).maps_root());
+        assert!(!parse("").maps_root());
+    }
+
+    #[test]
+    fn uses_root_for_maps() {
+        assert!(parse(r#"uid_map = ["current:0"]"#).uses_root());
+        assert!(!parse(r#"uid_map = ["current:1000"]"#).uses_root());
+    }
+
+    #[test]
+    fn uses_root_for_configuration() {
+        let configs = [
+            r#"hostname = "box""#,
+            r#"domainname = "example.com""#,
+            r#"chroot_dir = "/srv/root""#,
+            r#"readonly_paths = ["/proc/sys"]"#,
+            r#"masked_paths = ["/proc/kcore"]"#,
+            r#"[[mount]]
+               option = "tmpfs"
+               target = "/tmp""#,
+        ];
+        for config in &configs {
+            assert!(parse(config).uses_root(), "{}", config);
+        }
+    }
+
+    #[test]
+    fn uses_root_for_namespaces() {
+        // Only the mount namespace needs root, to mask paths within it.
+        assert!(parse(r#"namespaces = ["mount"]"#).uses_root());
+        assert!(!parse(r#"namespaces = ["pid", "ipc", "uts", "cgroup"]"#).uses_root());
+        assert!(!parse("").uses_root());
+    }
+
+    #[test]
+    fn start_ids_with_mount_namespace() {
+        // The default masks need root, so the configured ids are only used
+        // once the namespace is set up.
+        let config = parse(r#"
+            namespaces = ["mount"]
+            uid = 1000
+            gid = 100
+        "#);
+        assert_eq!(config.start_ids(), (Some(0), Some(0)));
+
+        let config = parse(r#"
+            namespaces = ["mount"]
+            uid = 1000
+            gid = 100
+            default_masks = false
+        "#);
+        assert_eq!(config.start_ids(), (Some(1000), Some(100)));
+    }
+
+    /// The environment of a configuration, with the given host variables.
+    fn environment(config: &str, host: &[(&str, &str)]) -> (bool, Vec<(String, String)>) {
+        let host: BTreeMap<_, _> = host.iter().cloned().collect();
+        let (clear, variables) = parse(config).environment(|key| host.get(key).map(OsString::from));
+        let variables = variables.into_iter()
+            .map(|(key, value)| (key.into_string().unwrap(), value.into_string().unwrap()))
+            .collect();
+        (clear, variables)
+    }
+
+    fn variables(variables: &[(&str, &str)]) -> Vec<(String, String)> {
+        variables.iter().map(|&(key, value)| (key.to_owned(), value.to_owned())).collect()
+    }
+
+    #[test]
+    fn environment_configured() {
+        let config = r#"env = { LANG = "C.UTF-8", HOME = "/root" }"#;
+        let (clear, env) = environment(config, &[("HOME", "/home/user"), ("TERM", "xterm")]);
+        assert!(!clear);
+        assert_eq!(env, variables(&[("HOME", "/root"), ("LANG", "C.UTF-8")]));
+    }
+
+    #[test]
+    fn environment_cleared() {
+        let config = r#"
+            env_clear = true
+            env = { PATH = "/bin" }
+        "#;
+        let (clear, env) = environment(config, &[("HOME", "/home/user")]);
+        assert!(clear);
+        assert_eq!(env, variables(&[("PATH", "/bin")]));
+
+        let (clear, env) = environment("", &[("HOME", "/home/user")]);
+        assert!(!clear);
+        assert!(env.is_empty());
+    }
+
+    #[test]
+    fn environment_passthrough() {
+        let config = r#"env_passthrough = ["TERM", "LANG", "MISSING"]"#;
+        let host = [("TERM", "xterm"), ("LANG", "C.UTF-8"), ("HOME", "/home/user"), ("SECRET", "1")];
+        let (clear, env) = environment(config, &host);
+        assert!(clear);
+        assert_eq!(env, variables(&[("LANG", "C.UTF-8"), ("TERM", "xterm")]));
+    }
+
+    #[test]
+    fn environment_set_over_passthrough() {
+        let config = r#"
+            env_passthrough = ["TERM", "LANG"]
+            env = { LANG = "C" }
+        "#;
+        let (clear, env) = environment(config, &[("TERM", "xterm"), ("LANG", "C.UTF-8")]);
+        assert!(clear);
+        assert_eq!(env, variables(&[("LANG", "C"), ("TERM", "xterm")]));
+    }
+
+    #[test]
+    fn oci_bundle() {
+        let bundle = test_dir("oci");
+        write(bundle.join("config.json"), r#"{
+            "ociVersion": "1.0.2",
+            "root": { "path": "rootfs" },
+            "hostname": "box",
+            "process": {
+                "terminal": false,
+                "user": { "uid": 1000, "gid": 100 },
+                "cwd": "/home",
+                "args": ["sh", "-l"],
+                "env": ["PATH=/bin", "TERM=xterm"]
+            },
+            "mounts": [
+                {
+                    "destination": "/proc",
+                    "type": "proc",
+                    "source": "proc",
+                    "options": ["nosuid", "noexec", "nodev", "lazytime"]
+                },
+                {
+                    "destination": "/tmp",
+                    "type": "tmpfs",
+                    "options": ["rw", "nosuid", "mode=1777"]
+                },
+                {
+                    "destination": "/data",
+                    "source": "/srv/data",
+                    "options": ["rbind", "ro"]
+                }
+            ],
+            "linux": {
+                "namespaces": [{ "type": "mount" }, { "type": "user" }, { "type": "other" }],
+                "uidMappings": [{ "containerID": 0, "hostID": 1000, "size": 1 }],
+                "gidMappings": [{ "containerID": 0, "hostID": 100, "size": 1 }],
+                "maskedPaths": ["/proc/kcore"]
+            }
+        }"#).unwrap();
+        let config = Config::from_oci(&bundle);
+        ::std::fs::remove_dir_all(&bundle).unwrap();
+        let (config, warnings) = config.unwrap();
+        assert_eq!(warnings, vec![
+            "ignoring unsupported OCI field process.terminal",
+            "ignoring unsupported OCI namespace other",
+        ]);
+
+        let root = bundle.join("rootfs");
+        let expected = Config {
+            bundle: Some(bundle.clone()),
+            chroot_dir: Some(root.clone()),
+            hostname: Some("box".to_owned()),
+            uid: Some(1000),
+            gid: Some(100),
+            working_dir: Some(PathBuf::from("/home")),
+            command: vec!["sh".to_owned(), "-l".to_owned()],
+            env_clear: true,
+            env: vec![("PATH", "/bin"), ("TERM", "xterm")].into_iter()
+                .map(|(key, value)| (key.to_owned(), value.to_owned()))
+                .collect(),
+            mounts: vec![
+                Mount::new(Path::new("proc"), &root.join("proc"), Path::new("proc"))
+                    .flags(vec![
+                        MountFlags::NoSuid,
+                        MountFlags::NoExecute,
+                        MountFlags::NoDevices,
+                        MountFlags::LazyAccessTime,
+                    ])
+                    .make_target_dir(),
+                Mount::new(Path::new("tmpfs"), &root.join("tmp"), Path::new("tmpfs"))
+                    .data("mode=1777")
+                    .flags(vec![MountFlags::NoSuid])
+                    .make_target_dir(),
+                Mount::recursive_bind(Path::new("/srv/data"), &root.join("data"))
+                    .flags(vec![MountFlags::ReadOnly])
+                    .make_target_dir(),
+            ],
+            ordered_mounts: true,
+            namespaces: vec![Namespace::Mount, Namespace::User],
+            uid_map: vec![UidMap { inside: 0, outside: 1000, count: 1 }],
+            gid_map: vec![GidMap { inside: 0, outside: 100, count: 1 }],
+            default_masks: Some(false),
+            masked_paths: vec![PathBuf::from("/proc/kcore")],
+            ..Config::default()
+        };
+
+        assert_eq!(config.bundle, expected.bundle);
+        assert_eq!(
+            toml::Value::try_from(&config).unwrap(),
+            toml::Value::try_from(&expected).unwrap(),
+        );
+    }
+
+    #[test]
+    fn working_dir_relative_in_chroot() {
+        let working_dir = resolve_working_dir(PathBuf::from("home/user/../work"), true);
+        assert_eq!(working_dir.unwrap(), PathBuf::from("/home/work"));
+        let working_dir = resolve_working_dir(PathBuf::from("./srv"), true);
+        assert_eq!(working_dir.unwrap(), PathBuf::from("/srv"));
+    }
+
+    #[test]
+    fn working_dir_escaping_chroot() {
+        match resolve_working_dir(PathBuf::from("home/../../etc"), true) {
+            Err(Error(ErrorKind::WorkingDirOutsideRoot(ref path), _)) => {
+                assert_eq!(path, Path::new("home/../../etc"));
+            },
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn working_dir_unchanged() {
+        let working_dir = resolve_working_dir(PathBuf::from("/srv/../work"), true);
+        assert_eq!(working_dir.unwrap(), PathBuf::from("/srv/../work"));
+        let working_dir = resolve_working_dir(PathBuf::from("../work"), false);
+        assert_eq!(working_dir.unwrap(), PathBuf::from("../work"));
+    }
+
+    /// An empty directory for the files of a test.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("container-{}-{}", name, process::id()));
+        let _ = ::std::fs::remove_dir_all(&dir);
+        create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// The reasons the id maps of a configuration are invalid.
+    fn invalid_maps(config: &str) -> Vec<String> {
+        match parse(config).validate() {
+            Ok(()) => Vec::new(),
+            Err(errors) => errors.into_iter()
+                .filter_map(|err| match err.0 {
+                    ErrorKind::InvalidIdMap(_, reason) => Some(reason),
+                    _ => None,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn maps_without_overlap() {
+        let config = r#"
+            uid_map = [{ inside = 0, outside = 1000, count = 1 }, { inside = 1, outside = 100000, count = 10 }]
+        "#;
+        assert_eq!(invalid_maps(config), Vec::<String>::new());
+    }
+
+    #[test]
+    fn maps_overlap_inside() {
+        let config = r#"
+            uid_map = [{ inside = 0, outside = 1000, count = 2 }, { inside = 1, outside = 100000, count = 10 }]
+        "#;
+        assert_eq!(invalid_maps(config), vec!["the ranges from 0 and 1 overlap inside"]);
+    }
+
+    #[test]
+    fn maps_overlap_outside() {
+        let config = r#"
+            gid_map = [{ inside = 0, outside = 1000, count = 1 }, { inside = 1, outside = 995, count = 10 }]
+        "#;
+        assert_eq!(invalid_maps(config), vec!["the ranges from 1000 and 995 overlap outside"]);
+    }
+
+    #[test]
+    fn maps_overflow() {
+        let config = r#"uid_map = [{ inside = 0, outside = 4294967295, count = 2 }]"#;
+        assert_eq!(invalid_maps(config), vec!["2 ids from 4294967295 outside exceeds the largest id"]);
+
+        let config = r#"uid_map = [{ inside = 4294967290, outside = 1000, count = 10 }]"#;
+        assert_eq!(invalid_maps(config), vec!["10 ids from 4294967290 inside exceeds the largest id"]);
+    }
+
+    #[test]
+    fn namespaces_all() {
+        let config = parse(r#"namespaces = "all""#);
+        assert_eq!(config.namespaces, Namespace::supported());
+        assert!(config.namespaces.contains(&Namespace::Mount));
+    }
+
+    #[test]
+    fn namespaces_all_except() {
+        let config = parse(r#"namespaces = { except = ["net"] }"#);
+        let mut expected = Namespace::supported();
+        expected.retain(|&namespace| namespace != Namespace::Net);
+        assert_eq!(config.namespaces, expected);
+    }
+
+    #[test]
+    fn namespaces_list() {
+        let config = parse(r#"namespaces = ["pid", "mount", "pid", "uts"]"#);
+        assert_eq!(config.namespaces, vec![Namespace::Pid, Namespace::Mount, Namespace::Uts]);
+    }
+
+    #[test]
+    fn namespaces_unknown() {
+        match "container".parse::<Namespace>() {
+            Err(Error(ErrorKind::UnknownNamespace(ref name), _)) => assert_eq!(name, "container"),
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert!(toml::from_str::<Config>(r#"namespaces = ["pid", "container"]"#).is_err());
+        assert!(toml::from_str::<Config>(r#"namespaces = "every""#).is_err());
+    }
+
+    /// Create a file with the given mode.
+    fn touch(path: &Path, mode: u32) {
+        use std::os::unix::fs::PermissionsExt;
+        write(path, "").unwrap();
+        ::std::fs::set_permissions(path, ::std::fs::Permissions::from_mode(mode)).unwrap();
+    }
+
+    #[test]
+    fn find_in_path_skips_non_executables() {
+        let dir = test_dir("find-exec");
+        create_dir_all(dir.join("a")).unwrap();
+        create_dir_all(dir.join("b")).unwrap();
+        create_dir_all(dir.join("c/helper")).unwrap();
+        touch(&dir.join("a/helper"), 0o644);
+        touch(&dir.join("b/helper"), 0o755);
+
+        let paths = env::join_paths(&[dir.join("c"), dir.join("a"), dir.join("b")]).unwrap();
+        let found = find_in_path(Some(&paths), "helper");
+        let missing = find_in_path(Some(&paths), "other");
+        ::std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(found, Some(dir.join("b/helper")));
+        assert_eq!(missing, None);
+    }
+
+    #[test]
+    fn find_in_path_skips_empty_and_relative_directories() {
+        let dir = test_dir("find-relative");
+        let helper = dir.join("helper");
+        touch(&helper, 0o755);
+
+        // Joining an absolute path to a directory gives the path itself, so the
+        // helper is found if a directory is searched at all.
+        let empty = find_in_path(Some(OsStr::new(":")), &helper);
+        let relative = find_in_path(Some(OsStr::new("bin")), &helper);
+        let absolute = find_in_path(Some(dir.as_os_str()), "helper");
+        ::std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(empty, None);
+        assert_eq!(relative, None);
+        assert_eq!(absolute, Some(helper));
+    }
+
+    #[test]
+    fn find_in_path_without_path() {
+        assert_eq!(find_in_path(None, "sh"), None);
+    }
+
+    #[test]
+    fn id_map_helper_missing() {
+        let dir = test_dir("find-helper");
+        touch(&dir.join("newgidmap"), 0o755);
+
+        let newuidmap = find_id_map_helper(Some(dir.as_os_str()), "newuidmap");
+        let newgidmap = find_id_map_helper(Some(dir.as_os_str()), "newgidmap");
+        ::std::fs::remove_dir_all(&dir).unwrap();
+        match newuidmap {
+            Err(Error(ErrorKind::MissingIdMapHelper(ref name), _)) => {
+                assert_eq!(name, "newuidmap");
+            },
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert_eq!(newgidmap.unwrap(), dir.join("newgidmap"));
+    }
+
+    #[test]
+    fn merge_overlay_takes_precedence() {
+        let base = parse(r#"
+            hostname = "base"
+            domainname = "example.com"
+            command = ["sh"]
+            namespaces = ["mount", "pid"]
+            env = { A = "base", B = "base" }
+        "#);
+        let overlay = parse(r#"
+            hostname = "overlay"
+            namespaces = ["pid", "uts"]
+            env = { B = "overlay" }
+        "#);
+        let config = Config::merge(base, overlay);
+        assert_eq!(config.hostname, Some("overlay".to_owned()));
+        assert_eq!(config.domainname, Some("example.com".to_owned()));
+        assert_eq!(config.command, vec!["sh"]);
+        assert_eq!(config.namespaces, vec![Namespace::Mount, Namespace::Pid, Namespace::Uts]);
+        assert_eq!(config.env["A"], "base");
+        assert_eq!(config.env["B"], "overlay");
+    }
+
+    #[test]
+    fn include_relative_to_including_file() {
+        let dir = test_dir("include");
+        create_dir_all(dir.join("sub")).unwrap();
+        write(dir.join("main.toml"), "include = [\"sub/base.toml\"]\nhostname = \"main\"\n").unwrap();
+        write(dir.join("sub/base.toml"), "include = [\"more.toml\"]\nhostname = \"base\"\n").unwrap();
+        write(dir.join("sub/more.toml"), "domainname = \"more\"\n").unwrap();
+
+        let config = Config::from_path(dir.join("main.toml"));
+        ::std::fs::remove_dir_all(&dir).unwrap();
+        let config = config.unwrap();
+        assert_eq!(config.hostname, Some("main".to_owned()));
+        assert_eq!(config.domainname, Some("more".to_owned()));
+        assert!(config.include.is_empty());
+    }
+
+    #[test]
+    fn include_cycle() {
+        let dir = test_dir("include-cycle");
+        write(dir.join("a.toml"), "include = [\"b.toml\"]\n").unwrap();
+        write(dir.join("b.toml"), "include = [\"a.toml\"]\n").unwrap();
+
+        let config = Config::from_path(dir.join("a.toml"));
+        ::std::fs::remove_dir_all(&dir).unwrap();
+        match config {
+            Err(Error(ErrorKind::IncludeCycle(ref path), _)) => assert_eq!(path, &dir.join("a.toml")),
+            other => panic!("unexpected result {:?}", other.map(drop)),
+        }
+    }
+}
+
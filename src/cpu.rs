@@ -0,0 +1,39 @@
+//! The CPUs the container may run on.
+//!
+//! See sched_setaffinity(2) and cpuset(7).
+
+use libc;
+use nix::sched::{sched_setaffinity, CpuSet};
+use nix::unistd::Pid;
+
+use error::*;
+
+/// Check that each CPU is one of those online.
+pub fn validate(cpus: &[usize]) -> Failure {
+    let online = online();
+    for &cpu in cpus {
+        ensure!(cpu < online, ErrorKind::CpuOutOfRange(cpu, online));
+    }
+    ok!()
+}
+
+/// Restrict the current process, and so each process it starts, to the CPUs.
+pub fn set_affinity(cpus: &[usize]) -> Failure {
+    validate(cpus)?;
+    let mut set = CpuSet::new();
+    for &cpu in cpus {
+        set.set(cpu).chain_err(|| ErrorKind::SetCpuAffinity)?;
+    }
+    sched_setaffinity(Pid::from_raw(0), &set).chain_err(|| ErrorKind::SetCpuAffinity)
+}
+
+/// The CPUs as a comma separated list, as in `cpuset.cpus`.
+pub fn list(cpus: &[usize]) -> String {
+    cpus.iter().map(usize::to_string).collect::<Vec<_>>().join(",")
+}
+
+/// The number of CPUs online.
+fn online() -> usize {
+    let online = unsafe { libc::sysconf(libc::_SC_NPROCESSORS_ONLN) };
+    if online < 1 { 1 } else { online as usize }
+}
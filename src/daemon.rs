@@ -0,0 +1,79 @@
+//! Running the container in the background.
+//!
+//! The process forks twice with a new session in between, so that it is not a
+//! session leader and can never gain a controlling terminal. See daemon(7).
+
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::path::Path;
+
+use libc::{self, pid_t, STDERR_FILENO, STDIN_FILENO, STDOUT_FILENO};
+use nix::sys::wait::waitpid;
+use nix::unistd::{close, dup2, fork, pipe, setsid, ForkResult};
+
+use error::*;
+
+/// The file the output of a detached container is discarded to.
+const DEV_NULL: &str = "/dev/null";
+
+/// Detach the current process into the background, with its output written to
+/// the log file or discarded.
+///
+/// Returns the PID of the detached process in the original process, and
+/// nothing in the detached process. The working directory is kept so that
+/// relative paths of the configuration still resolve.
+pub fn detach(log_file: Option<&Path>) -> Result<Option<pid_t>> {
+    // Opened before forking so that any error is reported by the original
+    // process.
+    let null = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(DEV_NULL)
+        .chain_err(|| ErrorKind::Detach)?;
+    let log = match log_file {
+        Some(path) => OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .chain_err(|| ErrorKind::OpenLogFile(path.to_owned()))?,
+        None => null.try_clone()?,
+    };
+
+    let (read_pid, write_pid) = pipe()?;
+    if let ForkResult::Parent { child } = fork()? {
+        close(write_pid)?;
+        waitpid(child, None)?;
+
+        // The pipe is empty if detaching failed.
+        let mut pid = String::new();
+        unsafe { File::from_raw_fd(read_pid) }.read_to_string(&mut pid)?;
+        let pid = pid.parse().map_err(|_| ErrorKind::Detach)?;
+        return Ok(Some(pid));
+    }
+    close(read_pid)?;
+
+    // Only the process between the two forks is a session leader, and it
+    // exits once the PID of the detached process has been passed on.
+    let detached = setsid().and_then(|_| fork());
+    match detached {
+        Ok(ForkResult::Parent { child }) => {
+            let mut pipe = unsafe { File::from_raw_fd(write_pid) };
+            let _ = write!(pipe, "{}", child);
+            unsafe { libc::_exit(0) };
+        },
+        Ok(ForkResult::Child) => {},
+        Err(_) => unsafe { libc::_exit(1) },
+    }
+    close(write_pid)?;
+
+    redirect(null.as_raw_fd(), STDIN_FILENO)?;
+    redirect(log.as_raw_fd(), STDOUT_FILENO)?;
+    redirect(log.as_raw_fd(), STDERR_FILENO)?;
+    Ok(None)
+}
+
+/// Replace a standard stream with an open file.
+fn redirect(fd: RawFd, stream: RawFd) -> Failure {
+    dup2(fd, stream).map(drop).chain_err(|| ErrorKind::Detach)
+}
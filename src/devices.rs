@@ -0,0 +1,298 @@
+//! Access to devices, allowed by rules attached to the cgroup.
+//!
+//! The unified hierarchy has no devices controller, so the rules are compiled
+//! to an eBPF program of type BPF_PROG_TYPE_CGROUP_DEVICE and attached to the
+//! cgroup. See bpf(2) and the kernel's cgroup-v2 documentation.
+
+use std::fs::File;
+use std::mem;
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::path::{Path, PathBuf};
+
+use libc::{c_int, syscall, SYS_bpf};
+use nix::errno::Errno;
+use nix::sys::stat::{makedev, mknod, Mode, SFlag};
+
+use error::*;
+
+const BPF_PROG_LOAD: c_int = 5;
+const BPF_PROG_ATTACH: c_int = 8;
+const BPF_PROG_TYPE_CGROUP_DEVICE: u32 = 15;
+const BPF_CGROUP_DEVICE: u32 = 6;
+const BPF_F_ALLOW_MULTI: u32 = 2;
+
+const BPF_DEVCG_DEV_BLOCK: i32 = 1;
+const BPF_DEVCG_DEV_CHAR: i32 = 2;
+const BPF_DEVCG_ACC_MKNOD: i32 = 1;
+const BPF_DEVCG_ACC_READ: i32 = 2;
+const BPF_DEVCG_ACC_WRITE: i32 = 4;
+
+// Instruction classes and operations.
+const BPF_LDX_MEM_W: u8 = 0x61;
+const BPF_ALU64_AND_K: u8 = 0x57;
+const BPF_ALU64_RSH_K: u8 = 0x77;
+const BPF_ALU64_MOV_K: u8 = 0xb7;
+const BPF_ALU64_MOV_X: u8 = 0xbf;
+const BPF_JMP_JNE_K: u8 = 0x55;
+const BPF_JMP_JNE_X: u8 = 0x5d;
+const BPF_EXIT: u8 = 0x95;
+
+// Registers holding the device and access checked by the program.
+const REG_CTX: u8 = 1;
+const REG_TYPE: u8 = 2;
+const REG_ACCESS: u8 = 3;
+const REG_MAJOR: u8 = 4;
+const REG_MINOR: u8 = 5;
+
+/// The license of the program, which only matters for the helpers it may use.
+const LICENSE: &[u8] = b"GPL\0";
+
+/// A rule allowing or denying access to devices.
+///
+/// A missing major or minor number matches any number, as does a type of `a`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DeviceRule {
+    #[serde(rename = "type", default)]
+    device_type: DeviceType,
+    major: Option<u32>,
+    minor: Option<u32>,
+    /// Any of `r` to read, `w` to write, and `m` to create the device.
+    #[serde(default = "default_access")]
+    access: String,
+    #[serde(default = "default_allow")]
+    allow: bool,
+    /// A device node to create within the container.
+    path: Option<PathBuf>,
+}
+
+fn default_access() -> String {
+    "rwm".to_owned()
+}
+
+fn default_allow() -> bool {
+    true
+}
+
+/// The type of device a rule applies to.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum DeviceType {
+    #[serde(rename = "a")]
+    All,
+    #[serde(rename = "c")]
+    Char,
+    #[serde(rename = "b")]
+    Block,
+}
+
+impl Default for DeviceType {
+    fn default() -> DeviceType {
+        DeviceType::All
+    }
+}
+
+impl DeviceRule {
+    /// Check that the access is known and that a node to create is a single
+    /// allowed device.
+    pub fn validate(&self) -> Failure {
+        self.access_bits()?;
+        if self.path.is_some() {
+            ensure!(
+                self.allow && self.device_type != DeviceType::All
+                    && self.major.is_some() && self.minor.is_some(),
+                ErrorKind::Devices(self.describe())
+            );
+        }
+        ok!()
+    }
+
+    /// The device node to create within the container.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_ref().map(PathBuf::as_path)
+    }
+
+    /// Create the device node of the rule, if it has one.
+    pub fn create_node(&self) -> Failure {
+        let path = match self.path {
+            Some(ref path) => path,
+            None => return ok!(),
+        };
+        self.validate()?;
+        let kind = match self.device_type {
+            DeviceType::Block => SFlag::S_IFBLK,
+            _ => SFlag::S_IFCHR,
+        };
+        let device = makedev(
+            self.major.unwrap_or_default() as u64,
+            self.minor.unwrap_or_default() as u64,
+        );
+        mknod(path, kind, Mode::from_bits_truncate(0o666), device)
+            .chain_err(|| ErrorKind::CreateDevice(path.clone()))
+            .chain_err(|| ErrorKind::Devices(self.describe()))
+    }
+
+    /// A description of the rule, as in the `devices.allow` file of the
+    /// original hierarchy.
+    pub fn describe(&self) -> String {
+        let device_type = match self.device_type {
+            DeviceType::All => "a",
+            DeviceType::Char => "c",
+            DeviceType::Block => "b",
+        };
+        let number = |number: Option<u32>| number.map_or("*".to_owned(), |n| n.to_string());
+        let mut description = format!(
+            "{} {} {}:{} {}",
+            if self.allow { "allow" } else { "deny" },
+            device_type,
+            number(self.major),
+            number(self.minor),
+            self.access,
+        );
+        if let Some(ref path) = self.path {
+            description.push_str(&format!(" {}", path.display()));
+        }
+        description
+    }
+
+    /// The access of the rule, as checked by the program.
+    fn access_bits(&self) -> Result<i32> {
+        let mut bits = 0;
+        for access in self.access.chars() {
+            bits |= match access {
+                'r' => BPF_DEVCG_ACC_READ,
+                'w' => BPF_DEVCG_ACC_WRITE,
+                'm' => BPF_DEVCG_ACC_MKNOD,
+                _ => bail!(ErrorKind::Devices(self.describe())),
+            };
+        }
+        Ok(bits)
+    }
+
+    /// The instructions that return whether access is allowed if the rule
+    /// matches, and otherwise skip to those following.
+    fn program(&self) -> Result<Vec<Insn>> {
+        let mut program = Vec::new();
+        match self.device_type {
+            DeviceType::All => {},
+            DeviceType::Char => program.push(jne(REG_TYPE, BPF_DEVCG_DEV_CHAR)),
+            DeviceType::Block => program.push(jne(REG_TYPE, BPF_DEVCG_DEV_BLOCK)),
+        }
+
+        // The access requested must be within that of the rule.
+        let access = self.access_bits()?;
+        let all = BPF_DEVCG_ACC_READ | BPF_DEVCG_ACC_WRITE | BPF_DEVCG_ACC_MKNOD;
+        if access != all {
+            program.push(Insn::new(BPF_ALU64_MOV_X, REG_CTX, REG_ACCESS, 0, 0));
+            program.push(Insn::new(BPF_ALU64_AND_K, REG_CTX, 0, 0, access));
+            program.push(Insn::new(BPF_JMP_JNE_X, REG_CTX, REG_ACCESS, 0, 0));
+        }
+        if let Some(major) = self.major {
+            program.push(jne(REG_MAJOR, major as i32));
+        }
+        if let Some(minor) = self.minor {
+            program.push(jne(REG_MINOR, minor as i32));
+        }
+
+        program.push(Insn::new(BPF_ALU64_MOV_K, 0, 0, 0, self.allow as i32));
+        program.push(Insn::new(BPF_EXIT, 0, 0, 0, 0));
+
+        // Each check jumps past the end of the rule.
+        let len = program.len();
+        for (index, insn) in program.iter_mut().enumerate() {
+            if insn.code == BPF_JMP_JNE_K || insn.code == BPF_JMP_JNE_X {
+                insn.off = (len - index - 1) as i16;
+            }
+        }
+        Ok(program)
+    }
+}
+
+/// Attach a program to a cgroup allowing access only to the devices allowed by
+/// the rules, with later rules taking precedence.
+pub fn attach(rules: &[DeviceRule], cgroup: &Path) -> Failure {
+    let mut program = vec![
+        Insn::new(BPF_LDX_MEM_W, REG_TYPE, REG_CTX, 0, 0),
+        Insn::new(BPF_ALU64_AND_K, REG_TYPE, 0, 0, 0xffff),
+        Insn::new(BPF_LDX_MEM_W, REG_ACCESS, REG_CTX, 0, 0),
+        Insn::new(BPF_ALU64_RSH_K, REG_ACCESS, 0, 0, 16),
+        Insn::new(BPF_LDX_MEM_W, REG_MAJOR, REG_CTX, 4, 0),
+        Insn::new(BPF_LDX_MEM_W, REG_MINOR, REG_CTX, 8, 0),
+    ];
+    for rule in rules.iter().rev() {
+        program.extend(rule.program()?);
+    }
+    program.push(Insn::new(BPF_ALU64_MOV_K, 0, 0, 0, 0));
+    program.push(Insn::new(BPF_EXIT, 0, 0, 0, 0));
+
+    let load = ProgLoadAttr {
+        prog_type: BPF_PROG_TYPE_CGROUP_DEVICE,
+        insn_cnt: program.len() as u32,
+        insns: program.as_ptr() as u64,
+        license: LICENSE.as_ptr() as u64,
+        log_level: 0,
+        log_size: 0,
+        log_buf: 0,
+        kern_version: 0,
+        prog_flags: 0,
+    };
+    let fd = bpf(BPF_PROG_LOAD, &load).chain_err(|| ErrorKind::DeviceFilter)?;
+    let program = unsafe { File::from_raw_fd(fd) };
+
+    // The cgroup keeps the program once attached.
+    let cgroup = File::open(cgroup).chain_err(|| ErrorKind::Cgroup(cgroup.to_owned()))?;
+    let attach = ProgAttachAttr {
+        target_fd: cgroup.as_raw_fd() as u32,
+        attach_bpf_fd: program.as_raw_fd() as u32,
+        attach_type: BPF_CGROUP_DEVICE,
+        attach_flags: BPF_F_ALLOW_MULTI,
+    };
+    bpf(BPF_PROG_ATTACH, &attach).map(drop).chain_err(|| ErrorKind::DeviceFilter)
+}
+
+/// An instruction, as `struct bpf_insn`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct Insn {
+    code: u8,
+    regs: u8,
+    off: i16,
+    imm: i32,
+}
+
+impl Insn {
+    fn new(code: u8, dst: u8, src: u8, off: i16, imm: i32) -> Insn {
+        Insn { code, regs: dst | src << 4, off, imm }
+    }
+}
+
+/// Skip the rest of a rule unless a register holds a value.
+fn jne(reg: u8, value: i32) -> Insn {
+    Insn::new(BPF_JMP_JNE_K, reg, 0, 0, value)
+}
+
+/// The attributes to load a program, as in `union bpf_attr`.
+#[repr(C)]
+struct ProgLoadAttr {
+    prog_type: u32,
+    insn_cnt: u32,
+    insns: u64,
+    license: u64,
+    log_level: u32,
+    log_size: u32,
+    log_buf: u64,
+    kern_version: u32,
+    prog_flags: u32,
+}
+
+/// The attributes to attach a program, as in `union bpf_attr`.
+#[repr(C)]
+struct ProgAttachAttr {
+    target_fd: u32,
+    attach_bpf_fd: u32,
+    attach_type: u32,
+    attach_flags: u32,
+}
+
+fn bpf<T>(cmd: c_int, attr: &T) -> Result<c_int> {
+    let result = unsafe { syscall(SYS_bpf, cmd, attr as *const T, mem::size_of::<T>()) };
+    Ok(Errno::result(result)? as c_int)
+}
@@ -14,37 +14,318 @@ error_chain!{
         Utf8(::std::str::Utf8Error);
         Nix(::nix::Error);
         Unshare(::unshare::Error);
+        Toml(::toml::ser::Error);
+        TomlParse(::toml::de::Error);
+        Json(::serde_json::Error);
     }
 
     // Internally defined errors.
     errors {
-        UnshareExit(status: ::unshare::ExitStatus) {
-            description("The unshared was unsuccessful")
+        Hint(hint: &'static str) {
+            description("A hint for the likely cause of an error")
+            display("Hint: {}", hint)
         }
-        CommandExit(status: ::std::process::ExitStatus) {
-            description("The requested command was unsuccessful")
+        MissingArgument(option: String) {
+            description("An option is missing its argument")
+            display("The option {} requires an argument", option)
         }
-        RelativeWorkingDir {
-            description("Attempted to use relative working directory in chroot")
+        ResolveExecutable {
+            description("Failed to determine the path of the executable to run in the container")
+        }
+        InheritConfig {
+            description("Failed to read the configuration passed to the container")
+        }
+        HookExit(hook: String, status: ::std::process::ExitStatus) {
+            description("A hook failed")
+            display("The {} hook failed with {}", hook, status)
+        }
+        WritePidFile(path: ::std::path::PathBuf) {
+            description("Failed to write the pid file")
+            display("Failed to write the pid file {}", path.display())
+        }
+        Pty {
+            description("Failed to allocate a pseudo-terminal for the container")
+        }
+        Detach {
+            description("Failed to detach the container into the background")
+        }
+        OpenLogFile(path: ::std::path::PathBuf) {
+            description("Failed to open the log file")
+            display("Failed to open the log file {}", path.display())
+        }
+        WriteStateFile(path: ::std::path::PathBuf) {
+            description("Failed to write the state file")
+            display("Failed to write the state file {}", path.display())
+        }
+        LoadConfig(path: ::std::path::PathBuf) {
+            description("Failed to load the configuration")
+            display("Failed to load the configuration from {}", path.display())
+        }
+        IncludeCycle(path: ::std::path::PathBuf) {
+            description("A configuration includes itself")
+            display("The configuration {} includes itself", path.display())
+        }
+        BadVolume(volume: String) {
+            description("Invalid volume, expected source:target[:ro|rw]")
+            display("Invalid volume '{}', expected source:target[:ro|rw]", volume)
+        }
+        ReadFstab(path: ::std::path::PathBuf) {
+            description("Failed to read an fstab file")
+            display("Failed to read the fstab file {}", path.display())
+        }
+        BadFstab(path: ::std::path::PathBuf, line: usize) {
+            description("Invalid fstab entry")
+            display("Invalid entry at line {} of the fstab file {}", line, path.display())
+        }
+        InvalidIdMap(map: &'static str, reason: String) {
+            description("Invalid id map")
+            display("Invalid {}: {}", map, reason)
+        }
+        IdMapHelpers {
+            description("Mapping more than the current user and group requires newuidmap and newgidmap")
+        }
+        MissingIdMapHelper(name: String) {
+            description("An id map helper was not found")
+            display("Could not find an executable {} in any absolute directory of PATH", name)
+        }
+        WriteIdMap(path: ::std::path::PathBuf) {
+            description("Failed to write an id map")
+            display("Failed to write the id map {}", path.display())
+        }
+        NoSubordinateIds(path: ::std::path::PathBuf) {
+            description("No subordinate ids are allocated to the user")
+            display("No subordinate ids are allocated to the user in {}", path.display())
+        }
+        JoinUnshared(namespace: String) {
+            description("Cannot both join and unshare a namespace")
+            display("Cannot both join and unshare the {} namespace", namespace)
+        }
+        UnsupportedJoin(namespace: String) {
+            description("Joining the namespace is not supported")
+            display("Joining the {} namespace is not supported", namespace)
+        }
+        UnshareTime {
+            description("Failed to unshare the time namespace")
+        }
+        SetTimeOffsets {
+            description("Failed to set the clock offsets of the time namespace")
+        }
+        UnknownNamespace(name: String) {
+            description("Unknown namespace")
+            display("Unknown namespace {}", name)
+        }
+        UnknownErrorFormat(format: String) {
+            description("Unknown error format, expected text or json")
+            display("Unknown error format '{}', expected text or json", format)
+        }
+        BadPid(pid: String) {
+            description("Invalid PID")
+            display("Invalid PID '{}'", pid)
+        }
+        NoSuchProcess(pid: ::libc::pid_t) {
+            description("No such process")
+            display("No process with PID {}", pid)
+        }
+        JoinNamespace(path: ::std::path::PathBuf) {
+            description("Failed to join a namespace")
+            display("Failed to join the namespace at {}", path.display())
+        }
+        WorkingDirOutsideRoot(path: ::std::path::PathBuf) {
+            description("Working directory is outside of the chroot")
+            display("Working directory {} is outside of the chroot", path.display())
         }
         EnterChroot {
             description("Failed to enter chroot directory")
         }
-        SetMount {
+        ChrootNotFound(path: ::std::path::PathBuf) {
+            description("The chroot directory does not exist")
+            display("The chroot directory {} does not exist or is not a directory", path.display())
+        }
+        MakeChrootDir(path: ::std::path::PathBuf) {
+            description("Failed to create chroot directory")
+            display("Failed to create chroot directory {}", path.display())
+        }
+        RootNotMapped {
+            description("Root must be mapped in the user namespace to set the hostname, chroot, or mounts")
+        }
+        MountSourceNotFound(source: ::std::path::PathBuf) {
+            description("A mount source does not exist")
+            display("The mount source {} does not exist", source.display())
+        }
+        SetMount(target: ::std::path::PathBuf) {
             description("Failed to set a mountpoint")
+            display("Failed to mount {}", target.display())
+        }
+        ProcPidNamespace {
+            description("Not permitted to mount proc, a PID namespace may need to be unshared")
+        }
+        Unmount(target: ::std::path::PathBuf) {
+            description("Failed to unmount a mountpoint")
+            display("Failed to unmount {}", target.display())
+        }
+        LoopDevice(image: ::std::path::PathBuf) {
+            description("Failed to attach an image to a loop device")
+            display("Failed to attach {} to a loop device", image.display())
+        }
+        UnknownMountFlag(name: String) {
+            description("Unknown mount flag")
+            display("Unknown mount flag {}", name)
+        }
+        UnknownFilesystem(filesystem_type: String) {
+            description("Unknown filesystem type")
+            display("Unknown filesystem type '{}'", filesystem_type)
+        }
+        UnknownVariable(name: String) {
+            description("Unknown environment variable")
+            display("Unknown environment variable '{}'", name)
+        }
+        BadExpansion(path: ::std::path::PathBuf) {
+            description("Invalid environment variable expansion")
+            display("Invalid environment variable expansion in {}", path.display())
+        }
+        SetTargetMode(target: ::std::path::PathBuf) {
+            description("Failed to set the mode of a mount target")
+            display("Failed to set the mode of {}", target.display())
+        }
+        SetTargetOwner(target: ::std::path::PathBuf) {
+            description("Failed to set the owner of a mount target")
+            display("Failed to set the owner of {}", target.display())
+        }
+        IdmapUnsupported {
+            description("Idmapped mounts require Linux 5.12 or later")
+        }
+        RemountReadOnly(target: ::std::path::PathBuf) {
+            description("Failed to make a mountpoint read-only")
+            display("Failed to make {} read-only", target.display())
+        }
+        SourceOutsidePrefix(source: ::std::path::PathBuf, prefix: ::std::path::PathBuf) {
+            description("Mount source resolves to a path outside of the source prefix")
+            display("Mount source {} is not within {}", source.display(), prefix.display())
+        }
+        CreateDevice(path: ::std::path::PathBuf) {
+            description("Failed to create a device")
+            display("Failed to create the device {}", path.display())
+        }
+        OverlayFilesystem {
+            description("Overlay upper and work directories must be on the same filesystem")
         }
         EnterWorkingDir {
             description("Failed to set working directory")
         }
+        MakeWorkingDir(path: ::std::path::PathBuf) {
+            description("Failed to create working directory")
+            display("Failed to create working directory {}", path.display())
+        }
         SetHostName {
             description("Failed to set the host name of the container")
         }
+        SetDomainName {
+            description("Failed to set the domain name of the container")
+        }
         SetUser {
             description("Failed to set user after configuring container")
         }
+        UnknownUser(name: String) {
+            description("Unknown user")
+            display("Unknown user {} in the /etc/passwd of the container", name)
+        }
+        ReadUsers(path: ::std::path::PathBuf) {
+            description("Failed to read the users of the container")
+            display("Failed to read {} in the container", path.display())
+        }
+        UnknownCapability(name: String) {
+            description("Unknown capability")
+            display("Unknown capability {}", name)
+        }
+        DropCapability(name: String) {
+            description("Failed to drop a capability")
+            display("Failed to drop capability {}", name)
+        }
+        RetainCapability(name: String) {
+            description("Failed to retain a capability through the change of user")
+            display("Failed to retain capability {} through the change of user", name)
+        }
+        UnknownSyscall(name: String) {
+            description("Unknown system call")
+            display("Unknown system call {}", name)
+        }
+        InstallSeccomp {
+            description("Failed to install the seccomp filter")
+        }
+        SeccompPrivileges {
+            description("Installing a seccomp filter requires no_new_privs or CAP_SYS_ADMIN")
+        }
+        SeccompArchitecture {
+            description("A seccomp filter is not supported on this architecture")
+        }
+        Cgroup(path: ::std::path::PathBuf) {
+            description("Failed to configure the cgroup")
+            display("Failed to write cgroup file {}", path.display())
+        }
+        Network(interface: String) {
+            description("Failed to configure a network interface")
+            display("Failed to configure the network interface {}", interface)
+        }
+        WriteEtcFile(path: ::std::path::PathBuf) {
+            description("Failed to write a configuration file in the container")
+            display("Failed to write {} in the container", path.display())
+        }
+        Devices(rule: String) {
+            description("Invalid device rule")
+            display("Invalid device rule '{}'", rule)
+        }
+        DeviceFilter {
+            description("Failed to attach the device rules to the cgroup")
+        }
+        CgroupController(controller: String) {
+            description("Cgroup controller is not available")
+            display("Cgroup controller {} is not available", controller)
+        }
+        CpuOutOfRange(cpu: usize, online: usize) {
+            description("CPU is out of range of those online")
+            display("CPU {} is out of range, only {} are online", cpu, online)
+        }
+        SetCpuAffinity {
+            description("Failed to set the CPU affinity of the container")
+        }
+        SetNice(nice: i32) {
+            description("Failed to set the nice value of the container")
+            display("Failed to set the nice value of the container to {}", nice)
+        }
+        SetOomScoreAdj(adj: i32) {
+            description("Failed to set the OOM score adjustment of the container")
+            display("Failed to set the OOM score adjustment of the container to {}", adj)
+        }
+        SetSchedPolicy {
+            description("Failed to set the scheduling policy of the container")
+        }
+        Sysctl(key: String, reason: String) {
+            description("Cannot set a sysctl within the container")
+            display("Cannot set the sysctl {} within the container, {}", key, reason)
+        }
+        SetSysctl(key: String) {
+            description("Failed to set a sysctl")
+            display("Failed to set the sysctl {}", key)
+        }
+        UnknownResource(name: String) {
+            description("Unknown resource limit")
+            display("Unknown resource limit {}", name)
+        }
+        Rlimit(name: String) {
+            description("Failed to set a resource limit")
+            display("Failed to set the {} resource limit", name)
+        }
+        RaiseRlimit(name: String) {
+            description("Not permitted to raise a hard resource limit")
+            display("Not permitted to raise the hard {} resource limit above its current value", name)
+        }
+        SetGroupsDenied {
+            description("Not permitted to set supplementary groups, setgroups may be denied in the user namespace")
+        }
     }
 }
 
 pub type Failure = Result<()>;
 
+#[macro_export]
 macro_rules! ok { () => (Ok(())) }
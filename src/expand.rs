@@ -0,0 +1,55 @@
+//! Expansion of environment variables in paths.
+
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Path, PathBuf};
+
+use error::*;
+
+/// Expand the environment variables in a path.
+///
+/// Variables may be written as either `$VAR` or `${VAR}` and `$$` is replaced
+/// with a single `$`. It is an error to refer to a variable that is not set.
+pub fn expand_path<P: AsRef<Path>>(path: P) -> Result<PathBuf> {
+    let path = path.as_ref();
+    let mut expanded = Vec::new();
+    let mut rest = path.as_os_str().as_bytes();
+
+    while let Some(index) = rest.iter().position(|&b| b == b'$') {
+        expanded.extend_from_slice(&rest[..index]);
+        rest = &rest[index + 1..];
+
+        let (name, remaining) = match rest.first() {
+            Some(&b'$') => {
+                expanded.push(b'$');
+                rest = &rest[1..];
+                continue;
+            },
+            Some(&b'{') => {
+                let end = rest.iter()
+                    .position(|&b| b == b'}')
+                    .ok_or_else(|| ErrorKind::BadExpansion(path.to_owned()))?;
+                (&rest[1..end], &rest[end + 1..])
+            },
+            _ => {
+                let end = rest.iter()
+                    .position(|&b| !(b.is_ascii_alphanumeric() || b == b'_'))
+                    .unwrap_or(rest.len());
+                (&rest[..end], &rest[end..])
+            },
+        };
+
+        ensure!(!name.is_empty(), ErrorKind::BadExpansion(path.to_owned()));
+
+        let name = OsStr::from_bytes(name);
+        let value = env::var_os(name).ok_or_else(|| {
+            ErrorKind::UnknownVariable(name.to_string_lossy().into_owned())
+        })?;
+        expanded.extend_from_slice(value.as_bytes());
+        rest = remaining;
+    }
+
+    expanded.extend_from_slice(rest);
+    Ok(PathBuf::from(OsString::from_vec(expanded)))
+}
@@ -0,0 +1,78 @@
+//! Mounts listed in the format of `/etc/fstab`.
+//!
+//! Each line gives the source, target, filesystem type, options, and the dump
+//! and pass fields, which are ignored. See fstab(5).
+
+use std::fs::read_to_string;
+use std::path::{Path, PathBuf};
+
+use error::*;
+use mount::{Mount, MountFlags};
+use mountinfo::unescape;
+
+/// Options that only affect how mounts are chosen or checked, or that are the
+/// default for a new mount.
+const IGNORED_OPTIONS: &[&str] = &[
+    "defaults", "rw", "auto", "user", "nouser", "users", "owner", "group", "nofail",
+    "_netdev", "suid", "dev", "exec", "atime", "diratime", "async", "nomand", "loud",
+];
+
+/// Load the mounts listed in a file, with each target within the root.
+///
+/// Entries marked `noauto` and swap entries are skipped, as they would not be
+/// mounted at boot.
+pub fn load(path: &Path, root: Option<&Path>) -> Result<Vec<Mount>> {
+    let fstab = read_to_string(path).chain_err(|| ErrorKind::ReadFstab(path.to_owned()))?;
+    let mut mounts = Vec::new();
+    for (index, line) in fstab.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mount = parse(line, root).ok_or_else(|| ErrorKind::BadFstab(path.to_owned(), index + 1))?;
+        mounts.extend(mount);
+    }
+    Ok(mounts)
+}
+
+/// Parse a single entry, or nothing if it is skipped.
+fn parse(line: &str, root: Option<&Path>) -> Option<Option<Mount>> {
+    let mut fields = line.split_whitespace();
+    let source = unescape(fields.next()?);
+    let target = unescape(fields.next()?);
+    let target = match root {
+        Some(root) => root.join(target.strip_prefix("/").unwrap_or(&target)),
+        None => target,
+    };
+    let filesystem_type = fields.next()?;
+    let options: Vec<_> = fields.next().unwrap_or("defaults").split(',').collect();
+
+    if filesystem_type == "swap" || options.contains(&"noauto") {
+        return Some(None);
+    }
+
+    let mut bind = None;
+    let mut remaining = Vec::new();
+    for option in options {
+        match option {
+            "bind" => bind = Some(false),
+            "rbind" => bind = Some(true),
+            _ if IGNORED_OPTIONS.contains(&option) => {},
+            _ if option.starts_with("x-") || option.starts_with("comment=") => {},
+            _ => remaining.push(option),
+        }
+    }
+    let (flags, data) = MountFlags::from_options(&remaining.join(","));
+
+    // Filesystem options have no meaning for a bind mount.
+    let mount = match bind {
+        Some(_) if data.is_some() => return None,
+        Some(false) => Mount::bind(source, target),
+        Some(true) => Mount::recursive_bind(source, target),
+        None => match data {
+            Some(data) => Mount::new(source, target, PathBuf::from(filesystem_type)).data(data),
+            None => Mount::new(source, target, PathBuf::from(filesystem_type)),
+        },
+    };
+    Some(Some(mount.flags(flags)))
+}
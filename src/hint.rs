@@ -0,0 +1,74 @@
+//! Hints for the likely cause of common failures.
+//!
+//! The errno of a failed system call rarely explains what is wrong with the
+//! configuration, so the most common are given a hint depending on what was
+//! being attempted.
+
+use nix::Error as NixError;
+use nix::errno::Errno;
+
+use error::*;
+
+/// An operation that may fail with a common errno.
+#[derive(Clone, Copy, Debug)]
+pub enum Operation {
+    SetHostName,
+    SetDomainName,
+    Mount,
+    EnterChroot,
+    SetUser,
+}
+
+impl Operation {
+    /// The hint for a failure of the operation with an errno.
+    fn hint(self, errno: Errno) -> Option<&'static str> {
+        use self::Operation::*;
+
+        let hint = match (self, errno) {
+            (SetHostName, Errno::EPERM) | (SetDomainName, Errno::EPERM) => {
+                "the UTS namespace may not be unshared, or CAP_SYS_ADMIN is lacking"
+            },
+            (Mount, Errno::EPERM) => {
+                "the mount namespace may not be unshared, or CAP_SYS_ADMIN is lacking"
+            },
+            (Mount, Errno::EACCES) => "check the permissions of the source and target",
+            (Mount, Errno::ENOENT) => {
+                "the source or target may not exist, set make_target to create the target"
+            },
+            (Mount, Errno::ENODEV) => "the filesystem type may not be supported by the kernel",
+            (Mount, Errno::EBUSY) => "the target may already be mounted or in use",
+            (EnterChroot, Errno::EPERM) => "entering a chroot requires CAP_SYS_CHROOT",
+            (EnterChroot, Errno::ENOENT) => "the chroot directory may not exist",
+            (EnterChroot, Errno::EINVAL) => {
+                "pivot_root requires the new root to be in a private mount namespace"
+            },
+            (SetUser, Errno::EINVAL) => "the uid or gid may not be mapped in the user namespace",
+            (SetUser, Errno::EPERM) => "changing user requires CAP_SETUID and CAP_SETGID",
+            _ => return None,
+        };
+
+        Some(hint)
+    }
+}
+
+/// Add a hint to an error caused by a common errno, for use with `map_err`.
+///
+/// ```rust,ignore
+/// sethostname(&name)
+///     .map_err(hint(Operation::SetHostName))
+///     .chain_err(|| ErrorKind::SetHostName)?;
+/// ```
+pub fn hint<E: Into<Error>>(operation: Operation) -> impl Fn(E) -> Error {
+    move |err| {
+        let err = err.into();
+        let errno = match err.kind() {
+            ErrorKind::Nix(NixError::Sys(errno)) => Some(*errno),
+            ErrorKind::Io(err) => err.raw_os_error().map(Errno::from_i32),
+            _ => None,
+        };
+        match errno.and_then(|errno| operation.hint(errno)) {
+            Some(hint) => Error::with_chain(err, ErrorKind::Hint(hint)),
+            None => err,
+        }
+    }
+}
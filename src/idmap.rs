@@ -0,0 +1,140 @@
+//! Idmapped bind mounts, which show the files of a mount as owned by other
+//! ids without changing them.
+//!
+//! The mapping is taken from a user namespace created only to hold it. See
+//! open_tree(2), mount_setattr(2), and move_mount(2), which require Linux 5.12
+//! or later.
+
+use std::ffi::CString;
+use std::fs::{write, File};
+use std::mem;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::path::Path;
+
+use libc::{self, c_int, c_uint, pause, syscall, AT_FDCWD, O_CLOEXEC};
+use libc::{SYS_move_mount, SYS_mount_setattr, SYS_open_tree};
+use nix::Error as NixError;
+use nix::errno::Errno;
+use nix::sched::{clone, CloneFlags};
+use nix::sys::signal::{kill, Signal};
+use nix::sys::wait::waitpid;
+
+use error::*;
+
+const OPEN_TREE_CLONE: c_uint = 1;
+const OPEN_TREE_CLOEXEC: c_uint = O_CLOEXEC as c_uint;
+const AT_EMPTY_PATH: c_uint = 0x1000;
+const MOVE_MOUNT_F_EMPTY_PATH: c_uint = 0x4;
+const MOUNT_ATTR_IDMAP: u64 = 0x0010_0000;
+
+/// The stack of the process holding the user namespace, which only pauses.
+const STACK_SIZE: usize = 16 * 1024;
+
+/// Attributes to change on a mount, as `struct mount_attr`.
+#[repr(C)]
+struct MountAttr {
+    attr_set: u64,
+    attr_clr: u64,
+    propagation: u64,
+    userns_fd: u64,
+}
+
+/// A range of ids owned by `outside` on the filesystem that are shown as owned
+/// by `inside` through the mount.
+///
+/// The same range is mapped for both users and groups.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct IdMap {
+    inside: u32,
+    outside: u32,
+    #[serde(default = "default_count")]
+    count: u32,
+}
+
+fn default_count() -> u32 {
+    1
+}
+
+impl IdMap {
+    /// Bind a source to a target with the ids mapped.
+    pub fn bind(&self, source: &Path, target: &Path) -> Failure {
+        let userns = self.user_namespace()?;
+
+        let flags = OPEN_TREE_CLONE | OPEN_TREE_CLOEXEC;
+        let source = CString::new(source.as_os_str().as_bytes())?;
+        let tree = unsafe { syscall(SYS_open_tree, AT_FDCWD, source.as_ptr(), flags) };
+        let tree = unsafe { File::from_raw_fd(check_syscall(tree)? as c_int) };
+
+        let attr = MountAttr {
+            attr_set: MOUNT_ATTR_IDMAP,
+            attr_clr: 0,
+            propagation: 0,
+            userns_fd: userns.as_raw_fd() as u64,
+        };
+        let result = unsafe {
+            syscall(
+                SYS_mount_setattr,
+                tree.as_raw_fd(),
+                b"\0".as_ptr(),
+                AT_EMPTY_PATH,
+                &attr as *const MountAttr,
+                mem::size_of::<MountAttr>(),
+            )
+        };
+        check_syscall(result)?;
+
+        let target = CString::new(target.as_os_str().as_bytes())?;
+        let result = unsafe {
+            syscall(
+                SYS_move_mount,
+                tree.as_raw_fd(),
+                b"\0".as_ptr(),
+                AT_FDCWD,
+                target.as_ptr(),
+                MOVE_MOUNT_F_EMPTY_PATH,
+            )
+        };
+        check_syscall(result).map(drop)
+    }
+
+    /// A description of the mapping.
+    pub fn describe(&self) -> String {
+        format!("idmap {} {} {}", self.inside, self.outside, self.count)
+    }
+
+    /// Create a user namespace with the mapping, held by a process that is
+    /// killed once the namespace has been opened.
+    fn user_namespace(&self) -> Result<File> {
+        let mut stack = vec![0; STACK_SIZE];
+        let child = clone(
+            Box::new(|| loop {
+                unsafe { pause() };
+            }),
+            &mut stack,
+            CloneFlags::CLONE_NEWUSER,
+            Some(Signal::SIGCHLD as c_int),
+        )?;
+
+        // Ids owned by outside on the filesystem are those inside the namespace.
+        let map = format!("{} {} {}\n", self.outside, self.inside, self.count);
+        let proc_dir = format!("/proc/{}", child);
+        let userns = write(format!("{}/uid_map", proc_dir), &map)
+            .and_then(|_| write(format!("{}/gid_map", proc_dir), &map))
+            .and_then(|_| File::open(format!("{}/ns/user", proc_dir)));
+
+        kill(child, Signal::SIGKILL)?;
+        waitpid(child, None)?;
+
+        Ok(userns?)
+    }
+}
+
+/// Check the result of a system call, which is only unavailable on kernels
+/// without idmapped mounts.
+fn check_syscall(result: libc::c_long) -> Result<libc::c_long> {
+    Errno::result(result).map_err(|err| match err {
+        NixError::Sys(Errno::ENOSYS) => ErrorKind::IdmapUnsupported.into(),
+        err => Error::from(err),
+    })
+}
@@ -0,0 +1,452 @@
+//! Library for spawning simple containers.
+//!
+//! A container is started in two stages. The first stage performs any external
+//! changes that need to be made before unsharing, then starts the current
+//! executable again from within the namespaces to complete the setup. A
+//! program using the library must therefore check whether it was started as
+//! the second stage before anything else.
+//!
+//! ```rust,no_run
+//! # extern crate container;
+//! # use std::ffi::OsString;
+//! # use std::process;
+//! # use container::*;
+//! # fn main() -> Result<()> {
+//! if Container::is_inside() {
+//!     process::exit(Container::run_inside()?);
+//! }
+//! let config = Config::from_path("container.toml")?;
+//! process::exit(Container::spawn(config, &[OsString::from("/bin/sh")])?);
+//! # }
+//! ```
+
+// The error chain macros recurse once for each error kind.
+#![recursion_limit = "512"]
+
+#[macro_use]
+extern crate error_chain;
+extern crate libc;
+extern crate loadconf;
+#[macro_use]
+extern crate nix;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+extern crate toml;
+extern crate unshare;
+
+#[macro_use]
+mod error;
+#[macro_use]
+mod verbose;
+mod mount;
+mod idmap;
+mod loop_device;
+mod expand;
+mod fstab;
+mod mountinfo;
+mod capability;
+mod seccomp;
+mod cgroup;
+mod devices;
+mod signal;
+mod tty;
+mod subid;
+mod sysctl;
+mod user;
+mod rlimit;
+mod cpu;
+mod daemon;
+mod priority;
+mod network;
+mod hint;
+mod oci;
+mod report;
+mod config;
+
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fs::{remove_file, write, File};
+use std::os::unix::io::{FromRawFd, RawFd};
+use std::os::unix::process::ExitStatusExt;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use nix::sys::wait::{waitpid, WaitStatus};
+use nix::unistd::Pid;
+
+use config::enter_namespaces;
+use oci::{State, Status, OCI_VERSION};
+use signal::SignalForward;
+use tty::Pty;
+
+pub use config::{Config, Namespace};
+pub use error::{Error, ErrorKind, Failure, Result, ResultExt};
+pub use mount::{Mount, MountFlags};
+pub use report::{ErrorReport, WarningReport};
+
+/// The environment variable used to indicate that the process in inside the shared.
+const COMMAND_ENV_KEY: &'static str = concat!(env!("CARGO_PKG_NAME"), "_CONTAINER_INTERNAL");
+
+/// The expected value of the envrionment variable.
+const COMMAND_ENV_VAL: &'static str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+/// The environment variable holding the file descriptor that the configuration
+/// is passed through.
+const CONFIG_FD_ENV_KEY: &'static str = concat!(env!("CARGO_PKG_NAME"), "_CONTAINER_CONFIG_FD");
+
+/// The file descriptor that the configuration is passed through.
+const CONFIG_FD: RawFd = 3;
+
+/// The default command to run once in the container, if none is configured.
+const DEFAULT_COMMAND: &'static str = "/bin/sh";
+
+/// Trace each step taken to set up a container to stderr.
+///
+/// Tracing is also enabled by setting `RUST_LOG` to `debug`.
+pub fn set_verbose(verbose: bool) {
+    verbose::init(verbose);
+}
+
+/// A container running a command.
+#[derive(Debug)]
+pub struct Container;
+
+impl Container {
+    /// Run a command in a container and wait for it to exit, returning the
+    /// code to exit with.
+    ///
+    /// If the command is empty, the configured command is run instead, or
+    /// /bin/sh if none is configured.
+    pub fn spawn(config: Config, command: &[OsString]) -> Result<i32> {
+        let program = env::current_exe().chain_err(|| ErrorKind::ResolveExecutable)?;
+        let mut unshare_command = unshare::Command::new(program);
+        if verbose::enabled() {
+            unshare_command.arg("--verbose");
+        }
+        unshare_command.arg("--");
+        unshare_command.args(command);
+        unshare_command.env(COMMAND_ENV_KEY, COMMAND_ENV_VAL);
+
+        // The configuration is passed on as loaded, rather than being loaded
+        // again from within the container where it may have changed.
+        unshare_command.file_descriptor(CONFIG_FD, unshare::Fd::from_file(config.to_file()?));
+        unshare_command.env(CONFIG_FD_ENV_KEY, CONFIG_FD.to_string());
+
+        // The container is given its own terminal, proxied to this one.
+        let pty = if config.tty() { Some(Pty::open()?) } else { None };
+        if let Some(ref pty) = pty {
+            unshare_command.stdin(unshare::Stdio::from_file(pty.slave()?));
+            unshare_command.stdout(unshare::Stdio::from_file(pty.slave()?));
+            unshare_command.stderr(unshare::Stdio::from_file(pty.slave()?));
+        }
+
+        let mut state_file = match config.state_file() {
+            Some(path) => Some(StateFile::create(path, &config)?),
+            None => None,
+        };
+        let pid_file = config.pid_file().map(Path::to_owned);
+        let post_stop = config.post_stop().to_vec();
+        let post_stop_required = config.post_stop_required();
+        config.unshare(&mut unshare_command)?;
+
+        let mut child = unshare_command.spawn()?;
+
+        // The copies of the slave end held by the command must be closed for
+        // the output of the container to end.
+        drop(unshare_command);
+        let proxy = match pty {
+            Some(pty) => Some(pty.proxy()?),
+            None => None,
+        };
+        let forwarding = SignalForward::new(child.pid())?;
+        let pid_file = match pid_file {
+            Some(path) => Some(PidFile::create(&path, child.pid())?),
+            None => None,
+        };
+        if let Some(ref mut state_file) = state_file {
+            state_file.update(Status::Running, Some(child.pid()))?;
+        }
+        let status = child.wait()?;
+        if let Some(ref mut state_file) = state_file {
+            state_file.update(Status::Stopped, None)?;
+        }
+        drop(forwarding);
+        drop(proxy);
+        drop(pid_file);
+
+        // Unless required, a failure of the hook is reported without replacing
+        // the exit status of the command.
+        if !post_stop.is_empty() {
+            match run_hook("post_stop", &post_stop) {
+                Err(ref err) if !post_stop_required => {
+                    eprintln!("{}: {}", env!("CARGO_PKG_NAME"), err);
+                },
+                result => result?,
+            }
+        }
+
+        Ok(exit_code(status.code(), status.signal()))
+    }
+
+    /// The process was started by `spawn` to complete the setup from within
+    /// the container.
+    pub fn is_inside() -> bool {
+        match env::var_os(COMMAND_ENV_KEY) {
+            Some(ref val) => val == AsRef::<OsStr>::as_ref(&COMMAND_ENV_VAL),
+            None => false,
+        }
+    }
+
+    /// Complete the setup from within the container, then run the command and
+    /// wait for it to exit, returning the code to exit with.
+    ///
+    /// The command and whether to trace each step are taken from the arguments
+    /// given by `spawn`.
+    pub fn run_inside() -> Result<i32> {
+        let mut args = env::args_os().skip(1).peekable();
+        if args.peek().map_or(false, |arg| arg == "--verbose") {
+            verbose::init(true);
+            args.next();
+        }
+        if args.peek().map_or(false, |arg| arg == "--") {
+            args.next();
+        }
+        let args: Vec<_> = args.collect();
+
+        let config = inherited_config()?;
+        let child_args = child_command(&args, config.command());
+
+        let mut command = process::Command::new(&child_args[0]);
+        command.args(&child_args[1..]);
+
+        let reaps_orphans = config.reaps_orphans();
+        let pre_start = config.pre_start().to_vec();
+        let _mounts = config.configure(&mut command)?;
+
+        // Removed after configuring, in case the configured environment sets
+        // them.
+        command.env_remove(COMMAND_ENV_KEY);
+        command.env_remove(CONFIG_FD_ENV_KEY);
+
+        if !pre_start.is_empty() {
+            run_hook("pre_start", &pre_start)?;
+        }
+
+        let mut child = command.spawn()?;
+        let _forwarding = SignalForward::new(child.id() as libc::pid_t)?;
+        if reaps_orphans {
+            return reap_until(Pid::from_raw(child.id() as libc::pid_t));
+        }
+        let status = child.wait()?;
+
+        Ok(exit_code(status.code(), status.signal()))
+    }
+
+    /// Detach the current process into the background, before the container
+    /// is spawned, with its output written to the log file or discarded.
+    ///
+    /// Returns the PID of the detached process to the original process, which
+    /// should then exit, and nothing to the detached process.
+    pub fn detach(log_file: Option<&Path>) -> Result<Option<libc::pid_t>> {
+        daemon::detach(log_file)
+    }
+
+    /// Run a command in the namespaces of an existing process, either every
+    /// namespace it has or only those given, and wait for it to exit.
+    pub fn enter(pid: libc::pid_t, namespaces: &[Namespace], command: &[OsString]) -> Result<i32> {
+        enter_namespaces(pid, namespaces)?;
+
+        // The command is run as a child so that it enters the PID namespace.
+        let child_args = child_command(command, &[]);
+        let mut child = process::Command::new(&child_args[0])
+            .args(&child_args[1..])
+            .spawn()?;
+        let _forwarding = SignalForward::new(child.id() as libc::pid_t)?;
+        let status = child.wait()?;
+
+        Ok(exit_code(status.code(), status.signal()))
+    }
+}
+
+/// A file containing the PID of a process, removed when dropped.
+#[derive(Debug)]
+struct PidFile {
+    path: PathBuf,
+}
+
+impl PidFile {
+    /// Write the PID to a file, replacing any existing file.
+    fn create(path: &Path, pid: libc::pid_t) -> Result<PidFile> {
+        write(path, format!("{}\n", pid))
+            .chain_err(|| ErrorKind::WritePidFile(path.to_owned()))?;
+        Ok(PidFile { path: path.to_owned() })
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        let _ = remove_file(&self.path);
+    }
+}
+
+/// A file describing the state of the container, in the format of the OCI
+/// runtime specification.
+///
+/// The file is kept once the container has stopped.
+#[derive(Debug)]
+struct StateFile {
+    path: PathBuf,
+    state: State,
+}
+
+impl StateFile {
+    /// Write the state of a container that is being created.
+    ///
+    /// The container is identified by its host name, or otherwise by the PID
+    /// of this process. Without a bundle, the current directory is given.
+    fn create(path: &Path, config: &Config) -> Result<StateFile> {
+        let id = config.hostname()
+            .map(String::from)
+            .unwrap_or_else(|| format!("{}-{}", env!("CARGO_PKG_NAME"), process::id()));
+        let bundle = match config.bundle() {
+            Some(bundle) => bundle.to_owned(),
+            None => env::current_dir().chain_err(|| ErrorKind::WriteStateFile(path.to_owned()))?,
+        };
+        let state = State {
+            oci_version: OCI_VERSION,
+            id,
+            status: Status::Creating,
+            pid: None,
+            bundle,
+        };
+        let state_file = StateFile { path: path.to_owned(), state };
+        state_file.write()?;
+        Ok(state_file)
+    }
+
+    /// Write a new status, keeping the PID unless a new one is given.
+    fn update(&mut self, status: Status, pid: Option<libc::pid_t>) -> Failure {
+        self.state.status = status;
+        self.state.pid = pid.or(self.state.pid);
+        self.write()
+    }
+
+    fn write(&self) -> Failure {
+        ::serde_json::to_string_pretty(&self.state)
+            .map_err(Error::from)
+            .and_then(|state| Ok(write(&self.path, state + "\n")?))
+            .chain_err(|| ErrorKind::WriteStateFile(self.path.clone()))
+    }
+}
+
+/// Run a hook to completion, failing if it does not succeed.
+fn run_hook(name: &str, hook: &[String]) -> Failure {
+    trace!("{}: {}", name, hook.join(" "));
+    let status = process::Command::new(&hook[0])
+        .args(&hook[1..])
+        .env_remove(COMMAND_ENV_KEY)
+        .env_remove(CONFIG_FD_ENV_KEY)
+        .status()?;
+    ensure!(status.success(), ErrorKind::HookExit(name.to_owned(), status));
+    ok!()
+}
+
+/// Load the configuration passed on from outside of the container.
+fn inherited_config() -> Result<Config> {
+    let fd = env::var(CONFIG_FD_ENV_KEY).ok()
+        .and_then(|fd| fd.parse().ok())
+        .ok_or(ErrorKind::InheritConfig)?;
+    let file = unsafe { File::from_raw_fd(fd) };
+    Config::from_file(file).chain_err(|| ErrorKind::InheritConfig)
+}
+
+/// Wait for any child process until the given child exits.
+///
+/// As the first process in a PID namespace, every orphaned process in the
+/// namespace becomes a child that must be waited for.
+fn reap_until(child: Pid) -> Result<i32> {
+    loop {
+        match waitpid(Pid::from_raw(-1), None)? {
+            WaitStatus::Exited(pid, code) if pid == child => {
+                return Ok(exit_code(Some(code), None));
+            },
+            WaitStatus::Signaled(pid, signal, _) if pid == child => {
+                return Ok(exit_code(None, Some(signal as i32)));
+            },
+            _ => continue,
+        }
+    }
+}
+
+/// The code to exit with for the exit status of a command.
+///
+/// A command killed by a signal is given 128 plus the signal number, as in the
+/// shell.
+fn exit_code(code: Option<i32>, signal: Option<i32>) -> i32 {
+    code.or_else(|| signal.map(|signal| 128 + signal)).unwrap_or(1)
+}
+
+/// Determine the command to run in the child.
+///
+/// The command given on the command line takes precedence over that of the
+/// configuration, and the default is used if neither is given.
+fn child_command(args: &[OsString], configured: &[String]) -> Vec<OsString> {
+    if args.len() > 0 {
+        args.to_vec()
+    } else if configured.len() > 0 {
+        configured.iter().map(OsString::from).collect()
+    } else {
+        let mut default = OsString::new();
+        default.push(DEFAULT_COMMAND);
+        vec![default]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use nix::unistd::{fork, ForkResult};
+
+    fn os_strings(strings: &[&str]) -> Vec<OsString> {
+        strings.iter().map(OsString::from).collect()
+    }
+
+    #[test]
+    fn command_from_arguments() {
+        let configured = vec!["sleep".to_owned(), "1".to_owned()];
+        let command = child_command(&os_strings(&["echo", "hello"]), &configured);
+        assert_eq!(command, os_strings(&["echo", "hello"]));
+    }
+
+    #[test]
+    fn command_from_configuration() {
+        let configured = vec!["sleep".to_owned(), "1".to_owned()];
+        assert_eq!(child_command(&[], &configured), os_strings(&["sleep", "1"]));
+    }
+
+    #[test]
+    fn command_default() {
+        assert_eq!(child_command(&[], &[]), os_strings(&["/bin/sh"]));
+    }
+
+    #[test]
+    fn exit_code_of_reaped_command() {
+        // Any child may be reaped, so the command is waited for from a separate
+        // process rather than alongside the children of other tests.
+        let reaper = match fork().unwrap() {
+            ForkResult::Parent { child } => child,
+            ForkResult::Child => {
+                let code = process::Command::new("/bin/false").spawn()
+                    .map_err(Error::from)
+                    .and_then(|child| reap_until(Pid::from_raw(child.id() as i32)))
+                    .unwrap_or(-1);
+                unsafe { ::libc::_exit(code) };
+            },
+        };
+
+        assert_eq!(waitpid(reaper, None).unwrap(), WaitStatus::Exited(reaper, 1));
+    }
+}
@@ -0,0 +1,98 @@
+//! Loop devices for mounting image files.
+//!
+//! See loop(4) for more details on the interface.
+
+use std::fs::{File, OpenOptions};
+use std::mem;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+
+use error::*;
+
+const LOOP_SET_FD: u32 = 0x4C00;
+const LOOP_CLR_FD: u32 = 0x4C01;
+const LOOP_SET_STATUS64: u32 = 0x4C04;
+const LOOP_CTL_GET_FREE: u32 = 0x4C82;
+
+const LO_FLAGS_READ_ONLY: u32 = 1;
+const LO_FLAGS_AUTOCLEAR: u32 = 4;
+
+const LO_NAME_SIZE: usize = 64;
+const LO_KEY_SIZE: usize = 32;
+
+/// Status of a loop device, as `struct loop_info64`.
+#[repr(C)]
+struct LoopInfo64 {
+    lo_device: u64,
+    lo_inode: u64,
+    lo_rdevice: u64,
+    lo_offset: u64,
+    lo_sizelimit: u64,
+    lo_number: u32,
+    lo_encrypt_type: u32,
+    lo_encrypt_key_size: u32,
+    lo_flags: u32,
+    lo_file_name: [u8; LO_NAME_SIZE],
+    lo_crypt_name: [u8; LO_NAME_SIZE],
+    lo_encrypt_key: [u8; LO_KEY_SIZE],
+    lo_init: [u64; 2],
+}
+
+ioctl_none_bad!(loop_ctl_get_free, LOOP_CTL_GET_FREE);
+ioctl_write_int_bad!(loop_set_fd, LOOP_SET_FD);
+ioctl_none_bad!(loop_clr_fd, LOOP_CLR_FD);
+ioctl_write_ptr_bad!(loop_set_status64, LOOP_SET_STATUS64, LoopInfo64);
+
+/// A loop device with an image file attached.
+///
+/// The device is set to automatically detach from the image once it is no
+/// longer in use. It must be kept open until it has been mounted, after which
+/// it will be detached once unmounted.
+#[derive(Debug)]
+pub struct LoopDevice {
+    // Held open until mounted to avoid being detached.
+    #[allow(dead_code)]
+    device: File,
+    path: PathBuf,
+}
+
+impl LoopDevice {
+    /// Attach an image file to the next free loop device.
+    pub fn attach<P: AsRef<Path>>(image: P, read_only: bool) -> Result<LoopDevice> {
+        let control = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open("/dev/loop-control")?;
+        let number = unsafe { loop_ctl_get_free(control.as_raw_fd()) }?;
+
+        let path = PathBuf::from(format!("/dev/loop{}", number));
+        let device = OpenOptions::new()
+            .read(true)
+            .write(!read_only)
+            .open(&path)?;
+        let image = OpenOptions::new()
+            .read(true)
+            .write(!read_only)
+            .open(image)?;
+
+        unsafe { loop_set_fd(device.as_raw_fd(), image.as_raw_fd()) }?;
+
+        let mut info: LoopInfo64 = unsafe { mem::zeroed() };
+        info.lo_flags = LO_FLAGS_AUTOCLEAR;
+        if read_only {
+            info.lo_flags |= LO_FLAGS_READ_ONLY;
+        }
+
+        if let Err(err) = unsafe { loop_set_status64(device.as_raw_fd(), &info) } {
+            let _ = unsafe { loop_clr_fd(device.as_raw_fd()) };
+            return Err(err.into());
+        }
+
+        Ok(LoopDevice { device, path })
+    }
+
+    /// The path of the loop device.
+    pub fn path(&self) -> &Path {
+        self.path.as_path()
+    }
+}
@@ -1,97 +1,355 @@
 //! Application for spawining simple containers.
 //!
-//! The application executes in two stages. The first stage loads the configuration and performs
-//! any external changes that need to be made before unsharing. It then calls itself again from
-//! within the namespaces to complete the sharing.
+//! See the library for how the container is started in two stages.
 
-#[macro_use]
-extern crate error_chain;
+extern crate container;
 extern crate libc;
 extern crate loadconf;
-extern crate nix;
-extern crate serde;
-#[macro_use]
-extern crate serde_derive;
-extern crate unshare;
-
-#[macro_use]
-mod error;
-mod mount;
-mod config;
 
 use std::env;
-use std::ffi::{OsStr, OsString};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
 use std::process;
 
 use loadconf::Load;
 
-use error::*;
-use config::*;
+use container::*;
+
+/// The usage printed by `--help`.
+const USAGE: &'static str = concat!("\
+Usage: ", env!("CARGO_PKG_NAME"), " [OPTIONS] [--] [COMMAND [ARGS...]]
+       ", env!("CARGO_PKG_NAME"), " --enter <pid> [--namespaces <list>] [--] [COMMAND [ARGS...]]
+
+Run a command in a container, or the configured command if none is given.
+The default command is /bin/sh.
+
+With --enter, the command is instead run in the namespaces of an existing
+process, such as a running container.
 
-/// The environment variable used to indicate that the process in inside the shared.
-const COMMAND_ENV_KEY: &'static str = concat!(env!("CARGO_PKG_NAME"), "_CONTAINER_INTERNAL");
+Options:
+    --config <path>         Load the configuration from a file, or from
+                            stdin if the path is -
+    --bundle <path>         Load the configuration from an OCI bundle
+    --volume <src>:<dst>[:ro]
+                            Bind mount a path into the container
+    --fstab <path>          Add the mounts listed in a file in the format of
+                            /etc/fstab
+    --pid-file <path>       Write the PID of the container to a file
+    --state-file <path>     Write the state of the container to a file as
+                            JSON, in the format of the OCI runtime
+                            specification
+    --detach                Run the container in the background and print
+                            the PID of the process waiting for it
+    --tty                   Give the container its own pseudo-terminal,
+                            proxied to the current terminal
+    --log-file <path>       Append the output of a detached container to a
+                            file rather than discarding it
+    --enter <pid>           Run the command in the namespaces of a process
+    --namespaces <list>     Enter only the given namespaces, separated by
+                            commas, such as mnt,net
+    --dry-run               Print the configuration that would be applied
+    --dump-config           Print the loaded configuration
+    --validate              Check the configuration and print every problem
+                            found
+    --error-format <format> Print errors as text, or as json with one object
+                            per line
+    --verbose               Trace each step of setting up the container,
+                            also enabled by RUST_LOG=debug
+    --help                  Print this help
+    --version               Print the version
 
-/// The expected value of the envrionment variable.
-const COMMAND_ENV_VAL: &'static str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+The configuration is otherwise loaded from the first of container.toml,
+.container.toml, ~/.container.toml, ~/.config/container.toml,
+~/.config/container/config.toml, /etc/.config/container.toml, or
+/etc/.config/container/config.toml that exists, with or without the .toml
+extension.
+");
+
+
+/// Options given on the command line before the command to run.
+#[derive(Debug, Default)]
+struct Options {
+    /// Print the configuration that would be applied without applying it.
+    dry_run: bool,
+    /// Print the configuration in the same format that it is loaded from.
+    dump_config: bool,
+    /// Check the configuration without applying it.
+    validate: bool,
+    /// Trace each step taken to set up the container.
+    verbose: bool,
+    /// Print the usage.
+    help: bool,
+    /// Print the version.
+    version: bool,
+    /// The configuration file to load in place of the default locations.
+    config: Option<PathBuf>,
+    /// The OCI bundle to load the configuration from.
+    bundle: Option<PathBuf>,
+    /// The file to write the PID of the container to.
+    pid_file: Option<PathBuf>,
+    /// Give the container its own pseudo-terminal.
+    tty: bool,
+    /// The file to write the state of the container to.
+    state_file: Option<PathBuf>,
+    /// Run the container in the background.
+    detach: bool,
+    /// The file to write the output of a detached container to.
+    log_file: Option<PathBuf>,
+    /// Bind mounts to make in addition to those of the configuration.
+    volumes: Vec<String>,
+    /// Files listing mounts to make in addition to those of the configuration.
+    fstabs: Vec<PathBuf>,
+    /// The process to enter the namespaces of in place of starting a container.
+    enter: Option<libc::pid_t>,
+    /// The namespaces to enter, or every namespace if empty.
+    namespaces: Vec<Namespace>,
+    /// The command to run in the container, if given.
+    command: Vec<OsString>,
+}
 
-/// The default command to run once in the container
-const DEFAULT_COMMAND: &'static str = "/bin/sh";
+impl Options {
+    /// Parse the options from the command line.
+    ///
+    /// Options are only parsed until the first argument that is not an option
+    /// or until `--`. The remaining arguments are the command to run.
+    fn from_args() -> Result<Options> {
+        let args: Vec<_> = env::args_os().skip(1).collect();
+        let mut options = Options::default();
 
+        let mut rest = args.as_slice();
+        while let Some(arg) = rest.first() {
+            match arg.to_str() {
+                Some("--") => {
+                    rest = &rest[1..];
+                    break;
+                },
+                Some("--dry-run") => options.dry_run = true,
+                Some("--dump-config") => options.dump_config = true,
+                Some("--validate") => options.validate = true,
+                Some("--verbose") => options.verbose = true,
+                Some("--detach") => options.detach = true,
+                Some("--tty") => options.tty = true,
+                Some("--help") => options.help = true,
+                Some("--version") => options.version = true,
+                Some("--config") => {
+                    let path = rest.get(1)
+                        .ok_or_else(|| ErrorKind::MissingArgument("--config".into()))?;
+                    options.config = Some(PathBuf::from(path));
+                    rest = &rest[1..];
+                },
+                Some("--bundle") => {
+                    let path = rest.get(1)
+                        .ok_or_else(|| ErrorKind::MissingArgument("--bundle".into()))?;
+                    options.bundle = Some(PathBuf::from(path));
+                    rest = &rest[1..];
+                },
+                Some("--volume") => {
+                    let volume = rest.get(1)
+                        .ok_or_else(|| ErrorKind::MissingArgument("--volume".into()))?;
+                    let volume = volume.to_str()
+                        .ok_or_else(|| ErrorKind::BadVolume(volume.to_string_lossy().into()))?;
+                    options.volumes.push(volume.to_owned());
+                    rest = &rest[1..];
+                },
+                Some("--fstab") => {
+                    let path = rest.get(1)
+                        .ok_or_else(|| ErrorKind::MissingArgument("--fstab".into()))?;
+                    options.fstabs.push(PathBuf::from(path));
+                    rest = &rest[1..];
+                },
+                Some("--pid-file") => {
+                    let path = rest.get(1)
+                        .ok_or_else(|| ErrorKind::MissingArgument("--pid-file".into()))?;
+                    options.pid_file = Some(PathBuf::from(path));
+                    rest = &rest[1..];
+                },
+                Some("--state-file") => {
+                    let path = rest.get(1)
+                        .ok_or_else(|| ErrorKind::MissingArgument("--state-file".into()))?;
+                    options.state_file = Some(PathBuf::from(path));
+                    rest = &rest[1..];
+                },
+                Some("--log-file") => {
+                    let path = rest.get(1)
+                        .ok_or_else(|| ErrorKind::MissingArgument("--log-file".into()))?;
+                    options.log_file = Some(PathBuf::from(path));
+                    rest = &rest[1..];
+                },
+                // The format is found before parsing, by json_errors.
+                Some("--error-format") => {
+                    let format = rest.get(1)
+                        .ok_or_else(|| ErrorKind::MissingArgument("--error-format".into()))?;
+                    check_error_format(&format.to_string_lossy())?;
+                    rest = &rest[1..];
+                },
+                Some(arg) if arg.starts_with("--error-format=") => {
+                    check_error_format(&arg["--error-format=".len()..])?;
+                },
+                Some("--enter") => {
+                    let pid = rest.get(1)
+                        .ok_or_else(|| ErrorKind::MissingArgument("--enter".into()))?;
+                    let pid = pid.to_str()
+                        .and_then(|pid| pid.parse().ok())
+                        .ok_or_else(|| ErrorKind::BadPid(pid.to_string_lossy().into()))?;
+                    options.enter = Some(pid);
+                    rest = &rest[1..];
+                },
+                Some("--namespaces") => {
+                    let list = rest.get(1)
+                        .ok_or_else(|| ErrorKind::MissingArgument("--namespaces".into()))?;
+                    let list = list.to_str()
+                        .ok_or_else(|| ErrorKind::UnknownNamespace(list.to_string_lossy().into()))?;
+                    for name in list.split(',').filter(|name| !name.is_empty()) {
+                        options.namespaces.push(name.parse()?);
+                    }
+                    rest = &rest[1..];
+                },
+                _ => break,
+            }
+            rest = &rest[1..];
+        }
+
+        options.command = rest.to_vec();
+        Ok(options)
+    }
+
+    /// Load the configuration, either from an OCI bundle, the given file, or
+    /// the default locations, then add the volumes and fstab mounts.
+    fn load_config(&self) -> Result<Config> {
+        let mut config = match (&self.bundle, &self.config) {
+            (Some(bundle), _) => {
+                let (config, warnings) = Config::from_oci(bundle)?;
+                for warning in &warnings {
+                    warn(warning);
+                }
+                config
+            },
+            (None, Some(path)) if path == Path::new("-") => Config::from_stdin()?,
+            (None, Some(path)) => Config::from_path(path)?,
+            (None, None) => Config::load(env!("CARGO_PKG_NAME")).resolve_includes()?,
+        };
+        for volume in &self.volumes {
+            config.add_volume(volume)?;
+        }
+        for path in &self.fstabs {
+            config.mounts_from_fstab(path)?;
+        }
+        if let Some(ref path) = self.pid_file {
+            config.set_pid_file(path);
+        }
+        if let Some(ref path) = self.state_file {
+            config.set_state_file(path);
+        }
+        if self.tty {
+            config.set_tty(true);
+        }
+        if let Some(ref path) = self.log_file {
+            config.set_log_file(path);
+        }
+        Ok(config)
+    }
+}
 
 /// Determines if inside or outside of container before proceeding.
+///
+/// Exits with the same code as the command run in the container.
 fn main() -> Failure {
-    let config = Config::load(env!("CARGO_PKG_NAME"));
+    if Container::is_inside() {
+        process::exit(Container::run_inside()?);
+    }
 
-    match env::var_os(COMMAND_ENV_KEY) {
-        Some(ref val) if val == AsRef::<OsStr>::as_ref(&COMMAND_ENV_VAL) => run_child(config),
-        _ => setup_unshare(config),
+    match run() {
+        Err(ref err) if json_errors() => {
+            eprintln!("{}", ErrorReport::new(err).to_json()?);
+            process::exit(1);
+        },
+        result => result,
     }
 }
 
-/// Set up the unshare externally.
-fn setup_unshare(config: Config) -> Failure {
-    let program = env::current_exe().expect("Determine executable name");
-    let mut command = unshare::Command::new(program);
-    command.args(child_command().as_ref());
-    command.env(COMMAND_ENV_KEY, COMMAND_ENV_VAL);
-
-    config.unshare(&mut command)?;
-
-    command.status()
-        .map_err(Error::from)
-        .and_then(|status| match status {
-            unshare::ExitStatus::Exited(0) => ok!(),
-            _ => Err(ErrorKind::UnshareExit(status).into()),
-        })
-}
+/// Parses the options and runs the container, or does whatever else they ask.
+fn run() -> Failure {
+    let options = Options::from_args()?;
+    set_verbose(options.verbose);
+    if options.help {
+        print!("{}", USAGE);
+        return ok!();
+    }
+    if options.version {
+        println!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+        return ok!();
+    }
+
+    if let Some(pid) = options.enter {
+        process::exit(Container::enter(pid, &options.namespaces, &options.command)?);
+    }
+
+    let config = options.load_config()?;
+    let code = match () {
+        _ if options.dump_config => config.dump().map(|_| 0)?,
+        _ if options.validate => validate(&config, json_errors()),
+        _ if options.dry_run => config.dry_run().map(|_| 0)?,
+        _ if options.detach => match Container::detach(config.log_file())? {
+            Some(pid) => {
+                println!("{}", pid);
+                0
+            },
+            None => Container::spawn(config, &options.command)?,
+        },
+        _ => Container::spawn(config, &options.command)?,
+    };
 
-/// Run the command from inside the unshare.
-fn run_child(config: Config) -> Failure {
-    let child = child_command();
-    let child_args: &[OsString] = child.as_ref();
+    process::exit(code)
+}
 
-    let mut command = process::Command::new(&child_args[0]);
-    command.args(&child_args[1..]);
-    command.env_remove(COMMAND_ENV_KEY);
+/// Print every problem with the configuration, exiting with 1 if there are
+/// any.
+fn validate(config: &Config, json: bool) -> i32 {
+    match config.validate() {
+        Ok(()) => 0,
+        Err(errors) => {
+            for err in errors {
+                match ErrorReport::new(&err).to_json() {
+                    Ok(ref report) if json => eprintln!("{}", report),
+                    _ => {
+                        let causes: Vec<_> = err.iter().map(ToString::to_string).collect();
+                        eprintln!("{}: {}", env!("CARGO_PKG_NAME"), causes.join(": "));
+                    },
+                }
+            }
+            1
+        },
+    }
+}
 
-    config.configure(&mut command)?;
+/// Print a warning, as JSON if errors are.
+fn warn(warning: &str) {
+    match WarningReport::new(warning).to_json() {
+        Ok(ref report) if json_errors() => eprintln!("{}", report),
+        _ => eprintln!("{}: warning: {}", env!("CARGO_PKG_NAME"), warning),
+    }
+}
 
-    command.status()
-        .map_err(Error::from)
-        .and_then(|status| match status.code() {
-            Some(0) => ok!(),
-            _ => Err(ErrorKind::CommandExit(status).into()),
-        })
+/// Whether errors are to be printed as JSON.
+///
+/// This is found before the options are parsed, so that errors in them are
+/// printed in the same format.
+fn json_errors() -> bool {
+    let mut args = env::args_os().skip(1).take_while(|arg| arg != "--");
+    while let Some(arg) = args.next() {
+        match arg.to_str() {
+            Some("--error-format") => return args.next().map_or(false, |format| format == "json"),
+            Some("--error-format=json") => return true,
+            _ => {},
+        }
+    }
+    false
 }
 
-/// Determine the command to run in the child.
-fn child_command() -> Vec<OsString> {
-    let args: Vec<_> = env::args_os().skip(1).collect();
-    if args.len() == 0 {
-            let mut default = OsString::new();
-            default.push(DEFAULT_COMMAND);
-            vec![default]
-    } else {
-        args
+/// Check that errors can be printed in a format.
+fn check_error_format(format: &str) -> Failure {
+    match format {
+        "text" | "json" => ok!(),
+        _ => Err(ErrorKind::UnknownErrorFormat(format.to_owned()).into()),
     }
 }
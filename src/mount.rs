@@ -1,11 +1,24 @@
-use std::fs::create_dir_all;
+use std::ffi::CString;
+use std::fs::{create_dir_all, metadata, read_to_string, set_permissions};
+use std::fs::{OpenOptions, Permissions};
+use std::os::unix::fs::{symlink, MetadataExt, PermissionsExt};
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
-use nix::mount::{mount, MsFlags};
-
-// TODO: MS_LAZYATIME (not currently in libc)
+use nix::Error as NixError;
+use nix::errno::Errno;
+use libc::{uid_t, gid_t, MS_LAZYTIME, MS_NOSYMFOLLOW};
+use nix::mount::{mount, umount2, MntFlags, MsFlags};
+use nix::sys::stat::{makedev, mknod, Mode, SFlag};
+use nix::sys::utsname::uname;
+use nix::unistd::{chown, Uid, Gid};
+use serde::{Deserialize, Deserializer};
 
 use ::error::*;
+use ::expand::expand_path;
+use ::idmap::IdMap;
+use ::loop_device::LoopDevice;
+use ::mountinfo::mounts;
 
 /// A new mountpoint within a mount namespace.
 ///
@@ -18,31 +31,50 @@ use ::error::*;
 ///
 /// This is simply a wrapper for `mount(2)` in Linux.
 ///
-/// ```rust
-/// DirMount::bind("/proc", "/tmp/jail/proc").read_only().mount();
+/// ```rust,no_run
+/// # extern crate container;
+/// # use container::*;
+/// # fn main() -> Result<()> {
+/// Mount::bind("/proc", "/tmp/jail/proc").read_only().mount()?;
+/// # Ok(())
+/// # }
 /// ```
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(tag = "option")]
 #[serde(rename_all = "snake_case")]
 pub enum Mount {
     /// Create a new mount from `src` to `target`.
     ///
     /// The file system type must be explicitly provided as along with the
-    /// target and the source.
+    /// target and the source. Any filesystem specific options can be provided
+    /// as `data`.
     Mount {
         source: PathBuf,
         target: PathBuf,
         filesystem_type: PathBuf,
         #[serde(default)]
+        data: Option<String>,
+        #[serde(default)]
+        #[serde(deserialize_with = "flag_list")]
         flags: Vec<MountFlags>,
         #[serde(default)]
         make_target: bool,
+        #[serde(default)]
+        target_mode: Option<u32>,
+        #[serde(default)]
+        target_owner: Option<(uid_t, gid_t)>,
     },
     /// Update the mount flags on an existing mount.
+    ///
+    /// The flags are added to those the mount already has, unless `exact` is
+    /// set to replace them with only the flags given.
     Remount {
         target: PathBuf,
         #[serde(default)]
+        #[serde(deserialize_with = "flag_list")]
         flags: Vec<MountFlags>,
+        #[serde(default)]
+        exact: bool,
     },
     /// Update an existing mount point to be _shared_.
     ///
@@ -52,6 +84,9 @@ pub enum Mount {
     Shared {
         target: PathBuf,
         #[serde(default)]
+        recursive: bool,
+        #[serde(default)]
+        #[serde(deserialize_with = "flag_list")]
         flags: Vec<MountFlags>,
     },
     /// Update an existing mount point to be _private_.
@@ -62,6 +97,9 @@ pub enum Mount {
     Private {
         target: PathBuf,
         #[serde(default)]
+        recursive: bool,
+        #[serde(default)]
+        #[serde(deserialize_with = "flag_list")]
         flags: Vec<MountFlags>,
     },
     /// Update an existing mount point to be a _slave_.
@@ -71,6 +109,9 @@ pub enum Mount {
     Slave {
         target: PathBuf,
         #[serde(default)]
+        recursive: bool,
+        #[serde(default)]
+        #[serde(deserialize_with = "flag_list")]
         flags: Vec<MountFlags>,
     },
     /// Update an existing mount point to be a _unbindable_.
@@ -78,41 +119,243 @@ pub enum Mount {
     /// This has the same effect as [`Mount::private`](#method.provate) but
     /// also ensures the mount point, and its children, can't be mounted as a
     /// bind. Recursive bind mounts will simply have _unbindable_ mounts pruned.
+    ///
+    /// Each of the propagation changes can be made `recursive` to also apply
+    /// to every mount point in the subtree.
     Unbindable {
         target: PathBuf,
         #[serde(default)]
+        recursive: bool,
+        #[serde(default)]
+        #[serde(deserialize_with = "flag_list")]
         flags: Vec<MountFlags>,
     },
-    /// Bind a directory to a new mount point.
+    /// Bind a directory or file to a new mount point.
+    ///
+    /// With an `idmap`, files owned by the outside ids are shown as owned by
+    /// the inside ids through the mount.
     Bind {
         source: PathBuf,
         target: PathBuf,
         #[serde(default)]
+        #[serde(deserialize_with = "flag_list")]
         flags: Vec<MountFlags>,
         #[serde(default)]
+        idmap: Option<IdMap>,
+        #[serde(default)]
         make_target: bool,
+        #[serde(default)]
+        target_mode: Option<u32>,
+        #[serde(default)]
+        target_owner: Option<(uid_t, gid_t)>,
     },
     /// Bind a directory and all mounts in its subtree to a new mount point.
+    ///
+    /// If `recursive_read_only` is set then every mount point in the subtree is
+    /// also made read-only.
     RecursiveBind {
         source: PathBuf,
         target: PathBuf,
         #[serde(default)]
+        recursive_read_only: bool,
+        #[serde(default)]
+        #[serde(deserialize_with = "flag_list")]
         flags: Vec<MountFlags>,
         #[serde(default)]
         make_target: bool,
+        #[serde(default)]
+        target_mode: Option<u32>,
+        #[serde(default)]
+        target_owner: Option<(uid_t, gid_t)>,
     },
     /// Move a mount from an existing mount point to a new mount point.
     Relocate {
         source: PathBuf,
         target: PathBuf,
         #[serde(default)]
+        #[serde(deserialize_with = "flag_list")]
+        flags: Vec<MountFlags>,
+        #[serde(default)]
+        make_target: bool,
+        #[serde(default)]
+        target_mode: Option<u32>,
+        #[serde(default)]
+        target_owner: Option<(uid_t, gid_t)>,
+    },
+    /// Mount a new `tmpfs` filesystem at the mount point.
+    ///
+    /// The size limit (in bytes) and the mode of the root directory of the
+    /// filesystem may optionally be specified.
+    Tmpfs {
+        target: PathBuf,
+        #[serde(default)]
+        size: Option<u64>,
+        #[serde(default)]
+        mode: Option<u32>,
+        #[serde(default)]
+        #[serde(deserialize_with = "flag_list")]
+        flags: Vec<MountFlags>,
+        #[serde(default)]
+        make_target: bool,
+        #[serde(default)]
+        target_mode: Option<u32>,
+        #[serde(default)]
+        target_owner: Option<(uid_t, gid_t)>,
+    },
+    /// Mount an `overlay` filesystem combining several directories.
+    ///
+    /// The `lowerdirs` are stacked read-only layers, with the first taking
+    /// precedence over those following it. All writes are made to the
+    /// `upperdir` and the `workdir` is used by the filesystem to prepare files
+    /// before they are moved into the `upperdir`. The `upperdir` and `workdir`
+    /// must both be on the same filesystem.
+    Overlay {
+        lowerdirs: Vec<PathBuf>,
+        upperdir: PathBuf,
+        workdir: PathBuf,
+        target: PathBuf,
+        #[serde(default)]
+        #[serde(deserialize_with = "flag_list")]
+        flags: Vec<MountFlags>,
+        #[serde(default)]
+        make_target: bool,
+        #[serde(default)]
+        target_mode: Option<u32>,
+        #[serde(default)]
+        target_owner: Option<(uid_t, gid_t)>,
+    },
+    /// Mount a new `proc` filesystem at the mount point.
+    ///
+    /// The filesystem is mounted with _nosuid_, _nodev_, and _noexec_. It will
+    /// only show the processes of the container if a new PID namespace has
+    /// been unshared and mounting will fail with a permission error if the
+    /// process is not permitted to mount a `proc` filesystem for the current
+    /// PID namespace.
+    Proc {
+        target: PathBuf,
+        #[serde(default)]
+        #[serde(deserialize_with = "flag_list")]
+        flags: Vec<MountFlags>,
+        #[serde(default)]
+        make_target: bool,
+        #[serde(default)]
+        target_mode: Option<u32>,
+        #[serde(default)]
+        target_owner: Option<(uid_t, gid_t)>,
+    },
+    /// Mount a new `sysfs` filesystem at the mount point.
+    ///
+    /// The filesystem is mounted with _nosuid_, _nodev_, _noexec_, and is
+    /// read-only unless `read_write` is set.
+    Sysfs {
+        target: PathBuf,
+        #[serde(default)]
+        read_write: bool,
+        #[serde(default)]
+        #[serde(deserialize_with = "flag_list")]
+        flags: Vec<MountFlags>,
+        #[serde(default)]
+        make_target: bool,
+        #[serde(default)]
+        target_mode: Option<u32>,
+        #[serde(default)]
+        target_owner: Option<(uid_t, gid_t)>,
+    },
+    /// Mount the unified `cgroup2` hierarchy at the mount point.
+    ///
+    /// The filesystem is mounted with _nosuid_, _nodev_, _noexec_, and is
+    /// read-only unless `read_write` is set. Only the subtree of the cgroup
+    /// the container is in is shown if the cgroup namespace is unshared,
+    /// otherwise the whole hierarchy is shown.
+    Cgroup2 {
+        target: PathBuf,
+        #[serde(default)]
+        read_write: bool,
+        #[serde(default)]
+        #[serde(deserialize_with = "flag_list")]
+        flags: Vec<MountFlags>,
+        #[serde(default)]
+        make_target: bool,
+        #[serde(default)]
+        target_mode: Option<u32>,
+        #[serde(default)]
+        target_owner: Option<(uid_t, gid_t)>,
+    },
+    /// Mount a new private instance of `devpts` at the mount point.
+    ///
+    /// The filesystem is mounted with _nosuid_ and _noexec_. The `mode` of new
+    /// pseudo-terminals defaults to `0620` and the `ptmxmode` of the `ptmx`
+    /// device defaults to `0666`. The `ptmx` device in the new instance should
+    /// be bound over `/dev/ptmx`.
+    Devpts {
+        target: PathBuf,
+        #[serde(default)]
+        mode: Option<u32>,
+        #[serde(default)]
+        ptmxmode: Option<u32>,
+        #[serde(default)]
+        #[serde(deserialize_with = "flag_list")]
+        flags: Vec<MountFlags>,
+        #[serde(default)]
+        make_target: bool,
+        #[serde(default)]
+        target_mode: Option<u32>,
+        #[serde(default)]
+        target_owner: Option<(uid_t, gid_t)>,
+    },
+    /// Mount an image file by attaching it to a loop device.
+    ///
+    /// The loop device is detached once the mount is unmounted. If the mount
+    /// is `read_only` then the image is also attached read-only.
+    LoopImage {
+        image: PathBuf,
+        target: PathBuf,
+        filesystem_type: PathBuf,
+        #[serde(default)]
+        read_only: bool,
+        #[serde(default)]
+        #[serde(deserialize_with = "flag_list")]
+        flags: Vec<MountFlags>,
+        #[serde(default)]
+        make_target: bool,
+        #[serde(default)]
+        target_mode: Option<u32>,
+        #[serde(default)]
+        target_owner: Option<(uid_t, gid_t)>,
+    },
+    /// Mount a minimal `/dev` containing only the most commonly used devices.
+    ///
+    /// A new `tmpfs` is mounted and the `null`, `zero`, `full`, `random`,
+    /// `urandom`, and `tty` devices are created in it. If the devices can't be
+    /// created, such as within a user namespace, then they are bound from the
+    /// host `/dev` instead.
+    Dev {
+        target: PathBuf,
+        #[serde(default)]
+        #[serde(deserialize_with = "flag_list")]
         flags: Vec<MountFlags>,
         #[serde(default)]
         make_target: bool,
+        #[serde(default)]
+        target_mode: Option<u32>,
+        #[serde(default)]
+        target_owner: Option<(uid_t, gid_t)>,
+    },
+    /// Remove an existing mount point.
+    ///
+    /// A `detach` will remove the mount point immediately but only clean up
+    /// once the mount is no longer busy. A `force` will attempt to unmount even
+    /// if the mount is busy, which is only supported by some filesystems.
+    Unmount {
+        target: PathBuf,
+        #[serde(default)]
+        detach: bool,
+        #[serde(default)]
+        force: bool,
     },
 }
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum MountFlags {
     /// This simply takes a non-bind mount and adds the bind flag.
@@ -139,7 +382,6 @@ pub enum MountFlags {
     /// Examples  of  workloads  where  this  option  could be of significant
     /// benefit include frequent random writes to preallocated files, as well as
     /// cases where the MS_STRICTATIME mount option is also enabled.
-    #[cfg(not)]
     LazyAccessTime,
     /// Permit mandatory locking on files in this filesystem.
     MandatoryLock,
@@ -154,6 +396,11 @@ pub enum MountFlags {
     /// Do not honor set-user-ID and set-group-ID bits or file capabilities when
     /// executing programs from this mount.
     NoSuid,
+    /// Do not follow symbolic links when resolving paths on this mount.
+    ///
+    /// Symbolic links can still be created and read. This requires Linux 5.10
+    /// or later.
+    NoSymlinkFollow,
     /// Mount read-only.
     ReadOnly,
     /// Update access time on files only if newer than the modification time.
@@ -172,6 +419,61 @@ pub enum MountFlags {
     StrictAccessTime,
     /// Make writes on this mount synchronous.
     Synchronous,
+    /// Apply the change to every mount in the subtree of the target.
+    ///
+    /// This is the same as setting `recursive` on a propagation change.
+    Recursive,
+}
+
+impl MountFlags {
+    /// Parse comma separated options, as given to mount(8), into the flags
+    /// they name and the options that remain to be passed to the filesystem.
+    ///
+    /// ```rust
+    /// # extern crate container;
+    /// # use container::*;
+    /// let (flags, data) = MountFlags::from_options("nodev,nosuid,size=64m");
+    /// ```
+    pub fn from_options(options: &str) -> (Vec<MountFlags>, Option<String>) {
+        let mut flags = Vec::new();
+        let mut data = Vec::new();
+        for option in options.split(',').map(str::trim).filter(|option| !option.is_empty()) {
+            match option.parse() {
+                Ok(flag) => flags.push(flag),
+                Err(_) => data.push(option),
+            }
+        }
+        let data = if data.is_empty() { None } else { Some(data.join(",")) };
+        (flags, data)
+    }
+}
+
+impl FromStr for MountFlags {
+    type Err = Error;
+
+    /// Parse a flag by its name in the options of mount(8), or by the name
+    /// used in the configuration.
+    fn from_str(name: &str) -> Result<MountFlags> {
+        Ok(match name {
+            "bind"                                     => MountFlags::Bind,
+            "dirsync"     | "synchronous_directories"  => MountFlags::SynchronousDirectories,
+            "lazytime"    | "lazy_access_time"         => MountFlags::LazyAccessTime,
+            "mand"        | "mandatory_lock"           => MountFlags::MandatoryLock,
+            "noatime"     | "no_access_time"           => MountFlags::NoAccessTime,
+            "nodev"       | "no_devices"               => MountFlags::NoDevices,
+            "nodiratime"  | "no_directory_access_time" => MountFlags::NoDirectoryAccessTime,
+            "noexec"      | "no_execute"               => MountFlags::NoExecute,
+            "nosuid"      | "no_suid"                  => MountFlags::NoSuid,
+            "nosymfollow" | "no_symlink_follow"        => MountFlags::NoSymlinkFollow,
+            "ro"          | "read_only"                => MountFlags::ReadOnly,
+            "relatime"    | "relative_access_time"     => MountFlags::RelativeAccessTime,
+            "silent"                                   => MountFlags::Silent,
+            "strictatime" | "strict_access_time"       => MountFlags::StrictAccessTime,
+            "sync"        | "synchronous"              => MountFlags::Synchronous,
+            "rec"         | "recursive"                => MountFlags::Recursive,
+            _ => bail!(ErrorKind::UnknownMountFlag(name.to_owned())),
+        })
+    }
 }
 
 impl Into<MsFlags> for MountFlags {
@@ -179,80 +481,118 @@ impl Into<MsFlags> for MountFlags {
         match self {
             MountFlags::Bind                   => MsFlags::MS_BIND,
             MountFlags::SynchronousDirectories => MsFlags::MS_DIRSYNC,
+            // Flags not defined by nix are passed on unmodified.
+            MountFlags::LazyAccessTime         => unsafe {
+                MsFlags::from_bits_unchecked(MS_LAZYTIME)
+            },
             MountFlags::MandatoryLock          => MsFlags::MS_MANDLOCK,
             MountFlags::NoAccessTime           => MsFlags::MS_NOATIME,
             MountFlags::NoDevices              => MsFlags::MS_NODEV,
             MountFlags::NoDirectoryAccessTime  => MsFlags::MS_NODIRATIME,
             MountFlags::NoExecute              => MsFlags::MS_NOEXEC,
             MountFlags::NoSuid                 => MsFlags::MS_NOSUID,
+            MountFlags::NoSymlinkFollow        => unsafe {
+                MsFlags::from_bits_unchecked(MS_NOSYMFOLLOW)
+            },
             MountFlags::ReadOnly               => MsFlags::MS_RDONLY,
             MountFlags::RelativeAccessTime     => MsFlags::MS_RELATIME,
             MountFlags::Silent                 => MsFlags::MS_SILENT,
             MountFlags::StrictAccessTime       => MsFlags::MS_STRICTATIME,
             MountFlags::Synchronous            => MsFlags::MS_SYNCHRONOUS,
+            MountFlags::Recursive              => MsFlags::MS_REC,
         }
     }
 }
 
 
-#[cfg(not)]
+#[allow(dead_code)]
 impl Mount {
     /// Create a new mount from `src` to `target`.
     ///
     /// The file system type must be explicitly provided as along with the
     /// target and the source.
     ///
-    /// ```rust
-    /// Mount::new("/dev/sda1", "/mnt", "ext4").mount();
+    /// ```rust,no_run
+    /// # extern crate container;
+    /// # use container::*;
+    /// # fn main() -> Result<()> {
+    /// Mount::new("/dev/sda1", "/mnt", "ext4").mount()?;
+    /// # Ok(())
+    /// # }
     /// ```
     pub fn new<P: AsRef<Path>>(src: P, target: P, fstype: P) -> Mount {
         Mount::Mount {
             source: src.as_ref().to_owned(),
             target: target.as_ref().to_owned(),
             filesystem_type: fstype.as_ref().to_owned(),
+            data: None,
             flags: Vec::new(),
             make_target: false,
+            target_mode: None,
+            target_owner: None,
         }
     }
 
     /// Update the mount flags on an existing mount.
     ///
-    /// ```rust
-    /// Mount::remount("/home").read_only().mount();
+    /// ```rust,no_run
+    /// # extern crate container;
+    /// # use container::*;
+    /// # fn main() -> Result<()> {
+    /// Mount::remount("/home").read_only().mount()?;
+    /// # Ok(())
+    /// # }
     /// ```
     pub fn remount<P: AsRef<Path>>(target: P) -> Mount {
         Mount::Remount {
             target: target.as_ref().to_owned(),
             flags: Vec::new(),
+            exact: false,
         }
     }
 
     /// Bind a directory to a new mount point.
     ///
-    /// ```rust
-    /// Mount::bind("/lib", "/tmp/jail/lib").mount();
+    /// ```rust,no_run
+    /// # extern crate container;
+    /// # use container::*;
+    /// # fn main() -> Result<()> {
+    /// Mount::bind("/lib", "/tmp/jail/lib").mount()?;
+    /// # Ok(())
+    /// # }
     /// ```
     pub fn bind<P: AsRef<Path>>(src: P, target: P) -> Mount {
         Mount::Bind {
             source: src.as_ref().to_owned(),
             target: target.as_ref().to_owned(),
             flags: Vec::new(),
+            idmap: None,
             make_target: false,
+            target_mode: None,
+            target_owner: None,
         }
     }
 
 
     /// Bind a directory and all mounts in its subtree to a new mount point.
     ///
-    /// ```rust
-    /// Mount::recursive_bind("/proc", "/tmp/jail/proc").mount();
+    /// ```rust,no_run
+    /// # extern crate container;
+    /// # use container::*;
+    /// # fn main() -> Result<()> {
+    /// Mount::recursive_bind("/proc", "/tmp/jail/proc").mount()?;
+    /// # Ok(())
+    /// # }
     /// ```
     pub fn recursive_bind<P: AsRef<Path>>(src: P, target: P) -> Mount {
         Mount::RecursiveBind {
             source: src.as_ref().to_owned(),
             target: target.as_ref().to_owned(),
+            recursive_read_only: false,
             flags: Vec::new(),
             make_target: false,
+            target_mode: None,
+            target_owner: None,
         }
     }
 
@@ -264,6 +604,7 @@ impl Mount {
     pub fn shared<P: AsRef<Path>>(target: P) -> Mount {
         Mount::Shared {
             target: target.as_ref().to_owned(),
+            recursive: false,
             flags: Vec::new(),
         }
     }
@@ -277,6 +618,7 @@ impl Mount {
     pub fn private<P: AsRef<Path>>(target: P) -> Mount {
         Mount::Private {
             target: target.as_ref().to_owned(),
+            recursive: false,
             flags: Vec::new(),
         }
     }
@@ -288,6 +630,7 @@ impl Mount {
     pub fn slave<P: AsRef<Path>>(target: P) -> Mount {
         Mount::Slave {
             target: target.as_ref().to_owned(),
+            recursive: false,
             flags: Vec::new(),
         }
     }
@@ -299,8 +642,106 @@ impl Mount {
     /// bind. Recursive bind mounts will simply have _unbindable_ mounts pruned.
     pub fn unbindable<P: AsRef<Path>>(target: P) -> Mount {
         Mount::Unbindable {
+            target: target.as_ref().to_owned(),
+            recursive: false,
+            flags: Vec::new(),
+        }
+    }
+
+    /// Update an existing mount point, and all mount points in its subtree, to
+    /// be _shared_.
+    pub fn recursive_shared<P: AsRef<Path>>(target: P) -> Mount {
+        Mount::shared(target).recursive()
+    }
+
+    /// Update an existing mount point, and all mount points in its subtree, to
+    /// be _private_.
+    pub fn recursive_private<P: AsRef<Path>>(target: P) -> Mount {
+        Mount::private(target).recursive()
+    }
+
+    /// Update an existing mount point, and all mount points in its subtree, to
+    /// be _slaves_.
+    ///
+    /// This is typically applied to `/` before making any other changes in a
+    /// new mount namespace.
+    ///
+    /// ```rust,no_run
+    /// # extern crate container;
+    /// # use container::*;
+    /// # fn main() -> Result<()> {
+    /// Mount::recursive_slave("/").mount()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn recursive_slave<P: AsRef<Path>>(target: P) -> Mount {
+        Mount::slave(target).recursive()
+    }
+
+    /// Update an existing mount point, and all mount points in its subtree, to
+    /// be _unbindable_.
+    pub fn recursive_unbindable<P: AsRef<Path>>(target: P) -> Mount {
+        Mount::unbindable(target).recursive()
+    }
+
+    /// Mount an image file by attaching it to a loop device.
+    ///
+    /// ```rust,no_run
+    /// # extern crate container;
+    /// # use container::*;
+    /// # fn main() -> Result<()> {
+    /// Mount::loop_image("rootfs.ext4", "/tmp/jail", "ext4").mount()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn loop_image<P: AsRef<Path>>(image: P, target: P, fstype: P) -> Mount {
+        Mount::LoopImage {
+            image: image.as_ref().to_owned(),
+            target: target.as_ref().to_owned(),
+            filesystem_type: fstype.as_ref().to_owned(),
+            read_only: false,
+            flags: Vec::new(),
+            make_target: false,
+            target_mode: None,
+            target_owner: None,
+        }
+    }
+
+    /// Mount a minimal `/dev` containing only the most commonly used devices.
+    ///
+    /// ```rust,no_run
+    /// # extern crate container;
+    /// # use container::*;
+    /// # fn main() -> Result<()> {
+    /// Mount::minimal_dev("/tmp/jail/dev").mount()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn minimal_dev<P: AsRef<Path>>(target: P) -> Mount {
+        Mount::Dev {
             target: target.as_ref().to_owned(),
             flags: Vec::new(),
+            make_target: false,
+            target_mode: None,
+            target_owner: None,
+        }
+    }
+
+    /// Remove an existing mount point.
+    ///
+    /// ```rust,no_run
+    /// # extern crate container;
+    /// # use container::*;
+    /// # fn main() -> Result<()> {
+    /// Mount::unmount("/tmp/jail/proc").detach().mount()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn unmount<P: AsRef<Path>>(target: P) -> Mount {
+        Mount::Unmount {
+            target: target.as_ref().to_owned(),
+            detach: false,
+            force: false,
         }
     }
 
@@ -311,6 +752,148 @@ impl Mount {
             target: target.as_ref().to_owned(),
             flags: Vec::new(),
             make_target: false,
+            target_mode: None,
+            target_owner: None,
+        }
+    }
+
+    /// Mount a new `tmpfs` filesystem at the mount point.
+    ///
+    /// ```rust,no_run
+    /// # extern crate container;
+    /// # use container::*;
+    /// # fn main() -> Result<()> {
+    /// Mount::tmpfs("/tmp/jail/tmp").size(64 * 1024 * 1024).mode(0o1777).mount()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn tmpfs<P: AsRef<Path>>(target: P) -> Mount {
+        Mount::Tmpfs {
+            target: target.as_ref().to_owned(),
+            size: None,
+            mode: None,
+            flags: Vec::new(),
+            make_target: false,
+            target_mode: None,
+            target_owner: None,
+        }
+    }
+
+    /// Mount an `overlay` filesystem combining several directories.
+    ///
+    /// ```rust,no_run
+    /// # extern crate container;
+    /// # use container::*;
+    /// # fn main() -> Result<()> {
+    /// Mount::overlay(&["/image"], "/tmp/upper", "/tmp/work", "/tmp/jail").mount()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn overlay<L, P>(lowerdirs: &[L], upperdir: P, workdir: P, target: P) -> Mount
+    where
+        L: AsRef<Path>,
+        P: AsRef<Path>,
+    {
+        Mount::Overlay {
+            lowerdirs: lowerdirs.iter().map(|l| l.as_ref().to_owned()).collect(),
+            upperdir: upperdir.as_ref().to_owned(),
+            workdir: workdir.as_ref().to_owned(),
+            target: target.as_ref().to_owned(),
+            flags: Vec::new(),
+            make_target: false,
+            target_mode: None,
+            target_owner: None,
+        }
+    }
+
+    /// Mount a new `proc` filesystem at the mount point.
+    ///
+    /// This requires a new PID namespace to show only the processes within
+    /// the container.
+    ///
+    /// ```rust,no_run
+    /// # extern crate container;
+    /// # use container::*;
+    /// # fn main() -> Result<()> {
+    /// Mount::proc("/tmp/jail/proc").mount()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn proc<P: AsRef<Path>>(target: P) -> Mount {
+        Mount::Proc {
+            target: target.as_ref().to_owned(),
+            flags: Vec::new(),
+            make_target: false,
+            target_mode: None,
+            target_owner: None,
+        }
+    }
+
+    /// Mount a new read-only `sysfs` filesystem at the mount point.
+    ///
+    /// ```rust,no_run
+    /// # extern crate container;
+    /// # use container::*;
+    /// # fn main() -> Result<()> {
+    /// Mount::sysfs("/tmp/jail/sys").mount()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn sysfs<P: AsRef<Path>>(target: P) -> Mount {
+        Mount::Sysfs {
+            target: target.as_ref().to_owned(),
+            read_write: false,
+            flags: Vec::new(),
+            make_target: false,
+            target_mode: None,
+            target_owner: None,
+        }
+    }
+
+    /// Mount the unified `cgroup2` hierarchy read-only at the mount point.
+    ///
+    /// The cgroup namespace should be unshared for only the subtree of the
+    /// container to be shown.
+    ///
+    /// ```rust,no_run
+    /// # extern crate container;
+    /// # use container::*;
+    /// # fn main() -> Result<()> {
+    /// Mount::cgroup2("/tmp/jail/sys/fs/cgroup").mount()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn cgroup2<P: AsRef<Path>>(target: P) -> Mount {
+        Mount::Cgroup2 {
+            target: target.as_ref().to_owned(),
+            read_write: false,
+            flags: Vec::new(),
+            make_target: false,
+            target_mode: None,
+            target_owner: None,
+        }
+    }
+
+    /// Mount a new private instance of `devpts` at the mount point.
+    ///
+    /// ```rust,no_run
+    /// # extern crate container;
+    /// # use container::*;
+    /// # fn main() -> Result<()> {
+    /// Mount::devpts("/tmp/jail/dev/pts").mount()?;
+    /// Mount::bind("/tmp/jail/dev/pts/ptmx", "/tmp/jail/dev/ptmx").mount()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn devpts<P: AsRef<Path>>(target: P) -> Mount {
+        Mount::Devpts {
+            target: target.as_ref().to_owned(),
+            mode: None,
+            ptmxmode: None,
+            flags: Vec::new(),
+            make_target: false,
+            target_mode: None,
+            target_owner: None,
         }
     }
 }
@@ -328,76 +911,282 @@ impl Mount {
             Mount::Bind          { flags, .. } => flags.push(flag),
             Mount::RecursiveBind { flags, .. } => flags.push(flag),
             Mount::Relocate      { flags, .. } => flags.push(flag),
+            Mount::Tmpfs         { flags, .. } => flags.push(flag),
+            Mount::Overlay       { flags, .. } => flags.push(flag),
+            Mount::Proc          { flags, .. } => flags.push(flag),
+            Mount::Sysfs         { flags, .. } => flags.push(flag),
+            Mount::Cgroup2       { flags, .. } => flags.push(flag),
+            Mount::Devpts        { flags, .. } => flags.push(flag),
+            Mount::LoopImage     { flags, .. } => flags.push(flag),
+            Mount::Dev           { flags, .. } => flags.push(flag),
+            Mount::Unmount       {..} => {},
         };
         self
     }
 
-    /// If the target directory does not exist, create it.
+    /// Apply a propagation change to the whole subtree of the mount point.
     #[allow(dead_code)]
-    pub fn make_target_dir(self) -> Mount {
-        match self {
-            Mount::Mount {
-                source,
-                target,
-                filesystem_type,
-                flags,
-                ..
-            } => Mount::Mount {
-                source,
-                target,
-                filesystem_type,
-                flags,
-                make_target: true,
-            },
-            Mount::Bind {
-                source,
-                target,
-                flags,
-                ..
-            } => Mount::Bind {
-                make_target: true,
-                source,
-                target,
-                flags,
-            },
-            Mount::RecursiveBind {
-                source,
-                target,
-                flags,
-                ..
-            } => Mount::RecursiveBind {
-                make_target: true,
-                source,
-                target,
-                flags,
-            },
-            Mount::Relocate {
-                source,
-                target,
-                flags,
-                ..
-            } => Mount::Relocate {
-                make_target: true,
-                source,
-                target,
-                flags,
-            },
-            _ => self,
+    pub fn recursive(mut self) -> Mount {
+        match &mut self {
+            Mount::Shared     { recursive, .. } => *recursive = true,
+            Mount::Private    { recursive, .. } => *recursive = true,
+            Mount::Slave      { recursive, .. } => *recursive = true,
+            Mount::Unbindable { recursive, .. } => *recursive = true,
+            _ => {},
         }
+        self
     }
 
-    fn should_make_dir(&self) -> bool {
-        match self {
-            Mount::Mount         { make_target, .. } => *make_target,
-            Mount::Bind          { make_target, .. } => *make_target,
-            Mount::RecursiveBind { make_target, .. } => *make_target,
-            Mount::Relocate      { make_target, .. } => *make_target,
-            _ => false,
+    /// Replace the flags of a remounted mount point with only those given,
+    /// rather than adding to them.
+    #[allow(dead_code)]
+    pub fn exact(mut self) -> Mount {
+        if let Mount::Remount { exact, .. } = &mut self {
+            *exact = true;
         }
+        self
     }
 
-    fn flags(&self) -> MsFlags {
-        let supplied = match self {
+    /// Detach the mount point when unmounting, even if it is busy.
+    #[allow(dead_code)]
+    pub fn detach(mut self) -> Mount {
+        if let Mount::Unmount { detach, .. } = &mut self {
+            *detach = true;
+        }
+        self
+    }
+
+    /// Force unmounting, even if the mount point is busy.
+    #[allow(dead_code)]
+    pub fn force(mut self) -> Mount {
+        if let Mount::Unmount { force, .. } = &mut self {
+            *force = true;
+        }
+        self
+    }
+
+    /// Make every mount point in the subtree of a recursive bind read-only.
+    ///
+    /// ```rust,no_run
+    /// # extern crate container;
+    /// # use container::*;
+    /// # fn main() -> Result<()> {
+    /// Mount::recursive_bind("/", "/tmp/jail").recursive_read_only().mount()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[allow(dead_code)]
+    pub fn recursive_read_only(mut self) -> Mount {
+        if let Mount::RecursiveBind { recursive_read_only, .. } = &mut self {
+            *recursive_read_only = true;
+        }
+        self
+    }
+
+    /// Allow a `sysfs` or `cgroup2` mount to be written to.
+    #[allow(dead_code)]
+    pub fn read_write(mut self) -> Mount {
+        match &mut self {
+            Mount::Sysfs   { read_write, .. } => *read_write = true,
+            Mount::Cgroup2 { read_write, .. } => *read_write = true,
+            _ => {},
+        }
+        self
+    }
+
+    /// Set the mode of the `ptmx` device of a `devpts` mount.
+    #[allow(dead_code)]
+    pub fn ptmxmode(mut self, octal: u32) -> Mount {
+        if let Mount::Devpts { ptmxmode, .. } = &mut self {
+            *ptmxmode = Some(octal);
+        }
+        self
+    }
+
+    /// Set the filesystem specific options of a mount.
+    ///
+    /// ```rust,no_run
+    /// # extern crate container;
+    /// # use container::*;
+    /// # fn main() -> Result<()> {
+    /// Mount::new("/dev/sda1", "/mnt", "ext4").data("data=journal").mount()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[allow(dead_code)]
+    pub fn data<S: Into<String>>(mut self, options: S) -> Mount {
+        if let Mount::Mount { data, .. } = &mut self {
+            *data = Some(options.into());
+        }
+        self
+    }
+
+    /// Set the size limit, in bytes, of a `tmpfs` mount.
+    #[allow(dead_code)]
+    pub fn size(mut self, bytes: u64) -> Mount {
+        if let Mount::Tmpfs { size, .. } = &mut self {
+            *size = Some(bytes);
+        }
+        self
+    }
+
+    /// Set the mode of the root directory of a `tmpfs` mount or of new
+    /// pseudo-terminals in a `devpts` mount.
+    #[allow(dead_code)]
+    pub fn mode(mut self, octal: u32) -> Mount {
+        match &mut self {
+            Mount::Tmpfs  { mode, .. } => *mode = Some(octal),
+            Mount::Devpts { mode, .. } => *mode = Some(octal),
+            _ => {},
+        }
+        self
+    }
+
+    /// Add several flags to the mount.
+    ///
+    /// ```rust,no_run
+    /// # extern crate container;
+    /// # use container::*;
+    /// # fn main() -> Result<()> {
+    /// Mount::bind("/lib", "/tmp/jail/lib")
+    ///     .flags(vec![MountFlags::ReadOnly, MountFlags::NoSuid])
+    ///     .mount()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[allow(dead_code)]
+    pub fn flags<I: IntoIterator<Item = MountFlags>>(self, flags: I) -> Mount {
+        flags.into_iter().fold(self, Mount::add_flag)
+    }
+
+    /// Mount read-only.
+    #[allow(dead_code)]
+    pub fn read_only(self) -> Mount {
+        self.add_flag(MountFlags::ReadOnly)
+    }
+
+    /// Do not allow programs to be executed from this mount.
+    #[allow(dead_code)]
+    pub fn no_exec(self) -> Mount {
+        self.add_flag(MountFlags::NoExecute)
+    }
+
+    /// Do not honor set-user-ID and set-group-ID bits or file capabilities when
+    /// executing programs from this mount.
+    #[allow(dead_code)]
+    pub fn no_suid(self) -> Mount {
+        self.add_flag(MountFlags::NoSuid)
+    }
+
+    /// Do not allow access to devices (special files) on this mount.
+    #[allow(dead_code)]
+    pub fn no_dev(self) -> Mount {
+        self.add_flag(MountFlags::NoDevices)
+    }
+
+    /// If the target directory does not exist, create it.
+    #[allow(dead_code)]
+    pub fn make_target_dir(mut self) -> Mount {
+        match &mut self {
+            Mount::Mount         { make_target, .. } => *make_target = true,
+            Mount::Bind          { make_target, .. } => *make_target = true,
+            Mount::RecursiveBind { make_target, .. } => *make_target = true,
+            Mount::Relocate      { make_target, .. } => *make_target = true,
+            Mount::Tmpfs         { make_target, .. } => *make_target = true,
+            Mount::Overlay       { make_target, .. } => *make_target = true,
+            Mount::Proc          { make_target, .. } => *make_target = true,
+            Mount::Sysfs         { make_target, .. } => *make_target = true,
+            Mount::Cgroup2       { make_target, .. } => *make_target = true,
+            Mount::Devpts        { make_target, .. } => *make_target = true,
+            Mount::LoopImage     { make_target, .. } => *make_target = true,
+            Mount::Dev           { make_target, .. } => *make_target = true,
+            _ => {},
+        }
+        self
+    }
+
+    /// Set the mode of the target if it is created.
+    #[allow(dead_code)]
+    pub fn target_mode(mut self, mode: u32) -> Mount {
+        match &mut self {
+            Mount::Mount         { target_mode, .. } => *target_mode = Some(mode),
+            Mount::Bind          { target_mode, .. } => *target_mode = Some(mode),
+            Mount::RecursiveBind { target_mode, .. } => *target_mode = Some(mode),
+            Mount::Relocate      { target_mode, .. } => *target_mode = Some(mode),
+            Mount::Tmpfs         { target_mode, .. } => *target_mode = Some(mode),
+            Mount::Overlay       { target_mode, .. } => *target_mode = Some(mode),
+            Mount::Proc          { target_mode, .. } => *target_mode = Some(mode),
+            Mount::Sysfs         { target_mode, .. } => *target_mode = Some(mode),
+            Mount::Cgroup2       { target_mode, .. } => *target_mode = Some(mode),
+            Mount::Devpts        { target_mode, .. } => *target_mode = Some(mode),
+            Mount::LoopImage     { target_mode, .. } => *target_mode = Some(mode),
+            Mount::Dev           { target_mode, .. } => *target_mode = Some(mode),
+            _ => {},
+        }
+        self
+    }
+
+    /// Set the owner of the target if it is created.
+    #[allow(dead_code)]
+    pub fn target_owner(mut self, uid: uid_t, gid: gid_t) -> Mount {
+        let owner = Some((uid, gid));
+        match &mut self {
+            Mount::Mount         { target_owner, .. } => *target_owner = owner,
+            Mount::Bind          { target_owner, .. } => *target_owner = owner,
+            Mount::RecursiveBind { target_owner, .. } => *target_owner = owner,
+            Mount::Relocate      { target_owner, .. } => *target_owner = owner,
+            Mount::Tmpfs         { target_owner, .. } => *target_owner = owner,
+            Mount::Overlay       { target_owner, .. } => *target_owner = owner,
+            Mount::Proc          { target_owner, .. } => *target_owner = owner,
+            Mount::Sysfs         { target_owner, .. } => *target_owner = owner,
+            Mount::Cgroup2       { target_owner, .. } => *target_owner = owner,
+            Mount::Devpts        { target_owner, .. } => *target_owner = owner,
+            Mount::LoopImage     { target_owner, .. } => *target_owner = owner,
+            Mount::Dev           { target_owner, .. } => *target_owner = owner,
+            _ => {},
+        }
+        self
+    }
+
+    fn should_make_dir(&self) -> bool {
+        match self {
+            Mount::Mount         { make_target, .. } => *make_target,
+            Mount::Bind          { make_target, .. } => *make_target,
+            Mount::RecursiveBind { make_target, .. } => *make_target,
+            Mount::Relocate      { make_target, .. } => *make_target,
+            Mount::Tmpfs         { make_target, .. } => *make_target,
+            Mount::Overlay       { make_target, .. } => *make_target,
+            Mount::Proc          { make_target, .. } => *make_target,
+            Mount::Sysfs         { make_target, .. } => *make_target,
+            Mount::Cgroup2       { make_target, .. } => *make_target,
+            Mount::Devpts        { make_target, .. } => *make_target,
+            Mount::LoopImage     { make_target, .. } => *make_target,
+            Mount::Dev           { make_target, .. } => *make_target,
+            _ => false,
+        }
+    }
+
+    /// The mode and owner to give the target if it is created.
+    fn target_attributes(&self) -> (Option<u32>, Option<(uid_t, gid_t)>) {
+        match self {
+            Mount::Mount         { target_mode, target_owner, .. } => (*target_mode, *target_owner),
+            Mount::Bind          { target_mode, target_owner, .. } => (*target_mode, *target_owner),
+            Mount::RecursiveBind { target_mode, target_owner, .. } => (*target_mode, *target_owner),
+            Mount::Relocate      { target_mode, target_owner, .. } => (*target_mode, *target_owner),
+            Mount::Tmpfs         { target_mode, target_owner, .. } => (*target_mode, *target_owner),
+            Mount::Overlay       { target_mode, target_owner, .. } => (*target_mode, *target_owner),
+            Mount::Proc          { target_mode, target_owner, .. } => (*target_mode, *target_owner),
+            Mount::Sysfs         { target_mode, target_owner, .. } => (*target_mode, *target_owner),
+            Mount::Cgroup2       { target_mode, target_owner, .. } => (*target_mode, *target_owner),
+            Mount::Devpts        { target_mode, target_owner, .. } => (*target_mode, *target_owner),
+            Mount::LoopImage     { target_mode, target_owner, .. } => (*target_mode, *target_owner),
+            Mount::Dev           { target_mode, target_owner, .. } => (*target_mode, *target_owner),
+            _ => (None, None),
+        }
+    }
+
+    fn supplied_flags(&self) -> &[MountFlags] {
+        match self {
             Mount::Mount         { flags, .. } => flags,
             Mount::Remount       { flags, .. } => flags,
             Mount::Shared        { flags, .. } => flags,
@@ -407,7 +1196,19 @@ impl Mount {
             Mount::Bind          { flags, .. } => flags,
             Mount::RecursiveBind { flags, .. } => flags,
             Mount::Relocate      { flags, .. } => flags,
-        };
+            Mount::Tmpfs         { flags, .. } => flags,
+            Mount::Overlay       { flags, .. } => flags,
+            Mount::Proc          { flags, .. } => flags,
+            Mount::Sysfs         { flags, .. } => flags,
+            Mount::Cgroup2       { flags, .. } => flags,
+            Mount::Devpts        { flags, .. } => flags,
+            Mount::LoopImage     { flags, .. } => flags,
+            Mount::Dev           { flags, .. } => flags,
+            Mount::Unmount       {..} => &[],
+        }
+    }
+
+    fn ms_flags(&self) -> MsFlags {
         let default = match self {
             Mount::Mount         {..} => MsFlags::empty(),
             Mount::Remount       {..} => MsFlags::MS_REMOUNT,
@@ -418,13 +1219,90 @@ impl Mount {
             Mount::Bind          {..} => MsFlags::MS_BIND,
             Mount::RecursiveBind {..} => MsFlags::MS_BIND | MsFlags::MS_REC,
             Mount::Relocate      {..} => MsFlags::MS_MOVE,
+            Mount::Tmpfs         {..} => MsFlags::empty(),
+            Mount::Overlay       {..} => MsFlags::empty(),
+            Mount::Proc          {..} => MsFlags::MS_NOSUID
+                                       | MsFlags::MS_NODEV
+                                       | MsFlags::MS_NOEXEC,
+            Mount::Sysfs { read_write: true, .. } => MsFlags::MS_NOSUID
+                                                   | MsFlags::MS_NODEV
+                                                   | MsFlags::MS_NOEXEC,
+            Mount::Sysfs         {..} => MsFlags::MS_NOSUID
+                                       | MsFlags::MS_NODEV
+                                       | MsFlags::MS_NOEXEC
+                                       | MsFlags::MS_RDONLY,
+            Mount::Cgroup2 { read_write: true, .. } => MsFlags::MS_NOSUID
+                                                     | MsFlags::MS_NODEV
+                                                     | MsFlags::MS_NOEXEC,
+            Mount::Cgroup2       {..} => MsFlags::MS_NOSUID
+                                       | MsFlags::MS_NODEV
+                                       | MsFlags::MS_NOEXEC
+                                       | MsFlags::MS_RDONLY,
+            Mount::Devpts        {..} => MsFlags::MS_NOSUID | MsFlags::MS_NOEXEC,
+            Mount::LoopImage { read_only: true, .. } => MsFlags::MS_RDONLY,
+            Mount::LoopImage     {..} => MsFlags::empty(),
+            Mount::Dev           {..} => MsFlags::MS_NOSUID | MsFlags::MS_NOEXEC,
+            Mount::Unmount       {..} => MsFlags::empty(),
+        };
+
+        let recursive = match self {
+            Mount::Shared     { recursive: true, .. } => MsFlags::MS_REC,
+            Mount::Private    { recursive: true, .. } => MsFlags::MS_REC,
+            Mount::Slave      { recursive: true, .. } => MsFlags::MS_REC,
+            Mount::Unbindable { recursive: true, .. } => MsFlags::MS_REC,
+            _ => MsFlags::empty(),
         };
 
-        let supplied: MsFlags = supplied.iter().map(|f| f.clone().into()).collect();
-        supplied | default
+        let supplied: MsFlags = self.supplied_flags().iter()
+            .map(|f| f.clone().into())
+            .collect();
+        supplied | default | recursive
+    }
+
+    /// Flags for a second remount needed to apply flags ignored by a bind.
+    ///
+    /// A bind mount ignores all flags other than those for the bind itself, so
+    /// a bind with any other flags, such as read-only or no_exec, must be
+    /// remounted for them to take effect.
+    fn remount_flags(&self) -> Option<MsFlags> {
+        let supplied = match self {
+            Mount::Bind          { flags, .. } => flags,
+            Mount::RecursiveBind { flags, .. } => flags,
+            _ => return None,
+        };
+
+        let supplied: Vec<_> = supplied.iter()
+            .filter(|&flag| *flag != MountFlags::Bind && *flag != MountFlags::Recursive)
+            .collect();
+        if supplied.is_empty() {
+            return None;
+        }
+
+        let supplied: MsFlags = supplied.into_iter()
+            .map(|f| f.clone().into())
+            .collect();
+        Some(supplied | MsFlags::MS_BIND | MsFlags::MS_REMOUNT)
+    }
+
+    /// The paths that must exist before the mount is made, other than the
+    /// target.
+    pub fn sources(&self) -> Vec<&Path> {
+        match self {
+            Mount::Bind          { source, .. } => vec![source.as_path()],
+            Mount::RecursiveBind { source, .. } => vec![source.as_path()],
+            Mount::Relocate      { source, .. } => vec![source.as_path()],
+            Mount::Overlay { lowerdirs, upperdir, workdir, .. } => {
+                let mut paths: Vec<_> = lowerdirs.iter().map(PathBuf::as_path).collect();
+                paths.extend(vec![upperdir.as_path(), workdir.as_path()]);
+                paths
+            },
+            Mount::LoopImage     { image, .. } => vec![image.as_path()],
+            _ => Vec::new(),
+        }
     }
 
-    fn target(&self) -> &Path {
+    /// The path of the mount point.
+    pub fn target(&self) -> &Path {
         match self {
             Mount::Mount         { target, .. } => target.as_path(),
             Mount::Remount       { target, .. } => target.as_path(),
@@ -435,6 +1313,15 @@ impl Mount {
             Mount::Bind          { target, .. } => target.as_path(),
             Mount::RecursiveBind { target, .. } => target.as_path(),
             Mount::Relocate      { target, .. } => target.as_path(),
+            Mount::Tmpfs         { target, .. } => target.as_path(),
+            Mount::Overlay       { target, .. } => target.as_path(),
+            Mount::Proc          { target, .. } => target.as_path(),
+            Mount::Sysfs         { target, .. } => target.as_path(),
+            Mount::Cgroup2       { target, .. } => target.as_path(),
+            Mount::Devpts        { target, .. } => target.as_path(),
+            Mount::LoopImage     { target, .. } => target.as_path(),
+            Mount::Dev           { target, .. } => target.as_path(),
+            Mount::Unmount       { target, .. } => target.as_path(),
         }
     }
 
@@ -444,36 +1331,984 @@ impl Mount {
             Mount::Bind          { source, .. } => Some(source.as_path()),
             Mount::RecursiveBind { source, .. } => Some(source.as_path()),
             Mount::Relocate      { source, .. } => Some(source.as_path()),
+            Mount::Tmpfs         {..} => Some(Path::new("tmpfs")),
+            Mount::Overlay       {..} => Some(Path::new("overlay")),
+            Mount::Proc          {..} => Some(Path::new("proc")),
+            Mount::Sysfs         {..} => Some(Path::new("sysfs")),
+            Mount::Cgroup2       {..} => Some(Path::new("cgroup2")),
+            Mount::Devpts        {..} => Some(Path::new("devpts")),
+            Mount::Dev           {..} => Some(Path::new("tmpfs")),
             _ => None,
         }
     }
 
+    /// All of the paths used by the mount.
+    fn paths_mut(&mut self) -> Vec<&mut PathBuf> {
+        match self {
+            Mount::Mount         { source, target, .. } => vec![source, target],
+            Mount::Remount       { target, .. } => vec![target],
+            Mount::Shared        { target, .. } => vec![target],
+            Mount::Private       { target, .. } => vec![target],
+            Mount::Slave         { target, .. } => vec![target],
+            Mount::Unbindable    { target, .. } => vec![target],
+            Mount::Bind          { source, target, .. } => vec![source, target],
+            Mount::RecursiveBind { source, target, .. } => vec![source, target],
+            Mount::Relocate      { source, target, .. } => vec![source, target],
+            Mount::Tmpfs         { target, .. } => vec![target],
+            Mount::Overlay {
+                lowerdirs,
+                upperdir,
+                workdir,
+                target,
+                ..
+            } => {
+                let mut paths: Vec<_> = lowerdirs.iter_mut().collect();
+                paths.extend(vec![upperdir, workdir, target]);
+                paths
+            },
+            Mount::Proc          { target, .. } => vec![target],
+            Mount::Sysfs         { target, .. } => vec![target],
+            Mount::Cgroup2       { target, .. } => vec![target],
+            Mount::Devpts        { target, .. } => vec![target],
+            Mount::LoopImage     { image, target, .. } => vec![image, target],
+            Mount::Dev           { target, .. } => vec![target],
+            Mount::Unmount       { target, .. } => vec![target],
+        }
+    }
+
+    /// Resolve the source of a bind to a canonical path, ensuring that it is
+    /// within the given prefix.
+    ///
+    /// This prevents symbolic links in the source from binding a directory
+    /// outside of the prefix.
+    pub fn confine_source<P: AsRef<Path>>(mut self, prefix: P) -> Result<Mount> {
+        let source = match &mut self {
+            Mount::Bind          { source, .. } => source,
+            Mount::RecursiveBind { source, .. } => source,
+            _ => return Ok(self),
+        };
+
+        let prefix = prefix.as_ref().canonicalize()?;
+        let canonical = source.canonicalize()?;
+        ensure!(
+            canonical.starts_with(&prefix),
+            ErrorKind::SourceOutsidePrefix(source.clone(), prefix)
+        );
+        *source = canonical;
+
+        Ok(self)
+    }
+
+    /// A description of the mount in a similar format to mount(8).
+    ///
+    /// ```rust
+    /// # extern crate container;
+    /// # use container::*;
+    /// println!("{}", Mount::proc("/tmp/jail/proc").describe());
+    /// ```
+    pub fn describe(&self) -> String {
+        if let Mount::Unmount { target, detach, force } = self {
+            return format!(
+                "umount {} (detach: {}, force: {})",
+                target.display(), detach, force
+            );
+        }
+
+        let source = match self {
+            Mount::LoopImage { image, .. } => format!("{} (loop)", image.display()),
+            _ => self.source()
+                .map(|source| source.display().to_string())
+                .unwrap_or_else(|| "none".to_owned()),
+        };
+        let filesystem_type = self.filesystem_type()
+            .map(|fstype| fstype.display().to_string())
+            .unwrap_or_else(|| "none".to_owned());
+        let data = self.options().unwrap_or_else(|| "none".to_owned());
+
+        let mut description = format!(
+            "mount {} on {} type {} flags {:?} data {}",
+            source, self.target().display(), filesystem_type, self.ms_flags(), data
+        );
+        if let Mount::Bind { idmap: Some(idmap), .. } = self {
+            description.push_str(&format!("; {}", idmap.describe()));
+        }
+        if let Some(flags) = self.remount_flags() {
+            description.push_str(&format!("; remount flags {:?}", flags));
+        }
+        if let Mount::Remount { exact: false, .. } = self {
+            description.push_str(" and existing flags");
+        }
+        description
+    }
+
+    /// Expand the environment variables in all of the paths of the mount.
+    pub fn expand_env(mut self) -> Result<Mount> {
+        for path in self.paths_mut() {
+            *path = expand_path(&path)?;
+        }
+        Ok(self)
+    }
+
     fn filesystem_type(&self) -> Option<&Path> {
         match self {
             Mount::Mount { filesystem_type, .. } => Some(filesystem_type.as_path()),
+            Mount::Tmpfs {..} => Some(Path::new("tmpfs")),
+            Mount::Overlay {..} => Some(Path::new("overlay")),
+            Mount::Proc {..} => Some(Path::new("proc")),
+            Mount::Sysfs {..} => Some(Path::new("sysfs")),
+            Mount::Cgroup2 {..} => Some(Path::new("cgroup2")),
+            Mount::Devpts {..} => Some(Path::new("devpts")),
+            Mount::LoopImage { filesystem_type, .. } => Some(filesystem_type.as_path()),
+            Mount::Dev {..} => Some(Path::new("tmpfs")),
             _ => None,
         }
     }
+
+    /// Filesystem specific options passed as the data argument of `mount(2)`.
+    fn options(&self) -> Option<String> {
+        let options = match self {
+            Mount::Mount { data, .. } => data.iter().cloned().collect(),
+            Mount::Tmpfs { size, mode, .. } => {
+                let mut options = Vec::new();
+                if let Some(size) = size {
+                    options.push(format!("size={}", size));
+                }
+                if let Some(mode) = mode {
+                    options.push(format!("mode={:04o}", mode));
+                }
+                options
+            },
+            Mount::Overlay { lowerdirs, upperdir, workdir, .. } => {
+                let lowerdirs: Vec<_> = lowerdirs.iter()
+                    .map(|l| l.display().to_string())
+                    .collect();
+                vec![
+                    format!("lowerdir={}", lowerdirs.join(":")),
+                    format!("upperdir={}", upperdir.display()),
+                    format!("workdir={}", workdir.display()),
+                ]
+            },
+            Mount::Devpts { mode, ptmxmode, .. } => vec![
+                "newinstance".to_owned(),
+                format!("ptmxmode={:04o}", ptmxmode.unwrap_or(0o666)),
+                format!("mode={:04o}", mode.unwrap_or(0o620)),
+            ],
+            Mount::Dev {..} => vec!["mode=0755".to_owned()],
+            _ => Vec::new(),
+        };
+
+        if options.is_empty() {
+            None
+        } else {
+            Some(options.join(","))
+        }
+    }
+
+    /// Whether the mount binds a single file rather than a directory.
+    ///
+    /// Anything other than a directory, such as a device node, can only be
+    /// bound over a file.
+    fn binds_file(&self) -> bool {
+        match self {
+            Mount::Bind          { source, .. } => !source.is_dir(),
+            Mount::RecursiveBind { source, .. } => !source.is_dir(),
+            _ => false,
+        }
+    }
+
+    /// Create the target of the mount.
+    ///
+    /// When binding a file, the target must also be a file so an empty file is
+    /// created in place of a directory. The mode and owner are only set if the
+    /// target did not already exist.
+    fn create_target(&self) -> Result<()> {
+        let target = self.target();
+        if target.exists() {
+            return ok!();
+        }
+
+        if self.binds_file() {
+            if let Some(parent) = target.parent() {
+                create_dir_all(parent)?;
+            }
+            OpenOptions::new().write(true).create(true).open(target)?;
+        } else {
+            create_dir_all(target)?;
+        }
+
+        let (mode, owner) = self.target_attributes();
+        if let Some(mode) = mode {
+            set_permissions(target, Permissions::from_mode(mode))
+                .chain_err(|| ErrorKind::SetTargetMode(target.to_owned()))?;
+        }
+        if let Some((uid, gid)) = owner {
+            chown(target, Some(Uid::from_raw(uid)), Some(Gid::from_raw(gid)))
+                .chain_err(|| ErrorKind::SetTargetOwner(target.to_owned()))?;
+        }
+
+        ok!()
+    }
+
+    /// Whether the mount creates a new mount point rather than modifying an
+    /// existing one.
+    fn creates_mount(&self) -> bool {
+        match self {
+            Mount::Remount    {..} => false,
+            Mount::Shared     {..} => false,
+            Mount::Private    {..} => false,
+            Mount::Slave      {..} => false,
+            Mount::Unbindable {..} => false,
+            Mount::Unmount    {..} => false,
+            _ => true,
+        }
+    }
+
+    /// Whether an equivalent mount is already mounted at the target.
+    ///
+    /// Changes to existing mount points are never considered to already be
+    /// mounted as they are always safe to reapply.
+    pub fn is_mounted(&self) -> Result<bool> {
+        if !self.creates_mount() || !self.target().exists() {
+            return Ok(false);
+        }
+
+        let target = self.target().canonicalize()?;
+        let existing = mounts()?.into_iter()
+            .filter(|info| info.mount_point == target)
+            .last();
+        let existing = match existing {
+            Some(existing) => existing,
+            None => return Ok(false),
+        };
+
+        let mounted = match self {
+            Mount::Bind { source, .. }
+            | Mount::RecursiveBind { source, .. }
+            | Mount::Relocate { source, .. } => {
+                // A bind of the source leaves the target as the same inode.
+                let source = metadata(source)?;
+                let target = metadata(&target)?;
+                source.dev() == target.dev() && source.ino() == target.ino()
+            },
+            // The source is whichever loop device was free when mounted.
+            Mount::LoopImage { filesystem_type, .. } => {
+                Path::new(&existing.filesystem_type) == filesystem_type.as_path()
+            },
+            _ => {
+                self.filesystem_type() == Some(Path::new(&existing.filesystem_type))
+                    && self.source() == Some(Path::new(&existing.source))
+            },
+        };
+
+        Ok(mounted)
+    }
+
+    /// Check the mount is valid before attempting it.
+    fn validate(&self) -> Result<()> {
+        if let Mount::Overlay { upperdir, workdir, .. } = self {
+            let upper = metadata(upperdir)?;
+            let work = metadata(workdir)?;
+            ensure!(upper.dev() == work.dev(), ErrorKind::OverlayFilesystem);
+        }
+
+        let explicit_type = match self {
+            Mount::Mount     { filesystem_type, .. } => Some(filesystem_type),
+            Mount::LoopImage { filesystem_type, .. } => Some(filesystem_type),
+            _ => None,
+        };
+        if let Some(filesystem_type) = explicit_type {
+            let name = filesystem_type.to_string_lossy();
+            ensure!(
+                filesystem_supported(&name),
+                ErrorKind::UnknownFilesystem(name.into_owned())
+            );
+        }
+
+        ok!()
+    }
 }
 
 impl Mount {
     /// Mount using the given specification.
     pub fn mount(self) -> Result<()> {
+        if let Mount::Unmount { target, detach, force } = &self {
+            let mut flags = MntFlags::empty();
+            if *detach {
+                flags |= MntFlags::MNT_DETACH;
+            }
+            if *force {
+                flags |= MntFlags::MNT_FORCE;
+            }
+            return retry_interrupted(|| umount2(target, flags))
+                .chain_err(|| ErrorKind::Unmount(target.clone()));
+        }
+
+        self.validate()?;
 
         if self.should_make_dir() {
-            create_dir_all(self.target())?;
+            self.create_target()?;
         }
 
-        let data: Option<&PathBuf> = None;
+        let data = match self.options() {
+            Some(options) => Some(CString::new(options)?),
+            None => None,
+        };
 
-        mount(
-            self.source(),
-            self.target(),
-            self.filesystem_type(),
-            self.flags(),
-            data
-        )?;
+        // The loop device must remain open until it has been mounted.
+        let device = match &self {
+            Mount::LoopImage { image, .. } => {
+                let read_only = self.ms_flags().contains(MsFlags::MS_RDONLY);
+                let device = LoopDevice::attach(image, read_only)
+                    .chain_err(|| ErrorKind::LoopDevice(image.clone()))?;
+                Some(device)
+            },
+            _ => None,
+        };
+        let source = match &device {
+            Some(device) => Some(device.path()),
+            None => self.source(),
+        };
+
+        let flags = match self {
+            Mount::Remount { exact: false, .. } => {
+                with_current_flags(self.target(), self.ms_flags())?
+            },
+            _ => self.ms_flags(),
+        };
+
+        match &self {
+            Mount::Bind { source, idmap: Some(idmap), .. } => idmap.bind(source, self.target())?,
+            _ => retry_interrupted(|| mount(
+                source,
+                self.target(),
+                self.filesystem_type(),
+                flags,
+                data.as_ref().map(CString::as_c_str)
+            )).map_err(|err| match (&self, err) {
+                (Mount::Proc {..}, NixError::Sys(Errno::EPERM)) => {
+                    ErrorKind::ProcPidNamespace.into()
+                },
+                (_, err) => Error::from(err),
+            })?,
+        }
+
+        // The bind keeps the flags of its source, some of which may be locked.
+        if let Some(flags) = self.remount_flags() {
+            let flags = with_current_flags(self.target(), flags)?;
+            let none: Option<&Path> = None;
+            retry_interrupted(|| mount(none, self.target(), none, flags, none))?;
+        }
+
+        if let Mount::RecursiveBind { recursive_read_only: true, .. } = self {
+            remount_read_only(self.target())?;
+        }
+
+        if let Mount::Dev {..} = self {
+            populate_dev(self.target())?;
+        }
 
         Ok(())
     }
+
+    /// Mount using the given specification, unmounting it again when the
+    /// returned guard is dropped.
+    ///
+    /// Changes to existing mount points, such as remounts and propagation
+    /// changes, are not undone by the guard.
+    ///
+    /// ```rust,no_run
+    /// # extern crate container;
+    /// # use container::*;
+    /// # fn main() -> Result<()> {
+    /// let proc = Mount::proc("/tmp/jail/proc").mount_scoped()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[allow(dead_code)]
+    pub fn mount_scoped(self) -> Result<MountGuard> {
+        let target = if self.creates_mount() {
+            Some(self.target().to_owned())
+        } else {
+            None
+        };
+
+        self.mount()?;
+
+        Ok(MountGuard { target })
+    }
+}
+
+/// A mount point that is unmounted when dropped.
+///
+/// The mount is detached from the filesystem along with any mount points in
+/// its subtree.
+#[derive(Debug)]
+#[must_use]
+pub struct MountGuard {
+    target: Option<PathBuf>,
+}
+
+impl MountGuard {
+    /// Leave the mount point in place when the guard is dropped.
+    #[allow(dead_code)]
+    pub fn forget(mut self) {
+        self.target.take();
+    }
+
+    /// Update the target to be relative to a new root directory.
+    fn rebase(&mut self, root: &Path) {
+        if let Some(target) = self.target.take() {
+            self.target = match target.strip_prefix(root) {
+                Ok(relative) => Some(Path::new("/").join(relative)),
+                Err(_) => Some(target.clone()),
+            };
+        }
+    }
+}
+
+impl Drop for MountGuard {
+    fn drop(&mut self) {
+        if let Some(target) = self.target.take() {
+            detach(&target);
+        }
+    }
+}
+
+/// Detach a mount point and its subtree, ignoring any failure as there is no
+/// one to report it to.
+#[cfg(not(test))]
+fn detach(target: &Path) {
+    let _ = retry_interrupted(|| umount2(target, MntFlags::MNT_DETACH));
+}
+
+/// Record the mount point rather than detaching it, so that the guards of
+/// tests never unmount anything.
+#[cfg(test)]
+fn detach(target: &Path) {
+    tests::record_unmount(target);
+}
+
+/// Remount every mount point in a subtree as read-only.
+///
+/// The flags of each mount point are preserved as some may be locked in place
+/// if the mount was made in a more privileged namespace.
+fn remount_read_only(target: &Path) -> Result<()> {
+    let target = target.canonicalize()?;
+    let submounts = mounts()?.into_iter()
+        .filter(|info| info.mount_point.starts_with(&target));
+
+    for info in submounts {
+        let flags = info.flags()
+            | MsFlags::MS_BIND
+            | MsFlags::MS_REMOUNT
+            | MsFlags::MS_RDONLY;
+        let none: Option<&Path> = None;
+        retry_interrupted(|| mount(none, &info.mount_point, none, flags, none))
+            .chain_err(|| ErrorKind::RemountReadOnly(info.mount_point.clone()))?;
+    }
+
+    ok!()
+}
+
+/// Add the flags a mount point already has to those of a remount.
+fn with_current_flags(target: &Path, flags: MsFlags) -> Result<MsFlags> {
+    let target = target.canonicalize()?;
+    let current = mounts()?.into_iter()
+        .filter(|info| info.mount_point == target)
+        .last()
+        .map(|info| info.flags())
+        .unwrap_or_else(MsFlags::empty);
+
+    Ok(combine_flags(current, flags))
+}
+
+/// Add the current flags of a mount point to those of a remount.
+///
+/// The access time flags replace each other, so the existing one is only kept
+/// if none is given.
+fn combine_flags(mut current: MsFlags, flags: MsFlags) -> MsFlags {
+    let access_time = MsFlags::MS_NOATIME | MsFlags::MS_RELATIME | MsFlags::MS_STRICTATIME;
+    if flags.intersects(access_time) {
+        current.remove(access_time);
+    }
+
+    current | flags
+}
+
+/// The number of times a system call is retried after being interrupted.
+const INTERRUPT_RETRIES: usize = 8;
+
+/// Retry a system call that was interrupted by a signal.
+///
+/// Any other error is returned immediately, as is the error from the final
+/// attempt if it is interrupted too many times.
+pub fn retry_interrupted<T, F>(mut call: F) -> ::nix::Result<T>
+where
+    F: FnMut() -> ::nix::Result<T>,
+{
+    let mut retries = 0;
+    loop {
+        match call() {
+            Err(NixError::Sys(Errno::EINTR)) if retries < INTERRUPT_RETRIES => {
+                retries += 1;
+            },
+            result => return result,
+        }
+    }
+}
+
+/// Character devices created in a minimal `/dev` with their major and minor
+/// numbers.
+const DEVICES: &[(&str, u64, u64)] = &[
+    ("null",    1, 3),
+    ("zero",    1, 5),
+    ("full",    1, 7),
+    ("random",  1, 8),
+    ("urandom", 1, 9),
+    ("tty",     5, 0),
+];
+
+/// Symbolic links created in a minimal `/dev`.
+const DEVICE_LINKS: &[(&str, &str)] = &[
+    ("fd",     "/proc/self/fd"),
+    ("stdin",  "/proc/self/fd/0"),
+    ("stdout", "/proc/self/fd/1"),
+    ("stderr", "/proc/self/fd/2"),
+    ("ptmx",   "pts/ptmx"),
+];
+
+/// Create the devices for a minimal `/dev`.
+///
+/// Devices that can't be created are instead bound from the host.
+fn populate_dev(target: &Path) -> Result<()> {
+    let mode = Mode::from_bits_truncate(0o666);
+
+    for &(name, major, minor) in DEVICES {
+        let device = target.join(name);
+        let created = mknod(&device, SFlag::S_IFCHR, mode, makedev(major, minor));
+        match created {
+            Ok(()) => {},
+            Err(NixError::Sys(Errno::EPERM)) => {
+                Mount::bind(Path::new("/dev").join(name), device.clone())
+                    .make_target_dir()
+                    .mount()
+                    .chain_err(|| ErrorKind::CreateDevice(device.clone()))?;
+            },
+            Err(err) => {
+                return Err(err).chain_err(|| ErrorKind::CreateDevice(device.clone()));
+            },
+        }
+    }
+
+    for &(name, destination) in DEVICE_LINKS {
+        let link = target.join(name);
+        symlink(destination, &link)
+            .chain_err(|| ErrorKind::CreateDevice(link.clone()))?;
+    }
+
+    ok!()
+}
+
+/// Check whether a filesystem type is supported by the kernel, either directly
+/// or by loading a module.
+///
+/// If the supported filesystems can't be determined, such as when `/proc` is
+/// not mounted, then the filesystem is assumed to be supported.
+fn filesystem_supported(filesystem_type: &str) -> bool {
+    // Each line is the filesystem name, optionally preceded by `nodev`.
+    let supported = match read_to_string("/proc/filesystems") {
+        Ok(supported) => supported,
+        Err(_) => return true,
+    };
+    let registered = supported.lines()
+        .filter_map(|line| line.split_whitespace().last())
+        .any(|name| name == filesystem_type);
+    if registered {
+        return true;
+    }
+
+    // Filesystem modules are loaded on demand using the `fs-` alias.
+    let aliases = format!("/lib/modules/{}/modules.alias", uname().release());
+    let aliases = match read_to_string(aliases) {
+        Ok(aliases) => aliases,
+        Err(_) => return true,
+    };
+    let alias = format!("fs-{}", filesystem_type);
+    aliases.lines()
+        .filter_map(|line| line.split_whitespace().nth(1))
+        .any(|name| name == alias)
+}
+
+/// A sequence of mount points that are unmounted in the reverse of the order
+/// in which they were mounted when dropped.
+///
+/// Unmounting in reverse ensures that nested mount points are unmounted
+/// before the mount points that contain them.
+#[derive(Debug, Default)]
+pub struct MountStack {
+    guards: Vec<MountGuard>,
+}
+
+impl MountStack {
+    /// Make each mount in order with the given function, which gives the guard
+    /// of the mount point made, if any.
+    ///
+    /// If a mount fails, the mount points already made are unmounted.
+    pub fn make<I, F>(mounts: I, mut make: F) -> Result<MountStack>
+    where
+        I: IntoIterator<Item = Mount>,
+        F: FnMut(Mount) -> Result<Option<MountGuard>>,
+    {
+        let mut stack = MountStack::default();
+        for mount in mounts {
+            if let Some(guard) = make(mount)? {
+                stack.push(guard);
+            }
+        }
+        Ok(stack)
+    }
+
+    /// Add a mount point to the top of the stack.
+    pub fn push(&mut self, guard: MountGuard) {
+        self.guards.push(guard);
+    }
+
+    /// Leave all the mount points in the stack in place.
+    pub fn forget(&mut self) {
+        for guard in self.guards.drain(..) {
+            guard.forget();
+        }
+    }
+
+    /// Update the targets of all mount points after changing the root
+    /// directory.
+    ///
+    /// Any mount points that are not within the new root are left unchanged.
+    pub fn rebase<P: AsRef<Path>>(&mut self, root: P) {
+        for guard in &mut self.guards {
+            guard.rebase(root.as_ref());
+        }
+    }
+}
+
+impl Drop for MountStack {
+    fn drop(&mut self) {
+        while let Some(guard) = self.guards.pop() {
+            drop(guard);
+        }
+    }
+}
+
+/// The forms the flags of a mount can be written in.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum FlagList {
+    /// Comma separated options, as given to mount(8).
+    Options(String),
+    List(Vec<MountFlags>),
+}
+
+/// Deserialize the flags of a mount, which may also be given as comma
+/// separated options.
+///
+/// Unlike `MountFlags::from_options`, each option must name a flag.
+fn flag_list<'de, D>(deserializer: D) -> ::std::result::Result<Vec<MountFlags>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match FlagList::deserialize(deserializer)? {
+        FlagList::Options(options) => options.split(',')
+            .map(str::trim)
+            .filter(|option| !option.is_empty())
+            .map(|option| option.parse().map_err(::serde::de::Error::custom))
+            .collect(),
+        FlagList::List(flags) => Ok(flags),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::cell::RefCell;
+    use std::env;
+    use std::fs::{copy, remove_dir_all};
+    use std::process::{self, Command};
+
+    use toml;
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Flags {
+        #[serde(deserialize_with = "flag_list")]
+        flags: Vec<MountFlags>,
+    }
+
+    fn parse_flags(config: &str) -> Vec<MountFlags> {
+        toml::from_str::<Flags>(config).unwrap().flags
+    }
+
+    #[test]
+    fn lazytime_round_trip() {
+        let config = toml::to_string(&Flags { flags: vec![MountFlags::LazyAccessTime] }).unwrap();
+        assert_eq!(parse_flags(&config), vec![MountFlags::LazyAccessTime]);
+        assert_eq!(parse_flags(r#"flags = "lazytime""#), vec![MountFlags::LazyAccessTime]);
+
+        let bits: MsFlags = MountFlags::LazyAccessTime.into();
+        assert_eq!(bits.bits(), MS_LAZYTIME);
+    }
+
+    #[test]
+    fn nosymfollow_parses() {
+        assert_eq!("nosymfollow".parse::<MountFlags>().unwrap(), MountFlags::NoSymlinkFollow);
+        assert_eq!("no_symlink_follow".parse::<MountFlags>().unwrap(), MountFlags::NoSymlinkFollow);
+
+        let (flags, data) = MountFlags::from_options("nosymfollow,nodev");
+        assert_eq!(flags, vec![MountFlags::NoSymlinkFollow, MountFlags::NoDevices]);
+        assert_eq!(data, None);
+
+        let bits: MsFlags = MountFlags::NoSymlinkFollow.into();
+        assert_eq!(bits.bits(), MS_NOSYMFOLLOW);
+    }
+
+    #[test]
+    fn options_of_flags() {
+        let (flags, data) = MountFlags::from_options("ro,nosuid,nodev");
+        assert_eq!(flags, vec![MountFlags::ReadOnly, MountFlags::NoSuid, MountFlags::NoDevices]);
+        assert_eq!(data, None);
+    }
+
+    #[test]
+    fn options_skip_empty_items() {
+        let (flags, data) = MountFlags::from_options(",ro,,size=64m,");
+        assert_eq!(flags, vec![MountFlags::ReadOnly]);
+        assert_eq!(data, Some("size=64m".to_owned()));
+
+        assert_eq!(MountFlags::from_options(""), (vec![], None));
+        assert_eq!(parse_flags(r#"flags = "ro,,nodev""#), vec![MountFlags::ReadOnly, MountFlags::NoDevices]);
+    }
+
+    #[test]
+    fn options_trim_whitespace() {
+        let (flags, data) = MountFlags::from_options(" ro , nosuid,\tmode=755 ");
+        assert_eq!(flags, vec![MountFlags::ReadOnly, MountFlags::NoSuid]);
+        assert_eq!(data, Some("mode=755".to_owned()));
+
+        assert_eq!(parse_flags(r#"flags = "ro, nodev""#), vec![MountFlags::ReadOnly, MountFlags::NoDevices]);
+    }
+
+    #[test]
+    fn unknown_flag() {
+        match "bogus".parse::<MountFlags>() {
+            Err(Error(ErrorKind::UnknownMountFlag(ref name), _)) => assert_eq!(name, "bogus"),
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert!(toml::from_str::<Flags>(r#"flags = "ro,bogus""#).is_err());
+
+        // Options that are not flags are left for the filesystem.
+        let (flags, data) = MountFlags::from_options("nodev,bogus");
+        assert_eq!(flags, vec![MountFlags::NoDevices]);
+        assert_eq!(data, Some("bogus".to_owned()));
+    }
+
+    thread_local! {
+        /// The targets unmounted by the guards of a test, in order.
+        static UNMOUNTED: RefCell<Vec<PathBuf>> = RefCell::new(Vec::new());
+    }
+
+    pub fn record_unmount(target: &Path) {
+        UNMOUNTED.with(|unmounted| unmounted.borrow_mut().push(target.to_owned()));
+    }
+
+    fn unmounted() -> Vec<PathBuf> {
+        UNMOUNTED.with(|unmounted| unmounted.borrow_mut().drain(..).collect())
+    }
+
+    fn guard(target: &str) -> MountGuard {
+        MountGuard { target: Some(PathBuf::from(target)) }
+    }
+
+    #[test]
+    fn stack_unmounts_in_reverse() {
+        let mut stack = MountStack::default();
+        stack.push(guard("/a"));
+        stack.push(guard("/a/b"));
+        stack.push(guard("/a/b/c"));
+        assert!(unmounted().is_empty());
+
+        drop(stack);
+        let expected: Vec<_> = ["/a/b/c", "/a/b", "/a"].iter().map(PathBuf::from).collect();
+        assert_eq!(unmounted(), expected);
+    }
+
+    #[test]
+    fn stack_rolled_back_on_failure() {
+        let mounts = ["/a", "/a/b", "/c", "/d"].iter()
+            .map(|target| Mount::new("tmpfs", target, "tmpfs"))
+            .collect::<Vec<_>>();
+        let mut made = Vec::new();
+        let result = MountStack::make(mounts, |mount| {
+            let target = mount.target().to_owned();
+            ensure!(target != Path::new("/c"), ErrorKind::SetMount(target));
+            made.push(target.clone());
+            Ok(Some(guard(target.to_str().unwrap())))
+        });
+
+        assert!(result.is_err());
+        assert_eq!(made, vec![PathBuf::from("/a"), PathBuf::from("/a/b")]);
+        assert_eq!(unmounted(), vec![PathBuf::from("/a/b"), PathBuf::from("/a")]);
+    }
+
+    #[test]
+    fn stack_forgotten() {
+        let mut stack = MountStack::default();
+        stack.push(guard("/a"));
+        stack.forget();
+        drop(stack);
+        assert!(unmounted().is_empty());
+    }
+
+    #[test]
+    fn stack_rebased() {
+        let mut stack = MountStack::default();
+        stack.push(guard("/root/proc"));
+        stack.push(guard("/elsewhere"));
+        stack.rebase("/root");
+        drop(stack);
+        assert_eq!(unmounted(), vec![PathBuf::from("/elsewhere"), PathBuf::from("/proc")]);
+    }
+
+    #[test]
+    fn interrupted_calls_retried() {
+        let mut calls = 0;
+        let result = retry_interrupted(|| {
+            calls += 1;
+            if calls < 3 { Err(NixError::Sys(Errno::EINTR)) } else { Ok(calls) }
+        });
+        assert_eq!(result, Ok(3));
+    }
+
+    #[test]
+    fn interrupted_calls_retried_a_limited_number_of_times() {
+        let mut calls = 0;
+        let result: ::nix::Result<()> = retry_interrupted(|| {
+            calls += 1;
+            Err(NixError::Sys(Errno::EINTR))
+        });
+        assert_eq!(result, Err(NixError::Sys(Errno::EINTR)));
+        assert_eq!(calls, INTERRUPT_RETRIES + 1);
+    }
+
+    #[test]
+    fn other_errors_not_retried() {
+        let mut calls = 0;
+        let result: ::nix::Result<()> = retry_interrupted(|| {
+            calls += 1;
+            Err(NixError::Sys(Errno::EBUSY))
+        });
+        assert_eq!(result, Err(NixError::Sys(Errno::EBUSY)));
+        assert_eq!(calls, 1);
+    }
+
+    fn parse_mount(config: &str) -> Mount {
+        toml::from_str(config).unwrap()
+    }
+
+    #[test]
+    fn propagation_recursive() {
+        let mount = parse_mount("option = \"slave\"\ntarget = \"/\"\nrecursive = true");
+        assert_eq!(mount.ms_flags(), MsFlags::MS_SLAVE | MsFlags::MS_REC);
+
+        let mount = parse_mount("option = \"slave\"\ntarget = \"/\"\nflags = [\"recursive\"]");
+        assert_eq!(mount.ms_flags(), MsFlags::MS_SLAVE | MsFlags::MS_REC);
+
+        assert_eq!(Mount::recursive_private("/").ms_flags(), MsFlags::MS_PRIVATE | MsFlags::MS_REC);
+        assert_eq!(Mount::shared("/").recursive().ms_flags(), MsFlags::MS_SHARED | MsFlags::MS_REC);
+    }
+
+    #[test]
+    fn propagation_not_recursive() {
+        let mount = parse_mount("option = \"unbindable\"\ntarget = \"/\"");
+        assert_eq!(mount.ms_flags(), MsFlags::MS_UNBINDABLE);
+        assert_eq!(Mount::private("/").ms_flags(), MsFlags::MS_PRIVATE);
+    }
+
+    #[test]
+    fn remount_keeps_current_flags() {
+        let current = MsFlags::MS_NOSUID | MsFlags::MS_NODEV | MsFlags::MS_RELATIME;
+        let mount = Mount::remount("/mnt").read_only();
+        assert_eq!(
+            combine_flags(current, mount.ms_flags()),
+            MsFlags::MS_REMOUNT | MsFlags::MS_RDONLY | current
+        );
+    }
+
+    #[test]
+    fn remount_replaces_access_time() {
+        let current = MsFlags::MS_NOSUID | MsFlags::MS_RELATIME;
+        let flags = MsFlags::MS_REMOUNT | MsFlags::MS_NOATIME;
+        assert_eq!(combine_flags(current, flags), flags | MsFlags::MS_NOSUID);
+    }
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("container-{}-{}", name, process::id()));
+        let _ = remove_dir_all(&dir);
+        create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn bind_device_over_file() {
+        let dir = test_dir("bind");
+        let file = dir.join("dev/null");
+        let directory = dir.join("dev/shm");
+        Mount::bind(Path::new("/dev/null"), file.as_path()).create_target().unwrap();
+        Mount::bind(Path::new("/dev"), directory.as_path()).create_target().unwrap();
+        let (file, directory) = (metadata(&file), metadata(&directory));
+        remove_dir_all(&dir).unwrap();
+
+        assert!(file.unwrap().is_file());
+        assert!(directory.unwrap().is_dir());
+    }
+
+    /// Whether mounts can be made, checked by binding a directory over itself.
+    fn can_mount(dir: &Path) -> bool {
+        Mount::bind(dir, dir).mount().is_ok() && umount2(dir, MntFlags::MNT_DETACH).is_ok()
+    }
+
+    #[test]
+    fn no_exec_bind() {
+        let dir = test_dir("noexec");
+        let (source, target) = (dir.join("source"), dir.join("target"));
+        create_dir_all(&source).unwrap();
+        copy("/bin/true", source.join("true")).unwrap();
+        if !can_mount(&source) {
+            remove_dir_all(&dir).unwrap();
+            return;
+        }
+
+        Mount::bind(&source, &target).no_exec().make_target_dir().mount().unwrap();
+        let status = Command::new(target.join("true")).status();
+        umount2(&target, MntFlags::MNT_DETACH).unwrap();
+        remove_dir_all(&dir).unwrap();
+
+        assert_eq!(status.unwrap_err().raw_os_error(), Some(libc::EACCES));
+    }
+
+    #[test]
+    fn read_only_bind() {
+        let dir = test_dir("readonly");
+        let (source, target) = (dir.join("source"), dir.join("target"));
+        create_dir_all(&source).unwrap();
+        if !can_mount(&source) {
+            remove_dir_all(&dir).unwrap();
+            return;
+        }
+
+        Mount::bind(&source, &target).read_only().make_target_dir().mount().unwrap();
+        let created = ::std::fs::File::create(target.join("file"));
+        umount2(&target, MntFlags::MNT_DETACH).unwrap();
+        remove_dir_all(&dir).unwrap();
+
+        assert_eq!(created.unwrap_err().raw_os_error(), Some(libc::EROFS));
+    }
 }
@@ -0,0 +1,144 @@
+//! Information about the mounts visible to the process.
+//!
+//! See proc(5) for details of the format of `/proc/self/mountinfo`.
+
+use std::ffi::OsString;
+use std::fs::read_to_string;
+use std::os::unix::ffi::OsStringExt;
+use std::path::PathBuf;
+
+use nix::mount::MsFlags;
+
+use error::*;
+
+/// A mount point listed in `/proc/self/mountinfo`.
+#[derive(Clone, Debug)]
+#[allow(dead_code)]
+pub struct MountInfo {
+    /// The path of the directory within the filesystem forming the root of the
+    /// mount.
+    pub root: PathBuf,
+    /// The mount point, relative to the root directory of the process.
+    pub mount_point: PathBuf,
+    /// Options specific to the mount point.
+    pub options: Vec<String>,
+    /// The type of the filesystem.
+    pub filesystem_type: String,
+    /// The filesystem specific source of the mount.
+    pub source: String,
+}
+
+impl MountInfo {
+    /// The flags of the mount point that can be changed with a remount.
+    pub fn flags(&self) -> MsFlags {
+        self.options.iter()
+            .map(|option| match option.as_str() {
+                "ro"          => MsFlags::MS_RDONLY,
+                "nosuid"      => MsFlags::MS_NOSUID,
+                "nodev"       => MsFlags::MS_NODEV,
+                "noexec"      => MsFlags::MS_NOEXEC,
+                "noatime"     => MsFlags::MS_NOATIME,
+                "nodiratime"  => MsFlags::MS_NODIRATIME,
+                "relatime"    => MsFlags::MS_RELATIME,
+                "strictatime" => MsFlags::MS_STRICTATIME,
+                _ => MsFlags::empty(),
+            })
+            .collect()
+    }
+
+    /// Parse a single line of `/proc/self/mountinfo`.
+    fn parse(line: &str) -> Option<MountInfo> {
+        // Skip the mount ID, parent ID, and device numbers.
+        let mut fields = line.split_whitespace().skip(3);
+        let root = unescape(fields.next()?);
+        let mount_point = unescape(fields.next()?);
+        let options = fields.next()?.split(',').map(String::from).collect();
+
+        // Optional fields are terminated by a single hyphen.
+        let mut fields = fields.skip_while(|&field| field != "-").skip(1);
+        let filesystem_type = fields.next()?.to_owned();
+        let source = fields.next()?.to_owned();
+
+        Some(MountInfo { root, mount_point, options, filesystem_type, source })
+    }
+}
+
+/// All of the mounts visible to the process, in the order they were mounted.
+pub fn mounts() -> Result<Vec<MountInfo>> {
+    let mountinfo = read_to_string("/proc/self/mountinfo")?;
+    Ok(mountinfo.lines().filter_map(MountInfo::parse).collect())
+}
+
+/// Replace the octal escapes used for whitespace and backslashes in paths.
+pub fn unescape(field: &str) -> PathBuf {
+    let bytes = field.as_bytes();
+    let mut unescaped = Vec::with_capacity(bytes.len());
+
+    let mut index = 0;
+    while index < bytes.len() {
+        let escape = bytes.get(index + 1..index + 4)
+            .filter(|_| bytes[index] == b'\\')
+            .and_then(|digits| ::std::str::from_utf8(digits).ok())
+            .and_then(|digits| u8::from_str_radix(digits, 8).ok());
+        match escape {
+            Some(byte) => {
+                unescaped.push(byte);
+                index += 4;
+            },
+            None => {
+                unescaped.push(bytes[index]);
+                index += 1;
+            },
+        }
+    }
+
+    PathBuf::from(OsString::from_vec(unescaped))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_line() {
+        let line = "36 35 98:0 /mnt1 /mnt2 rw,noatime master:1 - ext3 /dev/root rw,errors=continue";
+        let info = MountInfo::parse(line).unwrap();
+        assert_eq!(info.root, PathBuf::from("/mnt1"));
+        assert_eq!(info.mount_point, PathBuf::from("/mnt2"));
+        assert_eq!(info.options, vec!["rw", "noatime"]);
+        assert_eq!(info.filesystem_type, "ext3");
+        assert_eq!(info.source, "/dev/root");
+        assert_eq!(info.flags(), MsFlags::MS_NOATIME);
+    }
+
+    #[test]
+    fn parse_line_without_optional_fields() {
+        let line = "25 1 0:22 / /proc ro,nosuid,nodev,noexec - proc proc rw";
+        let info = MountInfo::parse(line).unwrap();
+        assert_eq!(info.mount_point, PathBuf::from("/proc"));
+        assert_eq!(info.filesystem_type, "proc");
+        assert_eq!(
+            info.flags(),
+            MsFlags::MS_RDONLY | MsFlags::MS_NOSUID | MsFlags::MS_NODEV | MsFlags::MS_NOEXEC
+        );
+    }
+
+    #[test]
+    fn parse_escaped_paths() {
+        let line = r"40 25 0:40 /a\134b /mnt/with\040space\011tab rw shared:2 - tmpfs tmpfs rw";
+        let info = MountInfo::parse(line).unwrap();
+        assert_eq!(info.root, PathBuf::from(r"/a\b"));
+        assert_eq!(info.mount_point, PathBuf::from("/mnt/with space\ttab"));
+    }
+
+    #[test]
+    fn parse_truncated_line() {
+        assert!(MountInfo::parse("36 35 98:0 /mnt1 /mnt2 rw master:1").is_none());
+    }
+
+    #[test]
+    fn unescape_incomplete_escape() {
+        assert_eq!(unescape(r"/a\04"), PathBuf::from(r"/a\04"));
+        assert_eq!(unescape(r"/a\999"), PathBuf::from(r"/a\999"));
+    }
+}
@@ -0,0 +1,452 @@
+//! Networking of the network namespace using a veth pair.
+//!
+//! The interfaces are configured with rtnetlink messages, see rtnetlink(7) and
+//! netlink(7) for the format of the messages.
+
+use std::convert::TryFrom;
+use std::ffi::CString;
+use std::fmt;
+use std::mem::{size_of, zeroed};
+use std::net::Ipv4Addr;
+use std::os::unix::io::RawFd;
+use std::slice;
+
+use libc::{self, c_void, pid_t, sockaddr_nl, AF_INET, AF_NETLINK, IFF_UP, NETLINK_ROUTE};
+use nix::Error as NixError;
+use nix::errno::Errno;
+
+use error::*;
+
+const NLMSG_ERROR: u16 = 2;
+
+const NLM_F_REQUEST: u16 = 0x001;
+const NLM_F_ACK: u16 = 0x004;
+const NLM_F_EXCL: u16 = 0x200;
+const NLM_F_CREATE: u16 = 0x400;
+
+const RTM_NEWLINK: u16 = 16;
+const RTM_NEWADDR: u16 = 20;
+const RTM_NEWROUTE: u16 = 24;
+
+const IFLA_IFNAME: u16 = 3;
+const IFLA_LINKINFO: u16 = 18;
+const IFLA_NET_NS_PID: u16 = 19;
+const IFLA_INFO_KIND: u16 = 1;
+const IFLA_INFO_DATA: u16 = 2;
+const VETH_INFO_PEER: u16 = 1;
+
+const IFA_ADDRESS: u16 = 1;
+const IFA_LOCAL: u16 = 2;
+
+const RTA_OIF: u16 = 4;
+const RTA_GATEWAY: u16 = 5;
+
+const RT_TABLE_MAIN: u8 = 254;
+const RTPROT_BOOT: u8 = 3;
+const RT_SCOPE_UNIVERSE: u8 = 0;
+const RTN_UNICAST: u8 = 1;
+
+/// The header of a message, as `struct nlmsghdr`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct MessageHeader {
+    len: u32,
+    kind: u16,
+    flags: u16,
+    seq: u32,
+    pid: u32,
+}
+
+/// A change to a link, as `struct ifinfomsg`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct InterfaceInfo {
+    family: u8,
+    pad: u8,
+    kind: u16,
+    index: i32,
+    flags: u32,
+    change: u32,
+}
+
+/// A change to an address, as `struct ifaddrmsg`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct AddressInfo {
+    family: u8,
+    prefix_len: u8,
+    flags: u8,
+    scope: u8,
+    index: u32,
+}
+
+/// A change to a route, as `struct rtmsg`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct RouteInfo {
+    family: u8,
+    dst_len: u8,
+    src_len: u8,
+    tos: u8,
+    table: u8,
+    protocol: u8,
+    scope: u8,
+    kind: u8,
+    flags: u32,
+}
+
+/// The network of the container.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Network {
+    /// Bring up the loopback interface.
+    #[serde(default = "default_loopback_up")]
+    loopback_up: bool,
+    /// A veth pair connecting the container to the host.
+    veth: Option<Veth>,
+}
+
+fn default_loopback_up() -> bool {
+    true
+}
+
+/// A pair of virtual ethernet interfaces, one on the host and the other in the
+/// container.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Veth {
+    /// The name of the interface on the host.
+    host: String,
+    /// The name of the interface in the container.
+    container: String,
+    /// The address of the interface in the container.
+    address: Ipv4Net,
+    /// The address of the interface on the host, used as the default route of
+    /// the container.
+    gateway: Option<Ipv4Addr>,
+}
+
+/// An IPv4 address with the length of its network prefix, as `10.0.0.2/24`.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Ipv4Net {
+    address: Ipv4Addr,
+    prefix_len: u8,
+}
+
+impl TryFrom<String> for Ipv4Net {
+    type Error = String;
+
+    fn try_from(net: String) -> ::std::result::Result<Ipv4Net, String> {
+        let invalid = || format!("invalid address '{}', expected a.b.c.d/prefix", net);
+        let mut parts = net.splitn(2, '/');
+        let address = parts.next().and_then(|address| address.parse().ok());
+        let prefix_len = parts.next().and_then(|prefix| prefix.parse().ok());
+        match (address, prefix_len) {
+            (Some(address), Some(prefix_len)) if prefix_len <= 32 => {
+                Ok(Ipv4Net { address, prefix_len })
+            },
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl Into<String> for Ipv4Net {
+    fn into(self) -> String {
+        self.to_string()
+    }
+}
+
+impl fmt::Display for Ipv4Net {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.address, self.prefix_len)
+    }
+}
+
+impl Network {
+    /// Create the interfaces of the network from outside of the container,
+    /// moving them into the network namespace of the given process.
+    pub fn create(&self, pid: pid_t) -> Failure {
+        if let Some(ref veth) = self.veth {
+            veth.create(pid).chain_err(|| ErrorKind::Network(veth.host.clone()))?;
+        }
+        ok!()
+    }
+
+    /// Configure the interfaces of the network from within the container.
+    pub fn configure(&self) -> Failure {
+        if let Some(ref veth) = self.veth {
+            let mut netlink = Netlink::open()?;
+            veth.configure(&mut netlink)
+                .chain_err(|| ErrorKind::Network(veth.container.clone()))?;
+        }
+        ok!()
+    }
+
+    /// The loopback interface is to be brought up.
+    pub fn loopback_up(&self) -> bool {
+        self.loopback_up
+    }
+
+    /// Describe the interfaces of the network.
+    pub fn describe(&self) -> Vec<String> {
+        let mut description = Vec::new();
+        if let Some(ref veth) = self.veth {
+            description.push(format!(
+                "network: veth {} on host, {} in container with address {}",
+                veth.host, veth.container, veth.address,
+            ));
+            if let Some(gateway) = veth.gateway {
+                description.push(format!("network: default route via {}", gateway));
+            }
+        }
+        description
+    }
+}
+
+impl Veth {
+    /// Create the pair with one end in the network namespace of the process,
+    /// then address the host end and bring it up.
+    fn create(&self, pid: pid_t) -> Failure {
+        let mut netlink = Netlink::open()?;
+
+        let mut message = Message::new(RTM_NEWLINK, NLM_F_CREATE | NLM_F_EXCL);
+        message.push(&interface_info(0));
+        message.attribute(IFLA_IFNAME, &name_bytes(&self.host)?);
+        message.nested(IFLA_LINKINFO, |message| {
+            message.attribute(IFLA_INFO_KIND, b"veth");
+            message.nested(IFLA_INFO_DATA, |message| {
+                message.nested(VETH_INFO_PEER, |message| {
+                    message.push(&interface_info(0));
+                    message.attribute(IFLA_IFNAME, &name_bytes(&self.container)?);
+                    message.attribute(IFLA_NET_NS_PID, &(pid as u32).to_ne_bytes());
+                    ok!()
+                })
+            })
+        })?;
+        netlink.request(message)?;
+
+        let index = interface_index(&self.host)?;
+        if let Some(gateway) = self.gateway {
+            netlink.add_address(index, gateway, self.address.prefix_len)?;
+        }
+        netlink.set_up(index)
+    }
+
+    /// Address the container end and bring it up, routing through the host end.
+    fn configure(&self, netlink: &mut Netlink) -> Failure {
+        let index = interface_index(&self.container)?;
+        netlink.add_address(index, self.address.address, self.address.prefix_len)?;
+        netlink.set_up(index)?;
+        if let Some(gateway) = self.gateway {
+            netlink.add_default_route(index, gateway)?;
+        }
+        ok!()
+    }
+}
+
+/// Bring up the loopback interface, which starts down in a new network
+/// namespace.
+pub fn bring_up_loopback() -> Failure {
+    interface_index("lo")
+        .and_then(|index| Netlink::open()?.set_up(index))
+        .chain_err(|| ErrorKind::Network("lo".into()))
+}
+
+/// A message being built to send to the kernel.
+struct Message {
+    buffer: Vec<u8>,
+}
+
+impl Message {
+    /// Start a request of the given type.
+    fn new(kind: u16, flags: u16) -> Message {
+        let mut message = Message { buffer: Vec::new() };
+        message.push(&MessageHeader {
+            len: 0,
+            kind,
+            flags: NLM_F_REQUEST | NLM_F_ACK | flags,
+            seq: 0,
+            pid: 0,
+        });
+        message
+    }
+
+    /// Append a structure, aligned to four bytes.
+    fn push<T: Copy>(&mut self, value: &T) {
+        let bytes = unsafe {
+            slice::from_raw_parts(value as *const T as *const u8, size_of::<T>())
+        };
+        self.bytes(bytes);
+    }
+
+    /// Append bytes, aligned to four bytes.
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+        while self.buffer.len() % 4 != 0 {
+            self.buffer.push(0);
+        }
+    }
+
+    /// Append an attribute, as `struct rtattr` followed by its value.
+    fn attribute(&mut self, kind: u16, value: &[u8]) {
+        let len = (4 + value.len()) as u16;
+        self.buffer.extend_from_slice(&len.to_ne_bytes());
+        self.buffer.extend_from_slice(&kind.to_ne_bytes());
+        self.bytes(value);
+    }
+
+    /// Append an attribute containing further attributes.
+    fn nested<F: FnOnce(&mut Message) -> Failure>(&mut self, kind: u16, build: F) -> Failure {
+        let start = self.buffer.len();
+        self.attribute(kind, &[]);
+        build(self)?;
+        let len = (self.buffer.len() - start) as u16;
+        self.buffer[start..start + 2].copy_from_slice(&len.to_ne_bytes());
+        ok!()
+    }
+
+    /// The complete message with its length.
+    fn finish(mut self, seq: u32) -> Vec<u8> {
+        let len = self.buffer.len() as u32;
+        self.buffer[0..4].copy_from_slice(&len.to_ne_bytes());
+        self.buffer[8..12].copy_from_slice(&seq.to_ne_bytes());
+        self.buffer
+    }
+}
+
+/// A route netlink socket.
+struct Netlink {
+    fd: RawFd,
+    seq: u32,
+}
+
+impl Netlink {
+    /// Open a socket in the current network namespace.
+    fn open() -> Result<Netlink> {
+        let fd = unsafe {
+            libc::socket(AF_NETLINK, libc::SOCK_RAW | libc::SOCK_CLOEXEC, NETLINK_ROUTE)
+        };
+        let netlink = Netlink { fd: Errno::result(fd)?, seq: 0 };
+
+        let mut address: sockaddr_nl = unsafe { zeroed() };
+        address.nl_family = AF_NETLINK as u16;
+        let result = unsafe {
+            libc::bind(
+                netlink.fd,
+                &address as *const sockaddr_nl as *const libc::sockaddr,
+                size_of::<sockaddr_nl>() as u32,
+            )
+        };
+        Errno::result(result)?;
+
+        Ok(netlink)
+    }
+
+    /// Send a request and wait for it to be acknowledged.
+    fn request(&mut self, message: Message) -> Failure {
+        self.seq += 1;
+        let message = message.finish(self.seq);
+        let result = unsafe {
+            libc::send(self.fd, message.as_ptr() as *const c_void, message.len(), 0)
+        };
+        Errno::result(result)?;
+
+        // The acknowledgement is an error message with an error of zero.
+        let mut reply = [0u8; 4096];
+        loop {
+            let len = unsafe {
+                libc::recv(self.fd, reply.as_mut_ptr() as *mut c_void, reply.len(), 0)
+            };
+            let len = Errno::result(len)? as usize;
+            let header_len = size_of::<MessageHeader>();
+            if len < header_len + 4 {
+                continue;
+            }
+            let header: MessageHeader = unsafe { *(reply.as_ptr() as *const MessageHeader) };
+            if header.kind != NLMSG_ERROR || header.seq != self.seq {
+                continue;
+            }
+            let error = unsafe { *(reply[header_len..].as_ptr() as *const i32) };
+            return match error {
+                0 => ok!(),
+                error => Err(NixError::Sys(Errno::from_i32(-error)).into()),
+            };
+        }
+    }
+
+    /// Bring an interface up.
+    fn set_up(&mut self, index: i32) -> Failure {
+        let mut info = interface_info(index);
+        info.flags = IFF_UP as u32;
+        info.change = IFF_UP as u32;
+
+        let mut message = Message::new(RTM_NEWLINK, 0);
+        message.push(&info);
+        self.request(message)
+    }
+
+    /// Add an address to an interface.
+    fn add_address(&mut self, index: i32, address: Ipv4Addr, prefix_len: u8) -> Failure {
+        let mut message = Message::new(RTM_NEWADDR, NLM_F_CREATE | NLM_F_EXCL);
+        message.push(&AddressInfo {
+            family: AF_INET as u8,
+            prefix_len,
+            flags: 0,
+            scope: RT_SCOPE_UNIVERSE,
+            index: index as u32,
+        });
+        message.attribute(IFA_LOCAL, &address.octets());
+        message.attribute(IFA_ADDRESS, &address.octets());
+        self.request(message)
+    }
+
+    /// Add a default route through a gateway.
+    fn add_default_route(&mut self, index: i32, gateway: Ipv4Addr) -> Failure {
+        let mut message = Message::new(RTM_NEWROUTE, NLM_F_CREATE | NLM_F_EXCL);
+        message.push(&RouteInfo {
+            family: AF_INET as u8,
+            dst_len: 0,
+            src_len: 0,
+            tos: 0,
+            table: RT_TABLE_MAIN,
+            protocol: RTPROT_BOOT,
+            scope: RT_SCOPE_UNIVERSE,
+            kind: RTN_UNICAST,
+            flags: 0,
+        });
+        message.attribute(RTA_GATEWAY, &gateway.octets());
+        message.attribute(RTA_OIF, &(index as u32).to_ne_bytes());
+        self.request(message)
+    }
+}
+
+impl Drop for Netlink {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.fd) };
+    }
+}
+
+/// A link message for the interface with the given index.
+fn interface_info(index: i32) -> InterfaceInfo {
+    InterfaceInfo {
+        family: 0,
+        pad: 0,
+        kind: 0,
+        index,
+        flags: 0,
+        change: 0,
+    }
+}
+
+/// The name of an interface as a null terminated attribute.
+fn name_bytes(name: &str) -> Result<Vec<u8>> {
+    Ok(CString::new(name)?.into_bytes_with_nul())
+}
+
+/// The index of an interface in the current network namespace.
+fn interface_index(name: &str) -> Result<i32> {
+    let name = CString::new(name)?;
+    match unsafe { libc::if_nametoindex(name.as_ptr()) } {
+        0 => Err(NixError::Sys(Errno::last()).into()),
+        index => Ok(index as i32),
+    }
+}
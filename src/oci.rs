@@ -0,0 +1,145 @@
+//! The parts of an OCI runtime bundle configuration that can be applied.
+//!
+//! See the runtime specification of the Open Container Initiative for the
+//! format of `config.json`.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use libc::pid_t;
+use serde_json::Value;
+
+/// The version of the specification the state is written in.
+pub const OCI_VERSION: &str = "1.0.2";
+
+/// Fields that are not supported.
+pub type Unsupported = BTreeMap<String, Value>;
+
+/// The state of a container, as written to a state file.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct State {
+    pub oci_version: &'static str,
+    pub id: String,
+    pub status: Status,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pid: Option<pid_t>,
+    pub bundle: PathBuf,
+}
+
+/// The stage of the lifetime of a container.
+#[derive(Clone, Copy, Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Creating,
+    Running,
+    Stopped,
+}
+
+/// The configuration of a bundle.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Spec {
+    pub root: Option<Root>,
+    pub process: Option<Process>,
+    pub hostname: Option<String>,
+    #[serde(default)]
+    pub mounts: Vec<Mount>,
+    pub linux: Option<Linux>,
+    // Any version is accepted.
+    #[allow(dead_code)]
+    pub oci_version: Option<String>,
+    #[serde(flatten)]
+    pub unsupported: Unsupported,
+}
+
+/// The root filesystem of the container.
+#[derive(Debug, Deserialize)]
+pub struct Root {
+    pub path: PathBuf,
+    #[serde(flatten)]
+    pub unsupported: Unsupported,
+}
+
+/// The process run in the container.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Process {
+    pub user: Option<User>,
+    pub cwd: Option<PathBuf>,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub env: Vec<String>,
+    #[serde(default)]
+    pub no_new_privileges: bool,
+    #[serde(flatten)]
+    pub unsupported: Unsupported,
+}
+
+/// The user the process is run as.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct User {
+    pub uid: u32,
+    pub gid: u32,
+    #[serde(default)]
+    pub additional_gids: Vec<u32>,
+    #[serde(flatten)]
+    pub unsupported: Unsupported,
+}
+
+/// A mount made in the container.
+#[derive(Debug, Deserialize)]
+pub struct Mount {
+    pub destination: PathBuf,
+    #[serde(rename = "type")]
+    pub filesystem_type: Option<String>,
+    pub source: Option<PathBuf>,
+    #[serde(default)]
+    pub options: Vec<String>,
+}
+
+/// Linux specific configuration.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Linux {
+    #[serde(default)]
+    pub namespaces: Vec<Namespace>,
+    #[serde(default)]
+    pub uid_mappings: Vec<IdMapping>,
+    #[serde(default)]
+    pub gid_mappings: Vec<IdMapping>,
+    #[serde(default)]
+    pub masked_paths: Vec<PathBuf>,
+    #[serde(default)]
+    pub readonly_paths: Vec<PathBuf>,
+    #[serde(flatten)]
+    pub unsupported: Unsupported,
+}
+
+/// A namespace to unshare, or to join if a path is given.
+#[derive(Debug, Deserialize)]
+pub struct Namespace {
+    #[serde(rename = "type")]
+    pub namespace: String,
+    pub path: Option<PathBuf>,
+}
+
+/// A range of ids mapped into the user namespace.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IdMapping {
+    #[serde(rename = "containerID")]
+    pub container_id: u32,
+    #[serde(rename = "hostID")]
+    pub host_id: u32,
+    pub size: u32,
+}
+
+/// A warning for each field that is not supported.
+pub fn unsupported_fields(prefix: &str, unsupported: &Unsupported) -> Vec<String> {
+    unsupported.keys()
+        .map(|field| format!("ignoring unsupported OCI field {}{}", prefix, field))
+        .collect()
+}
@@ -0,0 +1,67 @@
+//! Scheduling priority of the command.
+//!
+//! See setpriority(2), sched(7), and the description of oom_score_adj in
+//! proc(5).
+
+use std::fs::write;
+
+use libc::{self, sched_param};
+use nix::errno::Errno;
+
+use error::*;
+
+/// The file setting how likely the process is to be killed when out of memory.
+const OOM_SCORE_ADJ: &str = "/proc/self/oom_score_adj";
+
+/// A scheduling policy that does not require a static priority.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SchedPolicy {
+    /// The default time-sharing policy.
+    Other,
+    /// For CPU-intensive work that is not interactive.
+    Batch,
+    /// For work run only when nothing else would.
+    Idle,
+}
+
+impl SchedPolicy {
+    /// Set the policy of the current process, and so each process it starts.
+    pub fn set(self) -> Failure {
+        let policy = match self {
+            SchedPolicy::Other => libc::SCHED_OTHER,
+            SchedPolicy::Batch => libc::SCHED_BATCH,
+            SchedPolicy::Idle  => libc::SCHED_IDLE,
+        };
+        let param = sched_param { sched_priority: 0 };
+        let result = unsafe { libc::sched_setscheduler(0, policy, &param) };
+        Errno::result(result)
+            .map(drop)
+            .map_err(Error::from)
+            .chain_err(|| ErrorKind::SetSchedPolicy)
+    }
+
+    /// The name of the policy.
+    pub fn name(self) -> String {
+        format!("{:?}", self).to_lowercase()
+    }
+}
+
+/// Set the nice value of the current process, and so each process it starts.
+///
+/// Lowering the value below its current value requires CAP_SYS_NICE.
+pub fn set_nice(nice: i32) -> Failure {
+    let result = unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, nice) };
+    Errno::result(result)
+        .map(drop)
+        .map_err(Error::from)
+        .chain_err(|| ErrorKind::SetNice(nice))
+}
+
+/// Set the adjustment of the OOM score of the current process, and so each
+/// process it starts.
+///
+/// Lowering the value below its current value requires CAP_SYS_RESOURCE.
+pub fn set_oom_score_adj(adj: i32) -> Failure {
+    write(OOM_SCORE_ADJ, adj.to_string()).chain_err(|| ErrorKind::SetOomScoreAdj(adj))
+}
@@ -0,0 +1,119 @@
+//! Errors reported as JSON, for programs that run the container to read.
+
+use std::error::Error as StdError;
+use std::path::PathBuf;
+
+use error::*;
+
+/// An error with the chain of errors that caused it, and the details of the
+/// first of them to have each.
+#[derive(Debug, Serialize)]
+pub struct ErrorReport {
+    /// The name of the kind of error, such as `SetMount`.
+    kind: String,
+    /// The message of the error alone.
+    message: String,
+    /// The message of each error that caused it, from the most direct cause.
+    causes: Vec<String>,
+    /// The target of the mount that failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mount_target: Option<PathBuf>,
+    /// The error number returned by the kernel.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    errno: Option<i32>,
+    /// The exit status of the hook that failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    exit_status: Option<i32>,
+}
+
+impl ErrorReport {
+    /// Report an error and its causes.
+    pub fn new(err: &Error) -> ErrorReport {
+        let mut report = ErrorReport {
+            kind: kind_name(err.kind()),
+            message: err.to_string(),
+            causes: err.iter().skip(1).map(ToString::to_string).collect(),
+            mount_target: None,
+            errno: None,
+            exit_status: None,
+        };
+
+        // The type of a cause can only be told from a 'static reference, so
+        // the chain is followed through the errors of this crate by hand.
+        let mut next: Option<&(dyn StdError + Send + 'static)> = Some(err);
+        while let Some(cause) = next {
+            next = None;
+            if let Some(err) = cause.downcast_ref::<Error>() {
+                report.add_details(err.kind());
+                next = err.1.next_error.as_ref().map(|cause| &**cause);
+            } else if let Some(err) = cause.downcast_ref::<::std::io::Error>() {
+                report.errno = report.errno.or_else(|| err.raw_os_error());
+            } else if let Some(&::nix::Error::Sys(errno)) = cause.downcast_ref::<::nix::Error>() {
+                report.errno = report.errno.or(Some(errno as i32));
+            }
+        }
+        report
+    }
+
+    /// The report as a single line of JSON.
+    pub fn to_json(&self) -> Result<String> {
+        Ok(::serde_json::to_string(self)?)
+    }
+
+    /// Keep the details of an error, unless a more direct error had them.
+    fn add_details(&mut self, kind: &ErrorKind) {
+        match *kind {
+            ErrorKind::SetMount(ref target)
+            | ErrorKind::Unmount(ref target)
+            | ErrorKind::RemountReadOnly(ref target)
+            | ErrorKind::SetTargetMode(ref target)
+            | ErrorKind::SetTargetOwner(ref target) => {
+                self.mount_target = self.mount_target.take().or_else(|| Some(target.clone()));
+            },
+            ErrorKind::HookExit(_, status) => {
+                self.exit_status = self.exit_status.or_else(|| status.code());
+            },
+            ErrorKind::Io(ref err) => {
+                self.errno = self.errno.or_else(|| err.raw_os_error());
+            },
+            ErrorKind::Nix(::nix::Error::Sys(errno)) => {
+                self.errno = self.errno.or(Some(errno as i32));
+            },
+            _ => {},
+        }
+    }
+}
+
+/// A warning, reported in the same form as an error so that it is told apart
+/// only by its kind.
+#[derive(Debug, Serialize)]
+pub struct WarningReport {
+    /// Always `Warning`.
+    kind: &'static str,
+    /// The message of the warning.
+    message: String,
+}
+
+impl WarningReport {
+    /// Report a warning.
+    pub fn new(message: &str) -> WarningReport {
+        WarningReport {
+            kind: "Warning",
+            message: message.to_owned(),
+        }
+    }
+
+    /// The report as a single line of JSON.
+    pub fn to_json(&self) -> Result<String> {
+        Ok(::serde_json::to_string(self)?)
+    }
+}
+
+/// The name of the variant of a kind of error, without its fields.
+fn kind_name(kind: &ErrorKind) -> String {
+    let name = format!("{:?}", kind);
+    match name.find(|c: char| !c.is_alphanumeric()) {
+        Some(end) => name[..end].to_owned(),
+        None => name,
+    }
+}
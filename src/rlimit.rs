@@ -0,0 +1,90 @@
+//! Resource limits of the command.
+//!
+//! See getrlimit(2) for details of each resource.
+
+use libc::{self, rlimit, rlim_t, RLIM_INFINITY};
+use nix::Error as NixError;
+use nix::errno::Errno;
+
+use error::*;
+
+/// The value of a limit, which may be unlimited.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Limit {
+    Value(u64),
+    Unlimited(Unlimited),
+}
+
+/// Written as `unlimited` in place of a value.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Unlimited {
+    Unlimited,
+}
+
+impl Into<rlim_t> for Limit {
+    fn into(self) -> rlim_t {
+        match self {
+            Limit::Value(value) => value as rlim_t,
+            Limit::Unlimited(_) => RLIM_INFINITY,
+        }
+    }
+}
+
+/// The soft and hard limits of a resource.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Rlimit {
+    soft: Limit,
+    hard: Limit,
+}
+
+impl Rlimit {
+    /// Set the limits of a resource, by the name used in `ulimit` without the
+    /// `RLIMIT_` prefix.
+    pub fn set(self, name: &str) -> Failure {
+        let resource = match name {
+            "as"         => libc::RLIMIT_AS,
+            "core"       => libc::RLIMIT_CORE,
+            "cpu"        => libc::RLIMIT_CPU,
+            "data"       => libc::RLIMIT_DATA,
+            "fsize"      => libc::RLIMIT_FSIZE,
+            "locks"      => libc::RLIMIT_LOCKS,
+            "memlock"    => libc::RLIMIT_MEMLOCK,
+            "msgqueue"   => libc::RLIMIT_MSGQUEUE,
+            "nice"       => libc::RLIMIT_NICE,
+            "nofile"     => libc::RLIMIT_NOFILE,
+            "nproc"      => libc::RLIMIT_NPROC,
+            "rss"        => libc::RLIMIT_RSS,
+            "rtprio"     => libc::RLIMIT_RTPRIO,
+            "rttime"     => libc::RLIMIT_RTTIME,
+            "sigpending" => libc::RLIMIT_SIGPENDING,
+            "stack"      => libc::RLIMIT_STACK,
+            _ => bail!(ErrorKind::UnknownResource(name.to_owned())),
+        };
+
+        let limit = rlimit {
+            rlim_cur: self.soft.into(),
+            rlim_max: self.hard.into(),
+        };
+        let result = unsafe { libc::setrlimit(resource, &limit) };
+
+        match Errno::result(result) {
+            Ok(_) => ok!(),
+            // Only a privileged process can raise a hard limit.
+            Err(NixError::Sys(Errno::EPERM)) => {
+                bail!(ErrorKind::RaiseRlimit(name.to_owned()))
+            },
+            Err(err) => Err(Error::from(err)).chain_err(|| ErrorKind::Rlimit(name.to_owned())),
+        }
+    }
+
+    /// Describe the limits.
+    pub fn describe(self) -> String {
+        let describe = |limit| match limit {
+            Limit::Value(value) => value.to_string(),
+            Limit::Unlimited(_) => "unlimited".to_owned(),
+        };
+        format!("soft {} hard {}", describe(self.soft), describe(self.hard))
+    }
+}
@@ -0,0 +1,348 @@
+//! System call filtering with seccomp.
+//!
+//! The rules are compiled into a classic BPF program checking the system call
+//! number. See seccomp(2) for more details on the interface.
+
+use std::io;
+
+use libc::{c_long, c_uint, c_ushort, syscall, EPERM, SYS_seccomp};
+use nix::errno::Errno;
+
+use capability;
+use error::*;
+
+const SECCOMP_SET_MODE_FILTER: c_uint = 1;
+
+const SECCOMP_RET_KILL_PROCESS: u32 = 0x8000_0000;
+const SECCOMP_RET_ERRNO: u32 = 0x0005_0000;
+const SECCOMP_RET_ALLOW: u32 = 0x7fff_0000;
+
+const BPF_LD: u16 = 0x00;
+const BPF_JMP: u16 = 0x05;
+const BPF_RET: u16 = 0x06;
+const BPF_W: u16 = 0x00;
+const BPF_ABS: u16 = 0x20;
+const BPF_JEQ: u16 = 0x10;
+const BPF_JGE: u16 = 0x30;
+const BPF_K: u16 = 0x00;
+
+/// Offsets of the fields of `struct seccomp_data`.
+const SECCOMP_DATA_NR: u32 = 0;
+const SECCOMP_DATA_ARCH: u32 = 4;
+
+/// The architecture checked by the filter, if the system calls of this one are
+/// known.
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: Option<u32> = Some(0xc000_003e);
+#[cfg(target_arch = "aarch64")]
+const AUDIT_ARCH: Option<u32> = Some(0xc000_00b7);
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+const AUDIT_ARCH: Option<u32> = None;
+/// Set in the numbers of the x32 system calls, which share the architecture of
+/// x86_64.
+#[cfg(target_arch = "x86_64")]
+const X32_SYSCALL_BIT: u32 = 0x4000_0000;
+
+/// A single BPF instruction, as `struct sock_filter`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct SockFilter {
+    code: u16,
+    jt: u8,
+    jf: u8,
+    k: u32,
+}
+
+/// A BPF program, as `struct sock_fprog`.
+#[repr(C)]
+struct SockFprog {
+    len: c_ushort,
+    filter: *const SockFilter,
+}
+
+macro_rules! syscalls {
+    ($($name:ident)*) => {
+        &[$((stringify!($name), ::libc::$name as u32),)*]
+    };
+}
+
+/// System calls available on all supported architectures.
+const SYSCALLS: &[(&str, u32)] = syscalls!(
+    SYS_accept SYS_accept4 SYS_acct SYS_add_key SYS_adjtimex SYS_bind SYS_bpf
+    SYS_brk SYS_capget SYS_capset SYS_chdir SYS_chroot SYS_clock_adjtime
+    SYS_clock_getres SYS_clock_gettime SYS_clock_nanosleep SYS_clock_settime
+    SYS_clone SYS_clone3 SYS_close SYS_close_range SYS_connect
+    SYS_copy_file_range SYS_delete_module SYS_dup SYS_dup3 SYS_epoll_create1
+    SYS_epoll_ctl SYS_epoll_pwait SYS_epoll_pwait2 SYS_eventfd2 SYS_execve
+    SYS_execveat SYS_exit SYS_exit_group SYS_faccessat SYS_faccessat2
+    SYS_fadvise64 SYS_fallocate SYS_fanotify_init SYS_fanotify_mark SYS_fchdir
+    SYS_fchmod SYS_fchmodat SYS_fchown SYS_fchownat SYS_fcntl SYS_fdatasync
+    SYS_fgetxattr SYS_finit_module SYS_flistxattr SYS_flock SYS_fremovexattr
+    SYS_fsconfig SYS_fsetxattr SYS_fsmount SYS_fsopen SYS_fspick SYS_fstat
+    SYS_fstatfs SYS_fsync SYS_ftruncate SYS_futex SYS_futex_waitv
+    SYS_get_mempolicy SYS_get_robust_list SYS_getcpu SYS_getcwd SYS_getdents64
+    SYS_getegid SYS_geteuid SYS_getgid SYS_getgroups SYS_getitimer
+    SYS_getpeername SYS_getpgid SYS_getpid SYS_getppid SYS_getpriority
+    SYS_getrandom SYS_getresgid SYS_getresuid SYS_getrusage SYS_getsid
+    SYS_getsockname SYS_getsockopt SYS_gettid SYS_gettimeofday SYS_getuid
+    SYS_getxattr SYS_init_module SYS_inotify_add_watch SYS_inotify_init1
+    SYS_inotify_rm_watch SYS_io_cancel SYS_io_destroy SYS_io_getevents
+    SYS_io_setup SYS_io_submit SYS_io_uring_enter SYS_io_uring_register
+    SYS_io_uring_setup SYS_ioctl SYS_ioprio_get SYS_ioprio_set SYS_kcmp
+    SYS_kexec_file_load SYS_kexec_load SYS_keyctl SYS_kill SYS_landlock_add_rule
+    SYS_landlock_create_ruleset SYS_landlock_restrict_self SYS_lgetxattr
+    SYS_linkat SYS_listen SYS_listxattr SYS_llistxattr SYS_lookup_dcookie
+    SYS_lremovexattr SYS_lseek SYS_lsetxattr SYS_madvise SYS_mbind
+    SYS_membarrier SYS_memfd_create SYS_memfd_secret SYS_migrate_pages
+    SYS_mincore SYS_mkdirat SYS_mknodat SYS_mlock SYS_mlock2 SYS_mlockall
+    SYS_mmap SYS_mount SYS_mount_setattr SYS_move_mount SYS_move_pages
+    SYS_mprotect SYS_mq_getsetattr SYS_mq_notify SYS_mq_open SYS_mq_timedreceive
+    SYS_mq_timedsend SYS_mq_unlink SYS_mremap SYS_mseal SYS_msgctl SYS_msgget
+    SYS_msgrcv SYS_msgsnd SYS_msync SYS_munlock SYS_munlockall SYS_munmap
+    SYS_name_to_handle_at SYS_nanosleep SYS_newfstatat SYS_nfsservctl
+    SYS_open_by_handle_at SYS_open_tree SYS_openat SYS_openat2
+    SYS_perf_event_open SYS_personality SYS_pidfd_getfd SYS_pidfd_open
+    SYS_pidfd_send_signal SYS_pipe2 SYS_pivot_root SYS_pkey_alloc SYS_pkey_free
+    SYS_pkey_mprotect SYS_ppoll SYS_prctl SYS_pread64 SYS_preadv SYS_preadv2
+    SYS_prlimit64 SYS_process_madvise SYS_process_mrelease SYS_process_vm_readv
+    SYS_process_vm_writev SYS_pselect6 SYS_ptrace SYS_pwrite64 SYS_pwritev
+    SYS_pwritev2 SYS_quotactl SYS_quotactl_fd SYS_read SYS_readahead
+    SYS_readlinkat SYS_readv SYS_reboot SYS_recvfrom SYS_recvmmsg SYS_recvmsg
+    SYS_remap_file_pages SYS_removexattr SYS_renameat2 SYS_request_key
+    SYS_restart_syscall SYS_rseq SYS_rt_sigaction SYS_rt_sigpending
+    SYS_rt_sigprocmask SYS_rt_sigqueueinfo SYS_rt_sigreturn SYS_rt_sigsuspend
+    SYS_rt_sigtimedwait SYS_rt_tgsigqueueinfo SYS_sched_get_priority_max
+    SYS_sched_get_priority_min SYS_sched_getaffinity SYS_sched_getattr
+    SYS_sched_getparam SYS_sched_getscheduler SYS_sched_rr_get_interval
+    SYS_sched_setaffinity SYS_sched_setattr SYS_sched_setparam
+    SYS_sched_setscheduler SYS_sched_yield SYS_seccomp SYS_semctl SYS_semget
+    SYS_semop SYS_semtimedop SYS_sendfile SYS_sendmmsg SYS_sendmsg SYS_sendto
+    SYS_set_mempolicy SYS_set_mempolicy_home_node SYS_set_robust_list
+    SYS_set_tid_address SYS_setdomainname SYS_setfsgid SYS_setfsuid SYS_setgid
+    SYS_setgroups SYS_sethostname SYS_setitimer SYS_setns SYS_setpgid
+    SYS_setpriority SYS_setregid SYS_setresgid SYS_setresuid SYS_setreuid
+    SYS_setsid SYS_setsockopt SYS_settimeofday SYS_setuid SYS_setxattr SYS_shmat
+    SYS_shmctl SYS_shmdt SYS_shmget SYS_shutdown SYS_sigaltstack SYS_signalfd4
+    SYS_socket SYS_socketpair SYS_splice SYS_statfs SYS_statx SYS_swapoff
+    SYS_swapon SYS_symlinkat SYS_sync SYS_syncfs SYS_sysinfo SYS_syslog SYS_tee
+    SYS_tgkill SYS_timer_create SYS_timer_delete SYS_timer_getoverrun
+    SYS_timer_gettime SYS_timer_settime SYS_timerfd_create SYS_timerfd_gettime
+    SYS_timerfd_settime SYS_times SYS_tkill SYS_truncate SYS_umask SYS_umount2
+    SYS_uname SYS_unlinkat SYS_unshare SYS_userfaultfd SYS_utimensat SYS_vhangup
+    SYS_vmsplice SYS_wait4 SYS_waitid SYS_write SYS_writev
+);
+
+/// System calls only available on x86_64.
+#[cfg(target_arch = "x86_64")]
+const ARCH_SYSCALLS: &[(&str, u32)] = syscalls!(
+    SYS_access SYS_alarm SYS_arch_prctl SYS_chmod SYS_chown SYS_creat SYS_dup2
+    SYS_epoll_create SYS_epoll_wait SYS_eventfd SYS_fchmodat2 SYS_fork
+    SYS_futimesat SYS_get_thread_area SYS_getdents SYS_getpgrp SYS_getrlimit
+    SYS_inotify_init SYS_ioperm SYS_iopl SYS_lchown SYS_link SYS_lstat SYS_mkdir
+    SYS_mknod SYS_modify_ldt SYS_open SYS_pause SYS_pipe SYS_poll SYS_readlink
+    SYS_rename SYS_renameat SYS_rmdir SYS_select SYS_set_thread_area
+    SYS_setrlimit SYS_signalfd SYS_stat SYS_symlink SYS_sync_file_range
+    SYS_sysfs SYS_time SYS_unlink SYS_ustat SYS_utime SYS_utimes SYS_vfork
+);
+#[cfg(not(target_arch = "x86_64"))]
+const ARCH_SYSCALLS: &[(&str, u32)] = &[];
+
+/// The action to take for a system call.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    /// Kill the process.
+    Kill,
+    /// Fail with `EPERM`.
+    Errno,
+    /// Allow the system call.
+    Allow,
+}
+
+impl Action {
+    /// The value returned by the filter for the action.
+    fn value(self) -> u32 {
+        match self {
+            Action::Kill  => SECCOMP_RET_KILL_PROCESS,
+            Action::Errno => SECCOMP_RET_ERRNO | EPERM as u32,
+            Action::Allow => SECCOMP_RET_ALLOW,
+        }
+    }
+}
+
+/// An action to take for a set of system calls.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Rule {
+    /// The names of the system calls.
+    names: Vec<String>,
+    /// The action to take for each.
+    action: Action,
+}
+
+/// A filter on the system calls the container can make.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Seccomp {
+    /// The action for any system call not matched by a rule.
+    default_action: Action,
+    /// Rules checked in order, taking the action of the first to match.
+    #[serde(default)]
+    #[serde(rename = "rule")]
+    rules: Vec<Rule>,
+}
+
+/// A compiled filter, to be installed by the command just before it is
+/// executed.
+pub struct Filter {
+    program: Vec<SockFilter>,
+}
+
+impl Filter {
+    /// Install the filter for the current process.
+    ///
+    /// This is run in the command just before it is executed, so it doesn't
+    /// allocate.
+    pub fn install(&self) -> io::Result<()> {
+        let program = SockFprog {
+            len: self.program.len() as c_ushort,
+            filter: self.program.as_ptr(),
+        };
+
+        let result = unsafe {
+            syscall(
+                SYS_seccomp,
+                SECCOMP_SET_MODE_FILTER as c_long,
+                0 as c_long,
+                &program as *const SockFprog,
+            )
+        };
+        Errno::result(result).map(drop).map_err(|_| io::Error::last_os_error())
+    }
+}
+
+impl Seccomp {
+    /// Compile the filter for the command to install.
+    ///
+    /// Installing it requires either `no_new_privs` to be set or
+    /// `CAP_SYS_ADMIN`.
+    pub fn prepare(&self, no_new_privs: bool) -> Result<Filter> {
+        ensure!(
+            no_new_privs || capability::is_effective("CAP_SYS_ADMIN")?,
+            ErrorKind::SeccompPrivileges
+        );
+        let program = self.compile().chain_err(|| ErrorKind::InstallSeccomp)?;
+        Ok(Filter { program })
+    }
+
+    /// Summarise the rules of the filter.
+    pub fn describe(&self) -> Vec<String> {
+        let mut description: Vec<_> = self.rules.iter()
+            .map(|rule| format!("{:?}: {}", rule.action, rule.names.join(", ")))
+            .collect();
+        description.push(format!("{:?}: all others", self.default_action));
+        description
+    }
+
+    /// Compile the rules into a BPF program.
+    fn compile(&self) -> Result<Vec<SockFilter>> {
+        // Any other architecture could make the same system calls with
+        // different numbers.
+        let arch = AUDIT_ARCH.ok_or(ErrorKind::SeccompArchitecture)?;
+        let mut filter = vec![
+            statement(BPF_LD | BPF_W | BPF_ABS, SECCOMP_DATA_ARCH),
+            jump(BPF_JMP | BPF_JEQ | BPF_K, arch, 1, 0),
+            statement(BPF_RET | BPF_K, SECCOMP_RET_KILL_PROCESS),
+            statement(BPF_LD | BPF_W | BPF_ABS, SECCOMP_DATA_NR),
+        ];
+
+        // The x32 system calls pass the check of the architecture, but would
+        // otherwise avoid every rule with their different numbers.
+        #[cfg(target_arch = "x86_64")]
+        filter.extend(&[
+            jump(BPF_JMP | BPF_JGE | BPF_K, X32_SYSCALL_BIT, 0, 1),
+            statement(BPF_RET | BPF_K, SECCOMP_RET_KILL_PROCESS),
+        ]);
+
+        for rule in &self.rules {
+            for name in &rule.names {
+                filter.push(jump(BPF_JMP | BPF_JEQ | BPF_K, number(name)?, 0, 1));
+                filter.push(statement(BPF_RET | BPF_K, rule.action.value()));
+            }
+        }
+        filter.push(statement(BPF_RET | BPF_K, self.default_action.value()));
+
+        ensure!(filter.len() <= c_ushort::max_value() as usize, ErrorKind::InstallSeccomp);
+        Ok(filter)
+    }
+}
+
+/// A BPF instruction that doesn't jump.
+fn statement(code: u16, k: u32) -> SockFilter {
+    SockFilter { code, jt: 0, jf: 0, k }
+}
+
+/// A BPF instruction that jumps forward depending on a comparison.
+fn jump(code: u16, k: u32, jt: u8, jf: u8) -> SockFilter {
+    SockFilter { code, jt, jf, k }
+}
+
+/// The number of a system call from its name.
+fn number(name: &str) -> Result<u32> {
+    let symbol = format!("SYS_{}", name);
+    SYSCALLS.iter()
+        .chain(ARCH_SYSCALLS)
+        .find(|&&(syscall, _)| syscall == symbol)
+        .map(|&(_, number)| number)
+        .ok_or_else(|| ErrorKind::UnknownSyscall(name.to_owned()).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use toml;
+
+    fn seccomp(config: &str) -> Seccomp {
+        toml::from_str(config).unwrap()
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn x32_system_calls_are_killed() {
+        let filter = seccomp(r#"
+            default_action = "allow"
+            [[rule]]
+            names = ["ptrace"]
+            action = "kill"
+        "#).compile().unwrap();
+
+        // The check follows loading the number, before any rule.
+        let load_nr = statement(BPF_LD | BPF_W | BPF_ABS, SECCOMP_DATA_NR);
+        let index = filter.iter().position(|insn| *insn == load_nr).unwrap();
+        assert_eq!(filter[index + 1], jump(BPF_JMP | BPF_JGE | BPF_K, X32_SYSCALL_BIT, 0, 1));
+        assert_eq!(filter[index + 2], statement(BPF_RET | BPF_K, SECCOMP_RET_KILL_PROCESS));
+        assert_eq!(filter[index + 3], jump(BPF_JMP | BPF_JEQ | BPF_K, number("ptrace").unwrap(), 0, 1));
+    }
+
+    #[test]
+    fn default_action_ends_the_filter() {
+        let filter = seccomp(r#"default_action = "errno""#).compile().unwrap();
+        assert_eq!(
+            filter.last(),
+            Some(&statement(BPF_RET | BPF_K, SECCOMP_RET_ERRNO | EPERM as u32))
+        );
+    }
+
+    #[test]
+    fn unknown_system_call() {
+        let err = seccomp(r#"
+            default_action = "allow"
+            [[rule]]
+            names = ["not_a_syscall"]
+            action = "kill"
+        "#).compile().unwrap_err();
+        match *err.kind() {
+            ErrorKind::UnknownSyscall(ref name) => assert_eq!(name, "not_a_syscall"),
+            ref kind => panic!("unexpected error {:?}", kind),
+        }
+    }
+}
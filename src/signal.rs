@@ -0,0 +1,68 @@
+//! Forwarding of signals to the contained process.
+//!
+//! The signals that would normally stop the process are instead sent on to the
+//! child, so that the container behaves as the command itself would.
+
+use std::sync::atomic::{AtomicI32, Ordering};
+
+use libc::{self, c_int, pid_t};
+use nix::sys::signal::{sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal};
+
+use error::*;
+
+/// The signals forwarded to the child.
+const FORWARDED: &[Signal] = &[
+    Signal::SIGTERM,
+    Signal::SIGINT,
+    Signal::SIGHUP,
+    Signal::SIGQUIT,
+];
+
+/// The process to forward signals to, or zero if there is none.
+static CHILD: AtomicI32 = AtomicI32::new(0);
+
+extern "C" fn forward(signal: c_int) {
+    let child = CHILD.load(Ordering::SeqCst);
+    if child != 0 {
+        unsafe { libc::kill(child, signal) };
+    }
+}
+
+/// Forwards signals to a child until dropped.
+///
+/// Only one child can have signals forwarded at a time.
+#[must_use]
+#[derive(Debug)]
+pub struct SignalForward {
+    _private: (),
+}
+
+impl SignalForward {
+    /// Start forwarding signals to a child process.
+    pub fn new(child: pid_t) -> Result<SignalForward> {
+        CHILD.store(child, Ordering::SeqCst);
+        let forwarding = SignalForward { _private: () };
+
+        // Waiting for the child is restarted after forwarding a signal.
+        let action = SigAction::new(
+            SigHandler::Handler(forward),
+            SaFlags::SA_RESTART,
+            SigSet::empty(),
+        );
+        for &signal in FORWARDED {
+            unsafe { sigaction(signal, &action) }?;
+        }
+
+        Ok(forwarding)
+    }
+}
+
+impl Drop for SignalForward {
+    fn drop(&mut self) {
+        let action = SigAction::new(SigHandler::SigDfl, SaFlags::empty(), SigSet::empty());
+        for &signal in FORWARDED {
+            let _ = unsafe { sigaction(signal, &action) };
+        }
+        CHILD.store(0, Ordering::SeqCst);
+    }
+}
@@ -0,0 +1,53 @@
+//! Subordinate ids allocated to users.
+//!
+//! See subuid(5) and subgid(5) for the format of the files.
+
+use std::ffi::CStr;
+use std::fs::read_to_string;
+use std::path::Path;
+
+use libc::{self, uid_t};
+use nix::unistd::getuid;
+
+use error::*;
+
+/// The subordinate uids allocated to each user.
+pub const SUBUID: &str = "/etc/subuid";
+
+/// The subordinate gids allocated to each user.
+pub const SUBGID: &str = "/etc/subgid";
+
+/// The first subordinate id allocated to the current user and the number of ids
+/// allocated.
+pub fn allocation<P: AsRef<Path>>(path: P) -> Result<(u32, u32)> {
+    let path = path.as_ref();
+    let uid = uid_t::from(getuid());
+    let owners = [Some(uid.to_string()), user_name(uid)];
+
+    read_to_string(path)?
+        .lines()
+        .filter_map(parse)
+        .find(|&(owner, _, _)| owners.contains(&Some(owner.to_owned())))
+        .map(|(_, start, count)| (start, count))
+        .ok_or_else(|| ErrorKind::NoSubordinateIds(path.to_owned()).into())
+}
+
+/// Parse a single line as the owner, first id, and number of ids.
+fn parse(line: &str) -> Option<(&str, u32, u32)> {
+    let mut fields = line.trim().split(':');
+    let owner = fields.next()?;
+    let start = fields.next()?.parse().ok()?;
+    let count = fields.next()?.parse().ok()?;
+    Some((owner, start, count))
+}
+
+/// The name of a user.
+fn user_name(uid: uid_t) -> Option<String> {
+    unsafe {
+        let entry = libc::getpwuid(uid);
+        if entry.is_null() {
+            return None;
+        }
+        CStr::from_ptr((*entry).pw_name).to_str().ok().map(String::from)
+    }
+}
@@ -0,0 +1,104 @@
+//! Kernel parameters set within the namespaces of the container.
+//!
+//! Only the parameters that belong to a namespace can be set without changing
+//! them for the host. See sysctl(8) and the description of /proc/sys in
+//! proc(5).
+
+use std::fs::write;
+use std::path::{Path, PathBuf};
+
+use config::Namespace;
+use error::*;
+
+/// The directory of the files of each parameter.
+const PROC_SYS: &str = "/proc/sys";
+
+/// The parameters of the IPC namespace, other than those of POSIX message
+/// queues.
+const IPC_SYSCTLS: &[&str] = &[
+    "kernel.msgmax",
+    "kernel.msgmnb",
+    "kernel.msgmni",
+    "kernel.sem",
+    "kernel.shmall",
+    "kernel.shmmax",
+    "kernel.shmmni",
+    "kernel.shm_rmid_forced",
+];
+
+/// The namespace a parameter belongs to, if it is namespaced.
+pub fn namespace(key: &str) -> Option<Namespace> {
+    match key {
+        "kernel.hostname" | "kernel.domainname" => Some(Namespace::Uts),
+        _ if IPC_SYSCTLS.contains(&key) => Some(Namespace::Ipc),
+        _ if key.starts_with("fs.mqueue.") => Some(Namespace::Ipc),
+        _ if key.starts_with("net.") => Some(Namespace::Net),
+        _ => None,
+    }
+}
+
+/// Check that a parameter belongs to one of the private namespaces of the
+/// container.
+pub fn validate(key: &str, private: &[Namespace]) -> Failure {
+    match namespace(key) {
+        Some(namespace) if private.contains(&namespace) => ok!(),
+        Some(namespace) => bail!(ErrorKind::Sysctl(
+            key.to_owned(),
+            format!("the {} namespace is not unshared", namespace.name()),
+        )),
+        None => bail!(ErrorKind::Sysctl(key.to_owned(), "it is not namespaced".to_owned())),
+    }
+}
+
+/// Set a parameter of the current namespaces.
+pub fn set(key: &str, value: &str) -> Failure {
+    write(path(key), value).chain_err(|| ErrorKind::SetSysctl(key.to_owned()))
+}
+
+/// The file of a parameter, with each dot of its name being a directory.
+fn path(key: &str) -> PathBuf {
+    Path::new(PROC_SYS).join(key.replace('.', "/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn namespaces() {
+        assert_eq!(namespace("net.ipv4.ip_forward"), Some(Namespace::Net));
+        assert_eq!(namespace("kernel.shmmax"), Some(Namespace::Ipc));
+        assert_eq!(namespace("fs.mqueue.msg_max"), Some(Namespace::Ipc));
+        assert_eq!(namespace("kernel.hostname"), Some(Namespace::Uts));
+        assert_eq!(namespace("vm.swappiness"), None);
+        assert_eq!(namespace("kernel.shm_next_id"), None);
+    }
+
+    #[test]
+    fn validate_private() {
+        assert!(validate("net.ipv4.ip_forward", &[Namespace::Net]).is_ok());
+        assert!(validate("kernel.shmmax", &[Namespace::Net, Namespace::Ipc]).is_ok());
+    }
+
+    #[test]
+    fn validate_not_unshared() {
+        match validate("kernel.shmmax", &[Namespace::Net]) {
+            Err(Error(ErrorKind::Sysctl(ref key, ref reason), _)) => {
+                assert_eq!(key, "kernel.shmmax");
+                assert_eq!(reason, "the ipc namespace is not unshared");
+            },
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn validate_not_namespaced() {
+        match validate("vm.swappiness", &[Namespace::Net, Namespace::Ipc, Namespace::Uts]) {
+            Err(Error(ErrorKind::Sysctl(ref key, ref reason), _)) => {
+                assert_eq!(key, "vm.swappiness");
+                assert_eq!(reason, "it is not namespaced");
+            },
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+}
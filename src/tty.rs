@@ -0,0 +1,155 @@
+//! A pseudo-terminal for the container, proxied to the terminal it was started
+//! from.
+//!
+//! The command is given the slave end as its controlling terminal, so that job
+//! control within the container is independent of the terminal outside. See
+//! pty(7) and tty_ioctl(4).
+
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::thread::{self, JoinHandle};
+
+use libc::{self, c_int, winsize, STDIN_FILENO, TIOCGWINSZ, TIOCSCTTY, TIOCSWINSZ};
+use nix::errno::Errno;
+use nix::pty::openpty;
+use nix::sys::signal::{sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal};
+use nix::sys::termios::{cfmakeraw, tcgetattr, tcsetattr, SetArg, Termios};
+use nix::unistd::{isatty, setsid};
+
+use error::*;
+
+/// The master end to resize with the terminal, or -1 if there is none.
+static MASTER: AtomicI32 = AtomicI32::new(-1);
+
+extern "C" fn resize(_: c_int) {
+    let master = MASTER.load(Ordering::SeqCst);
+    if master >= 0 {
+        copy_window_size(STDIN_FILENO, master);
+    }
+}
+
+/// A pseudo-terminal allocated for the container.
+#[derive(Debug)]
+pub struct Pty {
+    master: File,
+    slave: File,
+}
+
+impl Pty {
+    /// Allocate a pseudo-terminal with the size of the current terminal.
+    pub fn open() -> Result<Pty> {
+        let pty = openpty(None, None).chain_err(|| ErrorKind::Pty)?;
+        let pty = unsafe {
+            Pty {
+                master: File::from_raw_fd(pty.master),
+                slave: File::from_raw_fd(pty.slave),
+            }
+        };
+        copy_window_size(STDIN_FILENO, pty.master.as_raw_fd());
+        Ok(pty)
+    }
+
+    /// A copy of the slave end, to be the standard streams of the container.
+    pub fn slave(&self) -> Result<File> {
+        self.slave.try_clone().chain_err(|| ErrorKind::Pty)
+    }
+
+    /// Copy data between the terminal and the pseudo-terminal until dropped,
+    /// once the container has been given the slave end.
+    ///
+    /// The terminal is put into raw mode, so that every key is passed on to be
+    /// handled within the container.
+    pub fn proxy(self) -> Result<PtyProxy> {
+        let Pty { master, slave } = self;
+
+        // Output ends once every copy of the slave end is closed.
+        drop(slave);
+
+        let restore = if isatty(STDIN_FILENO).unwrap_or(false) {
+            let original = tcgetattr(STDIN_FILENO).chain_err(|| ErrorKind::Pty)?;
+            let mut raw = original.clone();
+            cfmakeraw(&mut raw);
+            tcsetattr(STDIN_FILENO, SetArg::TCSANOW, &raw).chain_err(|| ErrorKind::Pty)?;
+            Some(original)
+        } else {
+            None
+        };
+
+        MASTER.store(master.as_raw_fd(), Ordering::SeqCst);
+        let action = SigAction::new(SigHandler::Handler(resize), SaFlags::SA_RESTART, SigSet::empty());
+        unsafe { sigaction(Signal::SIGWINCH, &action) }?;
+
+        // Input is never joined, as reading from the terminal only ends when
+        // it is closed.
+        let mut input = master.try_clone()?;
+        thread::spawn(move || copy(&mut io::stdin(), &mut input));
+        let mut output = master.try_clone()?;
+        let output = thread::spawn(move || copy(&mut output, &mut io::stdout()));
+
+        Ok(PtyProxy { _master: master, restore, output: Some(output) })
+    }
+}
+
+/// Copies data between the terminal and a pseudo-terminal until dropped.
+#[must_use]
+pub struct PtyProxy {
+    _master: File,
+    restore: Option<Termios>,
+    output: Option<JoinHandle<()>>,
+}
+
+impl Drop for PtyProxy {
+    fn drop(&mut self) {
+        if let Some(output) = self.output.take() {
+            let _ = output.join();
+        }
+
+        let action = SigAction::new(SigHandler::SigDfl, SaFlags::empty(), SigSet::empty());
+        let _ = unsafe { sigaction(Signal::SIGWINCH, &action) };
+        MASTER.store(-1, Ordering::SeqCst);
+
+        if let Some(ref original) = self.restore {
+            let _ = tcsetattr(STDIN_FILENO, SetArg::TCSANOW, original);
+        }
+    }
+}
+
+/// Make the terminal of the standard input the controlling terminal of the
+/// current process in a new session.
+///
+/// This is run in the command just before it is executed.
+pub fn set_controlling_terminal() -> io::Result<()> {
+    setsid().map_err(|_| io::Error::last_os_error())?;
+    let result = unsafe { libc::ioctl(STDIN_FILENO, TIOCSCTTY, 0) };
+    Errno::result(result).map_err(|_| io::Error::last_os_error())?;
+    Ok(())
+}
+
+/// Copy everything read from one file to another until either is closed.
+///
+/// Reading the master end fails with EIO once the slave end is closed, which
+/// ends the copy like the end of a file.
+fn copy<R: Read, W: Write>(from: &mut R, to: &mut W) {
+    let mut buffer = [0; 4096];
+    loop {
+        let len = match from.read(&mut buffer) {
+            Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Ok(0) | Err(_) => return,
+            Ok(len) => len,
+        };
+        if to.write_all(&buffer[..len]).and_then(|_| to.flush()).is_err() {
+            return;
+        }
+    }
+}
+
+/// Set the window size of one terminal to that of another, if it is a
+/// terminal.
+fn copy_window_size(from: RawFd, to: RawFd) {
+    let mut size: winsize = unsafe { ::std::mem::zeroed() };
+    if unsafe { libc::ioctl(from, TIOCGWINSZ, &mut size) } == 0 {
+        unsafe { libc::ioctl(to, TIOCSWINSZ, &size) };
+    }
+}
@@ -0,0 +1,96 @@
+//! Users and groups of the container, by name.
+//!
+//! See passwd(5) and group(5) for the format of the files.
+
+use std::fs::read_to_string;
+use std::path::Path;
+
+use libc::{gid_t, uid_t};
+
+use error::*;
+
+/// The users of the system.
+const PASSWD: &str = "/etc/passwd";
+
+/// The groups of the system.
+const GROUP: &str = "/etc/group";
+
+/// The ids a user runs with.
+#[derive(Debug)]
+pub struct User {
+    pub uid: uid_t,
+    /// The primary group, if the user has an entry.
+    pub gid: Option<gid_t>,
+    /// The supplementary groups of the user.
+    pub groups: Vec<gid_t>,
+}
+
+/// Look up a user by name, or by uid if the name is numeric, in the files of
+/// the current root.
+pub fn lookup(name: &str) -> Result<User> {
+    let entry = passwd_entries()?
+        .into_iter()
+        .find(|&(ref entry_name, uid, _)| match name.parse::<uid_t>() {
+            Ok(id) => uid == id,
+            Err(_) => entry_name == name,
+        });
+
+    let (name, uid, gid) = match (entry, name.parse::<uid_t>()) {
+        (Some(entry), _) => entry,
+        // A numeric user need not have an entry.
+        (None, Ok(uid)) => return Ok(User { uid, gid: None, groups: Vec::new() }),
+        (None, Err(_)) => bail!(ErrorKind::UnknownUser(name.to_owned())),
+    };
+
+    // As with initgroups(3), the primary group is also a supplementary group.
+    let mut groups = vec![gid];
+    for (group, members) in group_entries()? {
+        if members.contains(&name) && group != gid {
+            groups.push(group);
+        }
+    }
+
+    Ok(User { uid, gid: Some(gid), groups })
+}
+
+/// The name, uid, and gid of each user.
+fn passwd_entries() -> Result<Vec<(String, uid_t, gid_t)>> {
+    Ok(read_entries(PASSWD)?
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split(':');
+            let name = fields.next()?;
+            let _password = fields.next()?;
+            let uid = fields.next()?.parse().ok()?;
+            let gid = fields.next()?.parse().ok()?;
+            Some((name.to_owned(), uid, gid))
+        })
+        .collect())
+}
+
+/// The gid and members of each group.
+fn group_entries() -> Result<Vec<(gid_t, Vec<String>)>> {
+    Ok(read_entries(GROUP)?
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split(':');
+            let _name = fields.next()?;
+            let _password = fields.next()?;
+            let gid = fields.next()?.parse().ok()?;
+            let members = fields.next()?
+                .split(',')
+                .filter(|member| !member.is_empty())
+                .map(String::from)
+                .collect();
+            Some((gid, members))
+        })
+        .collect())
+}
+
+/// Read a file of entries, which is treated as empty if it does not exist.
+fn read_entries(path: &str) -> Result<String> {
+    if !Path::new(path).exists() {
+        return Ok(String::new());
+    }
+    read_to_string(path).chain_err(|| ErrorKind::ReadUsers(path.into()))
+}
@@ -0,0 +1,45 @@
+//! Tracing of each step taken to set up the container.
+//!
+//! Traces are written to stderr so that they are kept apart from the output of
+//! the command.
+
+use std::env;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Whether each step is traced.
+static VERBOSE: AtomicBool = AtomicBool::new(false);
+
+/// Trace a step when verbose.
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        if ::verbose::enabled() {
+            eprintln!("{}: {}", env!("CARGO_PKG_NAME"), format_args!($($arg)*));
+        }
+    };
+}
+
+/// Enable tracing if requested, or if `RUST_LOG` enables the debug or trace
+/// level for this crate.
+pub fn init(verbose: bool) {
+    let from_env = env::var("RUST_LOG")
+        .map(|filter| enables(&filter))
+        .unwrap_or(false);
+    VERBOSE.store(verbose || from_env, Ordering::SeqCst);
+}
+
+/// Each step is traced.
+pub fn enabled() -> bool {
+    VERBOSE.load(Ordering::SeqCst)
+}
+
+/// A filter of the form `level` or `crate=level`, separated by commas, enables
+/// tracing for this crate.
+fn enables(filter: &str) -> bool {
+    filter.split(',').any(|directive| {
+        let mut parts = directive.trim().rsplitn(2, '=');
+        let level = parts.next().unwrap_or("");
+        let target = parts.next();
+        let targets_crate = target.map_or(true, |target| target == env!("CARGO_PKG_NAME"));
+        targets_crate && (level == "debug" || level == "trace")
+    })
+}